@@ -0,0 +1,122 @@
+//! Batch Jobs
+//! ==========
+//!
+//! Loads the manifest for `spongify batch`. A manifest is a JSON list of jobs, each of which is run as if it were its
+//! own `spongify` command: it reads its `input` file and writes its `output` file, in its `style` if it has one, with
+//! any other options in `args`:
+//!
+//! ```json
+//! [
+//!   { "input": "notes.txt", "output": "notes-spongified.txt" },
+//!   { "input": "talk.txt", "output": "talk.png", "style": "lIkE ThIs", "args": ["--image", "--seed", "7"] }
+//! ]
+//! ```
+//!
+//! Relative paths are relative to the directory the manifest is in, not the current directory.
+
+use crate::Result;
+use serde::Deserialize;
+use std::{
+    ffi::OsString,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A job of a batch manifest.
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub style: Option<String>,
+    /// More options for the job, written as they would be on the command line.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl Job {
+    /// Get the command line which runs this job, with relative paths taken from `base`.
+    pub fn command_line(&self, base: &Path) -> Vec<OsString> {
+        let mut command_line = vec![
+            "spongify".into(),
+            "--file".into(),
+            base.join(&self.input).into(),
+            "--output-file".into(),
+            base.join(&self.output).into(),
+        ];
+        if let Some(ref style) = self.style {
+            command_line.extend(["--style".into(), style.into()]);
+        }
+        command_line.extend(self.args.iter().map(Into::into));
+        command_line
+    }
+}
+
+/// Parse the jobs of the manifest `source`.
+fn parse_manifest(source: &str) -> Result<Vec<Job>> {
+    let jobs: Vec<Job> = serde_json::from_str(source)?;
+    if jobs.is_empty() {
+        return Err("the manifest has no jobs".into());
+    }
+    Ok(jobs)
+}
+
+/// Load the manifest at `path`.
+pub fn load_manifest(path: &Path) -> Result<Vec<Job>> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("could not read manifest {}: {e}", path.display()))?;
+    parse_manifest(&source).map_err(|e| format!("invalid manifest {}: {e}", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_jobs() {
+        let jobs = parse_manifest(
+            r#"[
+                { "input": "taco.txt", "output": "/tmp/truck.txt" },
+                { "input": "salsa.txt", "output": "out/salsa.txt", "style": "lIkE ThIs", "args": ["--seed", "7"] }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            jobs[0].command_line(Path::new("jobs")),
+            [
+                "spongify",
+                "--file",
+                "jobs/taco.txt",
+                "--output-file",
+                "/tmp/truck.txt"
+            ]
+        );
+        assert_eq!(
+            jobs[1].command_line(Path::new("jobs")),
+            [
+                "spongify",
+                "--file",
+                "jobs/salsa.txt",
+                "--output-file",
+                "jobs/out/salsa.txt",
+                "--style",
+                "lIkE ThIs",
+                "--seed",
+                "7"
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for source in [
+            "[]",
+            r#"[{ "input": "taco.txt" }]"#,
+            r#"[{ "input": "taco.txt", "output": "truck.txt", "stlye": "lIkE ThIs" }]"#,
+            r#"{ "input": "taco.txt", "output": "truck.txt" }"#,
+        ] {
+            assert!(parse_manifest(source).is_err(), "{source} parsed");
+        }
+    }
+}
@@ -1,16 +1,187 @@
-use rand::Rng;
+use crate::tokenize;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use smallvec::SmallVec;
 use std::{fmt, str};
 
+/// The characters a `CapitalizationEngine` writes in place of one character of the input. Case mappings give at most
+/// three characters (like "ß" to "SS"), so these are usually kept inline.
+pub type MappedChars = SmallVec<[char; 3]>;
+
+/// Decides what each character of the input becomes.
 pub trait CapitalizationEngine {
+    /// Start the grapheme cluster at `index`, whose first character is `character`. The `index` is the position of the
+    /// grapheme cluster within the whole input, counting each line break as one grapheme, so it does not reset from
+    /// line to line. This is called once per grapheme cluster, so combining marks do not advance a pattern.
+    fn start_grapheme(&mut self, index: usize, character: char);
+
+    /// Get the characters to write in place of `character`, which is part of the grapheme cluster last started with
+    /// `start_grapheme`. This is called for each character of the cluster, in order.
+    fn map_char(&mut self, character: char) -> MappedChars;
+
+    /// Advance past a line break in the input, which `start_grapheme` is not called for. Engines which count the
+    /// characters they see can count the line break as one.
+    fn line_break(&mut self) {}
+
+    /// Start the capitalization pattern over, as if nothing had been capitalized yet. This is used by
+    /// `--reset-per-line` at the start of each line. Engines without a pattern to restart do nothing.
+    fn reset(&mut self) {}
+
+    /// Get a report of the random choices made so far, for engines which make them with a random number generator.
+    fn entropy_report(&self) -> Option<EntropyReport> {
+        None
+    }
+}
+
+/// An engine which only changes case, uppercasing or lowercasing each whole grapheme cluster. Most strategies are one
+/// of these, made into a `CapitalizationEngine` by `Cased`.
+pub trait CaseEngine {
+    /// Decide if the grapheme cluster starting with `character` should be uppercased, where `index` is as in
+    /// `CapitalizationEngine::start_grapheme`.
     fn should_capitalize(&mut self, index: usize, character: char) -> bool;
+
+    /// See `CapitalizationEngine::line_break`.
+    fn line_break(&mut self) {}
+
+    /// See `CapitalizationEngine::reset`.
+    fn reset(&mut self) {}
+
+    /// See `CapitalizationEngine::entropy_report`.
+    fn entropy_report(&self) -> Option<EntropyReport> {
+        None
+    }
+}
+
+/// A `CapitalizationEngine` which cases every character of a grapheme cluster as its `CaseEngine` decided for the
+/// cluster.
+pub struct Cased<E> {
+    engine: E,
+    capitalize: bool,
+}
+
+impl<E: CaseEngine> Cased<E> {
+    pub fn new(engine: E) -> Self {
+        Self {
+            engine,
+            capitalize: false,
+        }
+    }
+}
+
+impl<E: CaseEngine> CapitalizationEngine for Cased<E> {
+    fn start_grapheme(&mut self, index: usize, character: char) {
+        self.capitalize = self.engine.should_capitalize(index, character);
+    }
+
+    fn map_char(&mut self, character: char) -> MappedChars {
+        let mut mapped = MappedChars::new();
+        push_cased(&mut mapped, character, self.capitalize);
+        mapped
+    }
+
+    fn line_break(&mut self) {
+        self.engine.line_break();
+    }
+
+    fn reset(&mut self) {
+        self.engine.reset();
+    }
+
+    fn entropy_report(&self) -> Option<EntropyReport> {
+        self.engine.entropy_report()
+    }
+}
+
+/// Push `c` onto `out`, uppercased with `push_uppercase` if `capitalize` is set and lowercased if not.
+fn push_cased(out: &mut impl Extend<char>, c: char, capitalize: bool) {
+    if capitalize {
+        push_uppercase(out, c);
+    } else {
+        out.extend(c.to_lowercase());
+    }
+}
+
+/// Push the uppercase form of `c` onto `out`.
+///
+/// This is the full uppercase mapping, except for characters which it would split into more than one character while
+/// the titlecase mapping keeps them as one. Those are Greek letters with an iota subscript (like `ᾳ`), which uppercase
+/// to the letter followed by a separate capital iota (`ΑΙ`); their titlecase form (`ᾼ`) keeps the iota as a subscript.
+fn push_uppercase(out: &mut impl Extend<char>, c: char) {
+    let titlecase = unicode_case_mapping::to_titlecase(c);
+    let single_titlecase = match titlecase {
+        [title, 0, 0] if title != 0 => char::from_u32(title),
+        _ => None,
+    };
+
+    match single_titlecase {
+        Some(title) if c.to_uppercase().len() > 1 => out.extend([title]),
+        _ => out.extend(c.to_uppercase()),
+    }
+}
+
+/// Push the titlecase form of `c` onto `out`, which is how the first letter of a word is capitalized in title case.
+/// It differs from the uppercase form for digraphs like `ǆ`, which titlecases to `ǅ` but uppercases to `Ǆ`.
+fn push_titlecase(out: &mut impl Extend<char>, c: char) {
+    match unicode_case_mapping::to_titlecase(c) {
+        // The character is its own titlecase form
+        [0, 0, 0] => out.extend([c]),
+        titlecase => out.extend(
+            titlecase
+                .into_iter()
+                .take_while(|&title| title != 0)
+                .filter_map(char::from_u32),
+        ),
+    }
+}
+
+/// The random choices made by a `CapitalizationEngine`, reported by `--entropy-report`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct EntropyReport {
+    /// The number of times the random number generator was asked for a choice.
+    pub calls: u64,
+    /// How many of those choices were to uppercase.
+    pub uppercased: u64,
+}
+
+impl EntropyReport {
+    /// The fraction of choices which were to uppercase, or zero if no choices were made.
+    pub fn uppercase_ratio(&self) -> f64 {
+        if self.calls == 0 {
+            0.
+        } else {
+            self.uppercased as f64 / self.calls as f64
+        }
+    }
+}
+
+impl fmt::Display for EntropyReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>15}: {}", "rng calls", self.calls)?;
+        writeln!(
+            f,
+            "{:>15}: {:.3}",
+            "uppercase ratio",
+            self.uppercase_ratio()
+        )
+    }
 }
 
 struct AlternatingCapitalizationEngine {
+    pub first_is_capital: bool,
     pub next_is_capital: bool,
     pub skip_whitespace: bool,
 }
 
-impl CapitalizationEngine for AlternatingCapitalizationEngine {
+impl AlternatingCapitalizationEngine {
+    pub fn new(first_is_capital: bool, skip_whitespace: bool) -> Self {
+        Self {
+            first_is_capital,
+            next_is_capital: first_is_capital,
+            skip_whitespace,
+        }
+    }
+}
+
+impl CaseEngine for AlternatingCapitalizationEngine {
     fn should_capitalize(&mut self, _index: usize, character: char) -> bool {
         let ret = self.next_is_capital;
         if !(self.skip_whitespace && character.is_whitespace()) {
@@ -18,64 +189,441 @@ impl CapitalizationEngine for AlternatingCapitalizationEngine {
         }
         ret
     }
+
+    fn line_break(&mut self) {
+        // The pattern flows across lines as if the input were one stream, with the line break as a character
+        if !self.skip_whitespace {
+            self.next_is_capital = !self.next_is_capital;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.next_is_capital = self.first_is_capital;
+    }
+}
+
+/// Where a grapheme cluster is in the words `tokenize::words` finds.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum WordPart {
+    /// Between words, like whitespace or punctuation.
+    Outside,
+    /// The first letter or digit of a word.
+    Start,
+    /// Later in a word.
+    Inside,
+}
+
+/// Finds the words of the input as `tokenize::words` does, one grapheme cluster at a time, for the engines which work
+/// on words. So "don't" is one word, "taco-truck" is two, and each Chinese ideograph is its own.
+///
+/// Words never span whitespace, and whether a word starts at a character only depends on the few characters before it,
+/// so only the last `CONTEXT` characters since the last whitespace are kept.
+#[derive(Default)]
+struct WordTracker {
+    /// The first character of each of the last grapheme clusters since the last whitespace.
+    tail: String,
+}
+
+impl WordTracker {
+    const CONTEXT: usize = 16;
+
+    /// Find where the grapheme cluster starting with `character`, which follows the ones already given, is.
+    fn push(&mut self, character: char) -> WordPart {
+        if character.is_whitespace() {
+            self.tail.clear();
+            return WordPart::Outside;
+        }
+
+        let at = self.tail.len();
+        self.tail.push(character);
+        let part = match tokenize::words(&self.tail).last() {
+            Some((range, _)) if range.end == self.tail.len() => {
+                // A word can start with something other than a letter or digit, like "_taco", which starts at "t"
+                if self.tail[range.start..at]
+                    .chars()
+                    .any(char::is_alphanumeric)
+                {
+                    WordPart::Inside
+                } else {
+                    WordPart::Start
+                }
+            }
+            _ => WordPart::Outside,
+        };
+
+        if self.tail.chars().count() > Self::CONTEXT {
+            let first = self.tail.chars().next().map_or(0, char::len_utf8);
+            self.tail.drain(..first);
+        }
+        part
+    }
+
+    /// Move past a line break, which ends any word.
+    fn line_break(&mut self) {
+        self.tail.clear();
+    }
+}
+
+/// Alternates the case of whole words (as `tokenize::words` finds them) instead of single characters, starting with a
+/// lowercase word, "like THIS". Anything between words is lowercased.
+struct AlternatingWordsCapitalizationEngine {
+    /// Whether the current word, or the last one if between words, is uppercased.
+    word_is_capital: bool,
+    words: WordTracker,
+}
+
+impl AlternatingWordsCapitalizationEngine {
+    pub fn new() -> Self {
+        // Pretend the word before the first one was uppercase, so the first word is lowercase
+        Self {
+            word_is_capital: true,
+            words: WordTracker::default(),
+        }
+    }
+}
+
+impl CaseEngine for AlternatingWordsCapitalizationEngine {
+    fn should_capitalize(&mut self, _index: usize, character: char) -> bool {
+        match self.words.push(character) {
+            WordPart::Outside => false,
+            WordPart::Start => {
+                self.word_is_capital = !self.word_is_capital;
+                self.word_is_capital
+            }
+            WordPart::Inside => self.word_is_capital,
+        }
+    }
+
+    fn line_break(&mut self) {
+        self.words.line_break();
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Capitalizes the first letter or digit of each word (as `tokenize::words` finds them) and lowercases the rest, "Like
+/// This".
+/// The first letter is titlecased rather than uppercased (see `push_titlecase`).
+struct TitleCaseCapitalizationEngine {
+    words: WordTracker,
+    /// Whether the current grapheme cluster starts a word.
+    starts_word: bool,
+    /// Whether the next character is the first of its grapheme cluster, which is the only one titlecased.
+    at_start: bool,
+}
+
+impl TitleCaseCapitalizationEngine {
+    pub fn new() -> Self {
+        Self {
+            words: WordTracker::default(),
+            starts_word: false,
+            at_start: false,
+        }
+    }
+}
+
+impl CapitalizationEngine for TitleCaseCapitalizationEngine {
+    fn start_grapheme(&mut self, _index: usize, character: char) {
+        self.starts_word = self.words.push(character) == WordPart::Start;
+        self.at_start = true;
+    }
+
+    fn map_char(&mut self, character: char) -> MappedChars {
+        let at_start = std::mem::take(&mut self.at_start);
+        let mut mapped = MappedChars::new();
+        if self.starts_word && at_start {
+            push_titlecase(&mut mapped, character);
+        } else {
+            push_cased(&mut mapped, character, false);
+        }
+        mapped
+    }
+
+    fn line_break(&mut self) {
+        self.words.line_break();
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Alternates case like `AlternatingCapitalizationEngine`, but writes some letters as the digits they look like, "L1K3
+/// 7H15". Substituted letters still take their step of the pattern.
+struct LeetCapitalizationEngine {
+    alternating: Cased<AlternatingCapitalizationEngine>,
+    /// Whether the next character is the first of its grapheme cluster, which is the only one substituted.
+    at_start: bool,
+}
+
+impl LeetCapitalizationEngine {
+    pub fn new() -> Self {
+        Self {
+            alternating: Cased::new(AlternatingCapitalizationEngine::new(true, false)),
+            at_start: false,
+        }
+    }
+
+    /// Get the digit which `character` is written as, if it has one.
+    fn substitute(character: char) -> Option<char> {
+        match character.to_ascii_lowercase() {
+            'a' => Some('4'),
+            'e' => Some('3'),
+            'i' => Some('1'),
+            'o' => Some('0'),
+            's' => Some('5'),
+            't' => Some('7'),
+            _ => None,
+        }
+    }
+}
+
+impl CapitalizationEngine for LeetCapitalizationEngine {
+    fn start_grapheme(&mut self, index: usize, character: char) {
+        self.alternating.start_grapheme(index, character);
+        self.at_start = true;
+    }
+
+    fn map_char(&mut self, character: char) -> MappedChars {
+        let at_start = std::mem::take(&mut self.at_start);
+        match Self::substitute(character).filter(|_| at_start) {
+            Some(digit) => MappedChars::from_slice(&[digit]),
+            None => self.alternating.map_char(character),
+        }
+    }
+
+    fn line_break(&mut self) {
+        self.alternating.line_break();
+    }
+
+    fn reset(&mut self) {
+        self.alternating.reset();
+    }
 }
 
 struct RandomCapitalizationEngine {
-    rng: rand::rngs::ThreadRng,
+    rng: StdRng,
+    /// The probability of uppercasing each grapheme.
+    bias: f64,
+    report: EntropyReport,
 }
 
 impl RandomCapitalizationEngine {
-    pub fn new() -> RandomCapitalizationEngine {
+    pub fn new(rng: StdRng, bias: f64) -> RandomCapitalizationEngine {
         RandomCapitalizationEngine {
-            rng: rand::thread_rng(),
+            rng,
+            bias: bias.clamp(0., 1.),
+            report: EntropyReport::default(),
+        }
+    }
+}
+
+impl CaseEngine for RandomCapitalizationEngine {
+    fn should_capitalize(&mut self, _index: usize, _character: char) -> bool {
+        let capitalize = self.rng.gen_bool(self.bias);
+        self.report.calls += 1;
+        self.report.uppercased += capitalize as u64;
+        capitalize
+    }
+
+    fn entropy_report(&self) -> Option<EntropyReport> {
+        Some(self.report)
+    }
+}
+
+/// Capitalizes randomly, but the same word always comes out the same. The choice for each letter is made by a hash of
+/// the word so far (ignoring case), so it depends only on the word and not on where it appears in the text. Words are
+/// the ones `tokenize::words` finds, and anything between them is lowercased.
+struct RandomPerWordCapitalizationEngine {
+    /// The FNV-1a hash of the current word so far.
+    word_hash: u64,
+    words: WordTracker,
+}
+
+impl RandomPerWordCapitalizationEngine {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    pub fn new() -> Self {
+        Self {
+            word_hash: Self::FNV_OFFSET_BASIS,
+            words: WordTracker::default(),
+        }
+    }
+
+    /// Scramble the bits of `hash` (the SplitMix64 finalizer), since consecutive FNV-1a states are closely related.
+    fn mix(mut hash: u64) -> u64 {
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        hash ^ (hash >> 31)
+    }
+}
+
+impl CaseEngine for RandomPerWordCapitalizationEngine {
+    fn should_capitalize(&mut self, _index: usize, character: char) -> bool {
+        match self.words.push(character) {
+            WordPart::Outside => return false,
+            WordPart::Start => self.word_hash = Self::FNV_OFFSET_BASIS,
+            WordPart::Inside => {}
+        }
+
+        for c in character.to_lowercase() {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                self.word_hash = (self.word_hash ^ byte as u64).wrapping_mul(Self::FNV_PRIME);
+            }
+        }
+        Self::mix(self.word_hash) >> 63 == 1
+    }
+
+    fn line_break(&mut self) {
+        self.words.line_break();
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// A custom capitalization pattern like "uulL", where `u` means uppercase and `l` means lowercase (in either case).
+/// Whitespace in the pattern is ignored. The pattern is applied one character at a time, repeating as needed.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct Pattern(Vec<bool>);
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &capital in self.0.iter() {
+            write!(f, "{}", if capital { 'u' } else { 'l' })?;
         }
+        Ok(())
     }
 }
 
-impl CapitalizationEngine for RandomCapitalizationEngine {
+impl str::FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(input: &str) -> std::result::Result<Self, Self::Err> {
+        let pattern = input
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| match c {
+                'u' | 'U' => Ok(true),
+                'l' | 'L' => Ok(false),
+                _ => Err(format!(
+                    "Invalid pattern symbol '{}' (only 'u' and 'l' are allowed)",
+                    c
+                )),
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        if pattern.is_empty() {
+            Err("Capitalization pattern is empty".to_owned())
+        } else {
+            Ok(Self(pattern))
+        }
+    }
+}
+
+struct PatternCapitalizationEngine {
+    pattern: Pattern,
+    position: usize,
+}
+
+impl CaseEngine for PatternCapitalizationEngine {
     fn should_capitalize(&mut self, _index: usize, _character: char) -> bool {
-        self.rng.gen_bool(0.5)
+        let ret = self.pattern.0[self.position];
+        self.position = (self.position + 1) % self.pattern.0.len();
+        ret
+    }
+
+    fn reset(&mut self) {
+        self.position = 0;
+    }
+}
+
+/// The probability the random strategy uppercases each grapheme with, unless it is given another.
+pub const DEFAULT_RANDOM_BIAS: f64 = 0.5;
+
+/// Settings for the engines `CapitalizationStrategy` creates, which only some strategies use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EngineOptions {
+    /// Seed the random number generator of the random strategy, so the same seed and input always give the same
+    /// output. `None` seeds it from the system.
+    pub seed: Option<u64>,
+    /// The probability the random strategy uppercases each grapheme with, from 0 to 1.
+    pub random_bias: f64,
+}
+
+impl Default for EngineOptions {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            random_bias: DEFAULT_RANDOM_BIAS,
+        }
     }
 }
 
-#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+#[derive(Eq, PartialEq, Debug, Clone)]
 pub enum CapitalizationStrategy {
     AlternatingInitialUppercase,
     AlternatingInitialLowercase,
     AlternatingInitialUppercaseSkipWhitespace,
     AlternatingInitialLowercaseSkipWhitespace,
+    /// Alternate the case of whole words instead of single characters.
+    AlternatingWords,
+    /// Capitalize the first letter of each word with its titlecase form, and lowercase the rest.
+    TitleCase,
     Randomly,
+    /// Like `Randomly`, but every occurrence of a word is capitalized the same way.
+    RandomlyPerWord,
+    /// Alternate case like `AlternatingInitialUppercase`, and write some letters as digits.
+    Leet,
+    FromPattern(Pattern),
 }
 
 impl CapitalizationStrategy {
-    /// Create a `CapitalizationEngine` based on this strategy description.
-    pub fn create_engine(&self) -> Box<dyn CapitalizationEngine> {
+    /// Create a `CapitalizationEngine` based on this strategy description, with `options` for the strategies which
+    /// use them.
+    pub fn create_engine(&self, options: EngineOptions) -> Box<dyn CapitalizationEngine> {
         match self {
-            Self::AlternatingInitialUppercase => Box::new(AlternatingCapitalizationEngine {
-                next_is_capital: true,
-                skip_whitespace: false,
-            }),
-            Self::AlternatingInitialLowercase => Box::new(AlternatingCapitalizationEngine {
-                next_is_capital: false,
-                skip_whitespace: false,
-            }),
+            Self::AlternatingInitialUppercase => Box::new(Cased::new(
+                AlternatingCapitalizationEngine::new(true, false),
+            )),
+            Self::AlternatingInitialLowercase => Box::new(Cased::new(
+                AlternatingCapitalizationEngine::new(false, false),
+            )),
             Self::AlternatingInitialUppercaseSkipWhitespace => {
-                Box::new(AlternatingCapitalizationEngine {
-                    next_is_capital: true,
-                    skip_whitespace: true,
-                })
+                Box::new(Cased::new(AlternatingCapitalizationEngine::new(true, true)))
             }
-            Self::AlternatingInitialLowercaseSkipWhitespace => {
-                Box::new(AlternatingCapitalizationEngine {
-                    next_is_capital: false,
-                    skip_whitespace: true,
-                })
+            Self::AlternatingInitialLowercaseSkipWhitespace => Box::new(Cased::new(
+                AlternatingCapitalizationEngine::new(false, true),
+            )),
+            Self::AlternatingWords => {
+                Box::new(Cased::new(AlternatingWordsCapitalizationEngine::new()))
+            }
+            Self::TitleCase => Box::new(TitleCaseCapitalizationEngine::new()),
+            Self::Randomly => {
+                let rng = options
+                    .seed
+                    .map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+                Box::new(Cased::new(RandomCapitalizationEngine::new(
+                    rng,
+                    options.random_bias,
+                )))
             }
-            Self::Randomly => Box::new(RandomCapitalizationEngine::new()),
+            Self::RandomlyPerWord => Box::new(Cased::new(RandomPerWordCapitalizationEngine::new())),
+            Self::Leet => Box::new(LeetCapitalizationEngine::new()),
+            Self::FromPattern(pattern) => Box::new(Cased::new(PatternCapitalizationEngine {
+                pattern: pattern.clone(),
+                position: 0,
+            })),
         }
     }
 }
 
+#[allow(clippy::derivable_impls)]
 impl Default for CapitalizationStrategy {
     fn default() -> Self {
         Self::AlternatingInitialUppercase
@@ -91,7 +639,12 @@ impl fmt::Display for CapitalizationStrategy {
             AlternatingInitialUppercase => "LiKe tHiS",
             AlternatingInitialLowercaseSkipWhitespace => "lIkE tHiS",
             AlternatingInitialUppercaseSkipWhitespace => "LiKe ThIs",
+            AlternatingWords => "like THIS",
+            TitleCase => "Like This",
             Randomly => "RAnDOmlY",
+            RandomlyPerWord => "RAnDOmlY pEr WoRD",
+            Leet => "leet",
+            FromPattern(pattern) => return write!(f, "{}", pattern),
         };
 
         write!(f, "{}", s)
@@ -107,6 +660,10 @@ impl str::FromStr for CapitalizationStrategy {
             "LiKe tHiS" => Ok(Self::AlternatingInitialUppercase),
             "lIkE tHiS" => Ok(Self::AlternatingInitialLowercaseSkipWhitespace),
             "LiKe ThIs" => Ok(Self::AlternatingInitialUppercaseSkipWhitespace),
+            "like THIS" => Ok(Self::AlternatingWords),
+            "Like This" => Ok(Self::TitleCase),
+            x if x.to_lowercase() == "randomly per word" => Ok(Self::RandomlyPerWord),
+            x if x.eq_ignore_ascii_case("leet") || x.eq_ignore_ascii_case("l33t") => Ok(Self::Leet),
             x if x.to_lowercase().matches("randomly").count() == 1 => Ok(Self::Randomly),
             _ => Err(format!("Unknown capitalization \"{}\"", input)),
         }
@@ -116,6 +673,14 @@ impl str::FromStr for CapitalizationStrategy {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::capitalize_str;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    /// Check if `engine` uppercases `character` as the grapheme cluster at `index`.
+    fn capitalizes(engine: &mut dyn CapitalizationEngine, index: usize, character: char) -> bool {
+        engine.start_grapheme(index, character);
+        engine.map_char(character).iter().any(|c| c.is_uppercase())
+    }
 
     #[test]
     fn capitalization_strategy_from_str() {
@@ -131,50 +696,245 @@ mod tests {
             AlternatingInitialUppercaseSkipWhitespace,
             "LiKe ThIs".parse().unwrap()
         );
+        assert_eq!(AlternatingWords, "like THIS".parse().unwrap());
+        assert_eq!(TitleCase, "Like This".parse().unwrap());
         assert_eq!(Randomly, "randomly".parse().unwrap());
-    }
-
-    fn capitalize_with(style: CapitalizationStrategy, src: &str) -> String {
-        let mut engine = style.create_engine();
-
-        let mut out = String::new();
-
-        for (idx, c) in src.chars().enumerate() {
-            if engine.should_capitalize(idx, c) {
-                out.push(c.to_ascii_uppercase())
-            } else {
-                out.push(c.to_ascii_lowercase())
-            }
-        }
-
-        out
+        assert_eq!(RandomlyPerWord, "RAnDOmlY pEr WoRD".parse().unwrap());
+        assert_eq!(Leet, "leet".parse().unwrap());
+        assert_eq!(Leet, "L33T".parse().unwrap());
+        assert!("LIKE this".parse::<CapitalizationStrategy>().is_err());
     }
 
     #[test]
     fn alternating_initial_uppercase() {
         let strategy = CapitalizationStrategy::AlternatingInitialUppercase;
 
-        assert_eq!(capitalize_with(strategy, "taco truck"), "TaCo tRuCk");
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        assert_eq!(capitalize_str(engine.as_mut(), "taco truck"), "TaCo tRuCk");
     }
 
     #[test]
     fn alternating_initial_lowercase() {
         let strategy = CapitalizationStrategy::AlternatingInitialLowercase;
 
-        assert_eq!(capitalize_with(strategy, "taco truck"), "tAcO TrUcK");
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        assert_eq!(capitalize_str(engine.as_mut(), "taco truck"), "tAcO TrUcK");
     }
 
     #[test]
     fn alternating_initial_uppercase_skip_whitespace() {
         let strategy = CapitalizationStrategy::AlternatingInitialUppercaseSkipWhitespace;
 
-        assert_eq!(capitalize_with(strategy, "taco truck"), "TaCo TrUcK");
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        assert_eq!(capitalize_str(engine.as_mut(), "taco truck"), "TaCo TrUcK");
     }
 
     #[test]
     fn alternating_initial_lowercase_skip_whitespace() {
         let strategy = CapitalizationStrategy::AlternatingInitialLowercaseSkipWhitespace;
 
-        assert_eq!(capitalize_with(strategy, "taco truck"), "tAcO tRuCk");
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        assert_eq!(capitalize_str(engine.as_mut(), "taco truck"), "tAcO tRuCk");
+    }
+
+    #[test]
+    fn leet() {
+        let strategy = CapitalizationStrategy::Leet;
+
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        // Substituted letters still advance the alternating pattern
+        assert_eq!(capitalize_str(engine.as_mut(), "taco truck"), "74C0 7RuCk");
+        // Only the first character of a grapheme cluster is substituted
+        assert_eq!(capitalize_str(engine.as_mut(), "o\u{308}e"), "0\u{308}3");
+        engine.reset();
+        engine.start_grapheme(0, 'x');
+        assert_eq!(engine.map_char('x').as_slice(), ['X']);
+    }
+
+    #[test]
+    fn alternating_words() {
+        let strategy = CapitalizationStrategy::AlternatingWords;
+
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        assert_eq!(
+            capitalize_str(engine.as_mut(), "this is how it looks"),
+            "this IS how IT looks"
+        );
+        // Runs of whitespace and punctuation do not change the pattern; only the start of a word does, and the hyphen
+        // starts a new word
+        engine.reset();
+        assert_eq!(
+            capitalize_str(engine.as_mut(), "  taco,  truck!\tburrito-time"),
+            "  taco,  TRUCK!\tburrito-TIME"
+        );
+        assert_eq!(strategy.to_string().parse(), Ok(strategy));
+    }
+
+    #[test]
+    fn word_strategies_use_tokenizer_words() {
+        let capitalize = |strategy: CapitalizationStrategy, text| {
+            capitalize_str(
+                strategy.create_engine(EngineOptions::default()).as_mut(),
+                text,
+            )
+        };
+
+        // "don't" is one word, the hyphen splits "taco-truck" into two, and each ideograph is a word of its own
+        let text = "don't taco-truck 卡车 e\u{301}_x";
+        assert_eq!(
+            capitalize(CapitalizationStrategy::AlternatingWords, text),
+            "don't TACO-truck 卡车 E\u{301}_X"
+        );
+        assert_eq!(
+            capitalize(CapitalizationStrategy::TitleCase, text),
+            "Don't Taco-Truck 卡车 E\u{301}_x"
+        );
+        let per_word = capitalize(CapitalizationStrategy::RandomlyPerWord, text);
+        for (range, word) in tokenize::words(text) {
+            assert_eq!(
+                per_word[range].to_string(),
+                capitalize(CapitalizationStrategy::RandomlyPerWord, word),
+                "for {word}"
+            );
+        }
+
+        // Every word start the tracker finds is the start of a word the tokenizer finds
+        for text in [
+            text,
+            "  taco,  truck!\tburrito-time",
+            "_taco it's 3.14 我喜欢吃",
+        ] {
+            let mut tracker = WordTracker::default();
+            let starts = text
+                .grapheme_indices(true)
+                .filter(|(_, grapheme)| {
+                    tracker.push(grapheme.chars().next().unwrap()) == WordPart::Start
+                })
+                .map(|(start, _)| start)
+                .collect::<Vec<_>>();
+            let words = tokenize::words(text)
+                .map(|(range, word)| range.start + word.find(char::is_alphanumeric).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(starts, words, "for {text:?}");
+        }
+    }
+
+    #[test]
+    fn title_case() {
+        let strategy = CapitalizationStrategy::TitleCase;
+
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        assert_eq!(
+            capitalize_str(engine.as_mut(), "tHIS is (how) it 3rd-LOOKS"),
+            "This Is (How) It 3rd-Looks"
+        );
+        // Digraphs take their titlecase form at the start of a word, which is not their uppercase form
+        engine.reset();
+        assert_eq!(
+            capitalize_str(engine.as_mut(), "\u{1f3}emal \u{1f3}\u{1f3}"),
+            "\u{1f2}emal \u{1f2}\u{1f3}"
+        );
+        let mut uppercase = String::new();
+        push_uppercase(&mut uppercase, '\u{1f3}');
+        assert_eq!(uppercase, "\u{1f1}");
+        assert_eq!(strategy.to_string().parse(), Ok(strategy));
+    }
+
+    #[test]
+    fn random_entropy_report() {
+        let mut engine = CapitalizationStrategy::Randomly.create_engine(EngineOptions::default());
+        let uppercased = (0..100)
+            .filter(|&idx| capitalizes(engine.as_mut(), idx, 'a'))
+            .count();
+
+        let report = engine.entropy_report().unwrap();
+        assert_eq!(report.calls, 100);
+        assert_eq!(report.uppercased, uppercased as u64);
+        assert_eq!(report.uppercase_ratio(), uppercased as f64 / 100.);
+
+        let engine = CapitalizationStrategy::AlternatingInitialUppercase
+            .create_engine(EngineOptions::default());
+        assert_eq!(engine.entropy_report(), None);
+    }
+
+    #[test]
+    fn randomly_seeded_is_reproducible() {
+        let capitalize = |seed| {
+            let options = EngineOptions {
+                seed: Some(seed),
+                ..Default::default()
+            };
+            let mut engine = CapitalizationStrategy::Randomly.create_engine(options);
+            capitalize_str(
+                engine.as_mut(),
+                "the quick brown fox jumps over the lazy dog",
+            )
+        };
+
+        assert_eq!(capitalize(0), capitalize(0));
+        assert_eq!(capitalize(42), capitalize(42));
+        assert_ne!(capitalize(0), capitalize(42));
+    }
+
+    #[test]
+    fn random_bias() {
+        let uppercased = |random_bias| {
+            let options = EngineOptions {
+                seed: Some(0),
+                random_bias,
+            };
+            let mut engine = CapitalizationStrategy::Randomly.create_engine(options);
+            (0..1000)
+                .filter(|&idx| capitalizes(engine.as_mut(), idx, 'a'))
+                .count()
+        };
+
+        assert_eq!(uppercased(0.), 0);
+        assert_eq!(uppercased(1.), 1000);
+        let mostly_lowercase = uppercased(0.2);
+        assert!(
+            (150..250).contains(&mostly_lowercase),
+            "{mostly_lowercase} of 1000 uppercased"
+        );
+        let mostly_uppercase = uppercased(0.8);
+        assert!(
+            (750..850).contains(&mostly_uppercase),
+            "{mostly_uppercase} of 1000 uppercased"
+        );
+    }
+
+    #[test]
+    fn randomly_per_word_is_stable() {
+        let strategy = CapitalizationStrategy::RandomlyPerWord;
+        let capitalize = |text| {
+            capitalize_str(
+                strategy.create_engine(EngineOptions::default()).as_mut(),
+                text,
+            )
+        };
+        let out = capitalize("hello, world hello HELLO");
+
+        let words = out
+            .split([' ', ','])
+            .filter(|w| !w.is_empty())
+            .collect::<Vec<_>>();
+        assert_eq!(words[0], words[2]);
+        assert_eq!(words[0], words[3]);
+        assert_eq!(out, capitalize("hello, world hello HELLO"));
+    }
+
+    #[test]
+    fn pattern_from_str() {
+        assert_eq!("uul L".parse::<Pattern>().unwrap().to_string(), "uull");
+        assert!("ulx".parse::<Pattern>().is_err());
+        assert!(" \n".parse::<Pattern>().is_err());
+    }
+
+    #[test]
+    fn from_pattern() {
+        let strategy = CapitalizationStrategy::FromPattern("uul".parse().unwrap());
+
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        assert_eq!(capitalize_str(engine.as_mut(), "taco truck"), "TAcO tRUcK");
     }
 }
@@ -0,0 +1,131 @@
+//! Byte Counting
+//! =============
+//!
+//! Counts the bytes read from the input and written to the output for `--count-bytes`. The two counts can differ even
+//! though SpOnGiFying only changes the case of letters, since some letters take more bytes in one case than the other
+//! (like "ß", which uppercases to "SS").
+
+use std::{
+    fmt, io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// A count of bytes, shared between a `CountingReader` or `CountingWriter` and whoever wants to know the count.
+#[derive(Clone, Debug, Default)]
+pub struct ByteCount(Arc<AtomicU64>);
+
+impl ByteCount {
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    fn add(&self, bytes: usize) {
+        self.0.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// A reader which counts the bytes read through it.
+pub struct CountingReader<R> {
+    inner: R,
+    count: ByteCount,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R, count: ByteCount) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<R: io::Read> io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count.add(read);
+        Ok(read)
+    }
+}
+
+impl<R: io::BufRead> io::BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count.add(amt);
+        self.inner.consume(amt);
+    }
+}
+
+/// A writer which counts the bytes written through it.
+pub struct CountingWriter<W> {
+    inner: W,
+    count: ByteCount,
+}
+
+impl<W> CountingWriter<W> {
+    pub fn new(inner: W, count: ByteCount) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<W: io::Write> io::Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count.add(written);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The bytes read and written by a run, for `--count-bytes`.
+#[derive(Clone, Debug, Default)]
+pub struct ByteCounts {
+    pub read: ByteCount,
+    pub written: ByteCount,
+}
+
+impl fmt::Display for ByteCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>9}: {}", "bytes in", self.read.get())?;
+        writeln!(f, "{:>9}: {}", "bytes out", self.written.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, Read, Write};
+
+    #[test]
+    fn counting_reader() {
+        let count = ByteCount::default();
+        let mut reader = CountingReader::new(io::Cursor::new("taco\ntruck\n"), count.clone());
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(count.get(), 5);
+
+        // Looking at the buffer without consuming it does not count
+        reader.fill_buf().unwrap();
+        assert_eq!(count.get(), 5);
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(count.get(), 11);
+    }
+
+    #[test]
+    fn counting_writer() {
+        let count = ByteCount::default();
+        let mut writer = CountingWriter::new(Vec::new(), count.clone());
+
+        writer.write_all("taco trück".as_bytes()).unwrap();
+        assert_eq!(count.get(), 11);
+        assert_eq!(writer.inner, "taco trück".as_bytes());
+    }
+}
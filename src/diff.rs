@@ -0,0 +1,149 @@
+//! Change Highlighting
+//! ===================
+//!
+//! Shows which characters SpOnGiFy changed, for `spongify diff`. The SpOnGiFied text is printed with characters changed
+//! to uppercase in green and characters changed to lowercase in yellow, using ANSI colors. Following
+//! [`NO_COLOR`](https://no-color.org), colors are left out when that variable is set to anything but an empty string.
+
+use crate::{
+    read_lines,
+    stats::Stats,
+    timing::{Stage, Timings},
+    Result, Spongifier,
+};
+use std::{io, time::Instant};
+
+/// What happened to a piece of the input.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Change {
+    Unchanged,
+    Uppercased,
+    Lowercased,
+}
+
+impl Change {
+    /// Classify `original` being SpOnGiFied into `output`.
+    pub fn of(original: &str, output: &str) -> Self {
+        if original == output {
+            Self::Unchanged
+        } else if output.chars().any(char::is_uppercase) {
+            Self::Uppercased
+        } else {
+            Self::Lowercased
+        }
+    }
+
+    /// The ANSI escape code to highlight this change with, if it is highlighted at all.
+    fn color(self) -> Option<&'static str> {
+        match self {
+            Self::Unchanged => None,
+            Self::Uppercased => Some("\x1b[32m"),
+            Self::Lowercased => Some("\x1b[33m"),
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+
+/// Check if colors should be used, which is unless `NO_COLOR` is set to something.
+pub fn color_enabled() -> bool {
+    std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+/// SpOnGiFy `line`, highlighting the characters which changed if `color` is set.
+pub fn diff_line(
+    spongifier: &mut Spongifier,
+    line: &str,
+    color: bool,
+    stats: &mut Stats,
+) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut current = None;
+
+    spongifier.spongify_mask(line, stats, |original, output| {
+        if !color {
+            out.push_str(output);
+            return;
+        }
+        if output.is_empty() {
+            return;
+        }
+
+        let change = Change::of(original, output).color();
+        if change != current {
+            if current.is_some() {
+                out.push_str(RESET);
+            }
+            out.push_str(change.unwrap_or_default());
+            current = change;
+        }
+        out.push_str(output);
+    });
+
+    if current.is_some() {
+        out.push_str(RESET);
+    }
+    out
+}
+
+/// SpOnGiFy each line of `input` into `output` with `diff_line`.
+pub fn write_diff(
+    spongifier: &mut Spongifier,
+    input: impl io::BufRead,
+    output: &mut dyn io::Write,
+    color: bool,
+    stats: &mut Stats,
+    timings: &mut Timings,
+) -> Result<()> {
+    let mut lines = read_lines(input, spongifier.text.lossy_utf8);
+    while let Some(line) = timings.time(Stage::Read, || lines.next()) {
+        let line = line?;
+        let start = Instant::now();
+
+        writeln!(output, "{}", diff_line(spongifier, &line, color, stats))?;
+
+        timings.add(Stage::Spongify, start.elapsed());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextOpt;
+    use rand::{rngs::StdRng, SeedableRng};
+    use spongify::{CapitalizationStrategy, EngineOptions};
+
+    fn diff(line: &str, color: bool) -> String {
+        let text = TextOpt::default();
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        let mut spongifier = Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0));
+        diff_line(&mut spongifier, line, color, &mut Stats::new())
+    }
+
+    #[test]
+    fn change_of() {
+        assert_eq!(Change::of("a", "A"), Change::Uppercased);
+        assert_eq!(Change::of("A", "a"), Change::Lowercased);
+        assert_eq!(Change::of("a", "a"), Change::Unchanged);
+        assert_eq!(Change::of(" ", " "), Change::Unchanged);
+        assert_eq!(Change::of("ß", "SS"), Change::Uppercased);
+    }
+
+    #[test]
+    fn diff_line_highlights_changes() {
+        // "tAco!" spongifies to "TaCo!": t is uppercased, A lowercased, c uppercased, and o and ! are unchanged
+        assert_eq!(
+            diff("tAco!", true),
+            "\x1b[32mT\x1b[0m\x1b[33ma\x1b[0m\x1b[32mC\x1b[0mo!"
+        );
+        assert_eq!(diff("TaCo", true), "TaCo");
+    }
+
+    #[test]
+    fn diff_line_without_color() {
+        assert_eq!(diff("tAco!", false), "TaCo!");
+    }
+}
@@ -0,0 +1,183 @@
+//! Face Detection
+//! ==============
+//!
+//! Finds a face in the base image for `--smart-placement`, so the caption can go somewhere it does not cover it. This
+//! is a skin tone detector rather than a trained one (like a Haar cascade): pixels are classified as skin by their
+//! chroma in YCbCr, which holds up across skin tones and lighting, and the largest connected patch of skin is taken to
+//! be the face. It is easily fooled by skin-colored backgrounds, but it is small, fast, and needs no model files.
+
+use crate::rect::Rect;
+use std::collections::VecDeque;
+
+/// The longest side of the image that detection runs on. Larger images are sampled down to this first.
+const DETECTION_SIZE: u32 = 128;
+/// The smallest fraction of the image a patch of skin must cover to count as a face.
+const MIN_FACE_AREA: f32 = 0.01;
+
+/// Check if `pixel` looks like skin by its blue and red chroma (the Chai and Ngan ranges).
+fn is_skin(pixel: &image::Rgba<u8>) -> bool {
+    let [r, g, b, a] = pixel.0.map(f32::from);
+    let cb = 128. - 0.168_736 * r - 0.331_264 * g + 0.5 * b;
+    let cr = 128. + 0.5 * r - 0.418_688 * g - 0.081_312 * b;
+    a > 127. && (77. ..=127.).contains(&cb) && (133. ..=173.).contains(&cr)
+}
+
+/// Find the largest face in `image`, if there is one.
+pub fn detect_face(image: &image::RgbaImage) -> Option<Rect> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+
+    // Sample every `step`th pixel, so detection takes about the same time for any size of image
+    let step = width.max(height).div_ceil(DETECTION_SIZE).max(1);
+    let (grid_width, grid_height) = (width.div_ceil(step), height.div_ceil(step));
+    let mut skin = (0..grid_height)
+        .flat_map(|y| (0..grid_width).map(move |x| (x, y)))
+        .map(|(x, y)| is_skin(image.get_pixel(x * step, y * step)))
+        .collect::<Vec<_>>();
+
+    let mut largest: Option<(usize, Rect)> = None;
+    for start in 0..skin.len() {
+        if !skin[start] {
+            continue;
+        }
+
+        // Flood fill the patch of skin from `start`, clearing it so it is only counted once
+        skin[start] = false;
+        let mut queue = VecDeque::from([start]);
+        let mut area = 0;
+        let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+        while let Some(index) = queue.pop_front() {
+            let (x, y) = (index as u32 % grid_width, index as u32 / grid_width);
+            area += 1;
+            (left, top, right, bottom) = (left.min(x), top.min(y), right.max(x), bottom.max(y));
+
+            let neighbors = [
+                (x > 0).then(|| index - 1),
+                (x + 1 < grid_width).then(|| index + 1),
+                (y > 0).then(|| index - grid_width as usize),
+                (y + 1 < grid_height).then(|| index + grid_width as usize),
+            ];
+            for neighbor in neighbors.into_iter().flatten() {
+                if skin[neighbor] {
+                    skin[neighbor] = false;
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+
+        if largest.is_none_or(|(largest_area, _)| area > largest_area) {
+            let rect = Rect {
+                x: left * step,
+                y: top * step,
+                width: ((right + 1) * step).min(width) - left * step,
+                height: ((bottom + 1) * step).min(height) - top * step,
+            };
+            largest = Some((area, rect));
+        }
+    }
+
+    let (area, rect) = largest?;
+    (area as f32 >= MIN_FACE_AREA * skin.len() as f32).then_some(rect)
+}
+
+/// Get the full-width band of an image `height` pixels tall which a caption should go in to avoid `face`: whichever of
+/// the space above and below it is taller. The band is given as its top row and height.
+pub fn caption_band(face: Rect, height: u32) -> (u32, u32) {
+    let above = face.y;
+    let below = height.saturating_sub(face.bottom());
+    if above >= below {
+        (0, above)
+    } else {
+        (face.bottom(), below)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SKIN: image::Rgba<u8> = image::Rgba([224, 172, 140, 255]);
+    const SKY: image::Rgba<u8> = image::Rgba([90, 140, 220, 255]);
+
+    /// An image of sky with a patch of skin at `face`.
+    fn portrait(width: u32, height: u32, face: Rect) -> image::RgbaImage {
+        image::RgbaImage::from_fn(width, height, |x, y| {
+            let inside =
+                (face.x..face.x + face.width).contains(&x) && (face.y..face.bottom()).contains(&y);
+            if inside {
+                SKIN
+            } else {
+                SKY
+            }
+        })
+    }
+
+    #[test]
+    fn skin_tones() {
+        assert!(is_skin(&SKIN));
+        assert!(is_skin(&image::Rgba([141, 85, 36, 255])));
+        assert!(!is_skin(&SKY));
+        assert!(!is_skin(&image::Rgba([255, 255, 255, 255])));
+        assert!(!is_skin(&image::Rgba([255, 236, 0, 255])));
+    }
+
+    #[test]
+    fn detect_face_finds_largest_patch() {
+        let face = Rect {
+            x: 120,
+            y: 40,
+            width: 100,
+            height: 120,
+        };
+        let mut image = portrait(400, 300, face);
+        // A small patch of skin elsewhere is not the face
+        for (x, y) in (10..20).flat_map(|x| (250..260).map(move |y| (x, y))) {
+            image.put_pixel(x, y, SKIN);
+        }
+
+        let detected = detect_face(&image).unwrap();
+        // Detection is done on a sampled grid, so the box is only accurate to a few pixels
+        for (found, expected) in [
+            (detected.x, face.x),
+            (detected.y, face.y),
+            (detected.width, face.width),
+            (detected.height, face.height),
+        ] {
+            assert!(
+                found.abs_diff(expected) <= 4,
+                "found {detected:?}, expected {face:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn detect_face_none() {
+        assert_eq!(
+            detect_face(&image::RgbaImage::from_pixel(300, 200, SKY)),
+            None
+        );
+
+        // Specks of skin are too small to be a face
+        let speck = Rect {
+            x: 10,
+            y: 10,
+            width: 3,
+            height: 3,
+        };
+        assert_eq!(detect_face(&portrait(300, 200, speck)), None);
+    }
+
+    #[test]
+    fn caption_band_avoids_face() {
+        let face = Rect {
+            x: 0,
+            y: 50,
+            width: 10,
+            height: 100,
+        };
+        assert_eq!(caption_band(face, 400), (150, 250));
+        assert_eq!(caption_band(face, 180), (0, 50));
+    }
+}
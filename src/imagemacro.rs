@@ -5,41 +5,190 @@
 //! masses would refer to as a "meme." This module generates image macros for Mocking Spongebob. It could be useful to
 //! expand the capabilities of this module into a library all its own, but that would require fewer magic numbers.
 
-use std::{cell::RefCell, collections::HashMap};
+use std::{borrow::Cow, cell::RefCell, collections::HashMap, io};
 use bytes::Bytes;
 use fontdue::{Font, layout::{self, Layout}};
 use image::Pixel;
+use serde::Serialize;
+use crate::{face, rect::Rect, timing::{Stage, Timings}};
+use unicode_properties::UnicodeEmoji;
+use unicode_script::{Script, UnicodeScript};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
-const ANTON_REGULAR_SOURCE: &'static [u8] = include_bytes!("Anton-Regular.ttf");
-const MOCKING_SPONGEBOB_SOURCE: &'static [u8] = include_bytes!("mocking-spongebob.jpg");
+const ANTON_REGULAR_SOURCE: &[u8] = include_bytes!("Anton-Regular.ttf");
+/// The fonts built into SpOnGiFy.
+const BUNDLED_FONTS: &[&[u8]] = &[ANTON_REGULAR_SOURCE];
+const MOCKING_SPONGEBOB_SOURCE: &[u8] = include_bytes!("mocking-spongebob.jpg");
 
 type Color = image::Rgba<u8>;
 
+/// A group of Unicode scripts which can be rendered with its own font.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ScriptClass {
+    Latin,
+    /// Chinese, Japanese, and Korean.
+    Cjk,
+    /// Emoji and other pictographs, which may be drawn in color (see `RenderOptions::color_emoji`).
+    Emoji,
+}
+
+impl ScriptClass {
+    pub const ALL: [Self; 3] = [Self::Latin, Self::Cjk, Self::Emoji];
+
+    /// Characters which a font must have to be considered to cover this class.
+    fn samples(self) -> &'static [char] {
+        match self {
+            Self::Latin => &['A', 'Z', 'a', 'z'],
+            Self::Cjk => &['漢', 'あ', 'ア', '한'],
+            Self::Emoji => &['😀', '👍', '🎉', '🧽'],
+        }
+    }
+
+    /// Get the class of `c`, or `None` if it is not part of any class or is shared between scripts (like punctuation,
+    /// digits, and combining marks).
+    pub fn of(c: char) -> Option<Self> {
+        if !c.is_ascii() && c.is_emoji_char() {
+            return Some(Self::Emoji);
+        }
+
+        match c.script() {
+            Script::Latin => Some(Self::Latin),
+            Script::Han | Script::Hiragana | Script::Katakana | Script::Hangul | Script::Bopomofo => Some(Self::Cjk),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ScriptClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            Self::Latin => "Latin",
+            Self::Cjk => "CJK",
+            Self::Emoji => "Emoji",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+/// The name of a font and the scripts it can render, for `spongify fonts`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FontInfo {
+    pub family: String,
+    pub scripts: Vec<ScriptClass>,
+}
+
+impl FontInfo {
+    /// Read the info of the TrueType or OpenType font in `source`.
+    pub fn read(source: &[u8]) -> Result<Self, String> {
+        let face = ttf_parser::Face::from_slice(source, 0).map_err(|e| e.to_string())?;
+
+        // Prefer the typographic family, since the legacy family name can include the style for fonts with many styles
+        let find_name = |name_id| {
+            face.names()
+                .into_iter()
+                .filter(|name| name.name_id == name_id)
+                .find_map(|name| name.to_string())
+        };
+        let family = find_name(ttf_parser::name_id::TYPOGRAPHIC_FAMILY)
+            .or_else(|| find_name(ttf_parser::name_id::FAMILY))
+            .ok_or("font has no family name")?;
+
+        let scripts = ScriptClass::ALL
+            .into_iter()
+            .filter(|class| class.samples().iter().all(|c| face.glyph_index(*c).is_some()))
+            .collect();
+
+        Ok(Self { family, scripts })
+    }
+}
+
+/// Get the `FontInfo` of each font built into SpOnGiFy.
+pub fn bundled_fonts() -> Vec<FontInfo> {
+    BUNDLED_FONTS
+        .iter()
+        .map(|source| FontInfo::read(source).expect("Failed to read built-in font"))
+        .collect()
+}
+
+/// The fonts a caption is rendered with: the default font and, optionally, a font for each `ScriptClass`.
+struct CaptionFonts<'a> {
+    /// All the fonts, where the default font is first. This is what `Layout` font indices refer to.
+    fonts: Vec<&'a Font>,
+    /// The classes with their own font, where `scripts[i]` is rendered with `fonts[i + 1]`.
+    scripts: Vec<ScriptClass>,
+}
+
+impl<'a> CaptionFonts<'a> {
+    pub fn new(default_font: &'a Font, script_fonts: &'a [(ScriptClass, Font)]) -> Self {
+        let mut fonts = vec![default_font];
+        fonts.extend(script_fonts.iter().map(|(_, font)| font));
+        Self {
+            fonts,
+            scripts: script_fonts.iter().map(|(script, _)| *script).collect(),
+        }
+    }
+
+    /// Split `text` into runs which use the same font.
+    ///
+    /// # Return
+    /// A list of text runs and the index of the font in `fonts` to render them with. Characters which are not part of
+    /// a `ScriptClass` stay with the font of the run they are in.
+    pub fn runs<'t>(&self, text: &'t str) -> Vec<(&'t str, usize)> {
+        let mut runs = Vec::new();
+        let mut run_start = 0;
+        let mut run_font = None;
+
+        for (offset, c) in text.char_indices() {
+            let Some(class) = ScriptClass::of(c) else {
+                continue;
+            };
+            let font = self.scripts.iter().position(|x| *x == class).map_or(0, |idx| idx + 1);
+
+            match run_font {
+                Some(current) if current != font => {
+                    runs.push((&text[run_start..offset], current));
+                    run_start = offset;
+                    run_font = Some(font);
+                }
+                Some(_) => {}
+                None => run_font = Some(font),
+            }
+        }
+
+        if run_start < text.len() {
+            runs.push((&text[run_start..], run_font.unwrap_or(0)));
+        }
+        runs
+    }
+}
+
 struct GlyphGenerator<'a> {
-    font: &'a Font,
+    fonts: &'a [&'a Font],
     cache: RefCell<HashMap<layout::GlyphRasterConfig, (fontdue::Metrics, Bytes)>>,
 }
 
 impl<'a> GlyphGenerator<'a> {
-    pub fn with_capacity(font: &'a Font, capacity: usize) -> Self {
+    pub fn with_capacity(fonts: &'a [&'a Font], capacity: usize) -> Self {
         Self {
-            font,
+            fonts,
             cache: RefCell::new(HashMap::with_capacity(capacity))
         }
     }
 
-    /// Get the glyph named `key`.
+    /// Get the glyph named `key` from the font at `font_index`.
     ///
     /// # Return
     /// The returned value is a tuple of `Metrics` and data. The `Metrics` covers positioning metadata like initial
     /// position as well as width and height. The data is covering information, where 0 represents no coverage and 255
     /// represents full coverage.
-    pub fn glyph(&self, key: layout::GlyphRasterConfig) -> (fontdue::Metrics, Bytes) {
+    pub fn glyph(&self, font_index: usize, key: layout::GlyphRasterConfig) -> (fontdue::Metrics, Bytes) {
         self.cache
             .borrow_mut()
             .entry(key)
             .or_insert_with(|| {
-                let (metrics, coverage) = self.font.rasterize_config(key);
+                let (metrics, coverage) = self.fonts[font_index].rasterize_config(key);
                 let coverage = Bytes::from(coverage);
                 (metrics, coverage)
             })
@@ -85,156 +234,3033 @@ impl<T> Vec2<T> where T: Clone + Copy {
     }
 }
 
-/// Create an overlay image for the rendered `text`.
+/// How the lines of a caption are centered within their region.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CaptionCentering {
+    /// Center each line based on the advance widths of its glyphs.
+    #[default]
+    Advance,
+    /// Center each line based on its display width in terminal columns (see `unicode-width`), so wide characters like
+    /// CJK ideographs count as two columns. Lines are pre-padded with spaces and the padded block is centered as a
+    /// whole. This only applies to explicit line breaks, not to lines created by word wrapping.
+    Columns,
+}
+
+impl CaptionCentering {
+    /// Prepare `text` for layout with this centering.
+    pub fn prepare<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Advance => Cow::Borrowed(text),
+            Self::Columns => {
+                let columns = text.lines().map(UnicodeWidthStr::width).max().unwrap_or(0);
+                let padded = text
+                    .lines()
+                    .map(|line| format!("{}{line}", " ".repeat((columns - line.width()) / 2)))
+                    .collect::<Vec<_>>();
+                Cow::Owned(padded.join("\n"))
+            }
+        }
+    }
+
+    fn horizontal_align(&self) -> layout::HorizontalAlign {
+        match self {
+            Self::Advance => layout::HorizontalAlign::Center,
+            Self::Columns => layout::HorizontalAlign::Left,
+        }
+    }
+}
+
+/// Options controlling how `generate_image` renders captions.
+#[derive(Clone, Default)]
+pub struct RenderOptions {
+    /// The image to caption, instead of the built-in Mocking Spongebob image.
+    pub base_image: Option<image::RgbaImage>,
+    pub centering: CaptionCentering,
+    /// The source of the font to render captions with, instead of the built-in Anton. Characters with a font in
+    /// `script_fonts` still use that font.
+    pub font: Option<Vec<u8>>,
+    /// Fonts to use instead of the default font for characters of a `ScriptClass`.
+    pub script_fonts: Vec<(ScriptClass, Font)>,
+    /// The source of the font for `ScriptClass::Emoji`, to draw its color bitmaps (from `sbix` or `CBDT` tables) over
+    /// the caption. Emoji without a color bitmap are drawn like any other glyph.
+    pub color_emoji: Option<Vec<u8>>,
+    /// Round the corners of the image to this radius in pixels, making the cut-off parts transparent.
+    pub corner_radius: Option<u32>,
+    /// Move the top caption down by this many pixels (or up, if negative).
+    pub top_offset: i32,
+    /// Move the bottom caption down by this many pixels (or up, if negative).
+    pub bottom_offset: i32,
+    /// Extra horizontal space between glyphs in pixels. Negative spacing pulls glyphs closer together.
+    pub letter_spacing: f32,
+    /// Space glyphs by their advances alone, without the font's kerning pairs (like "AV" tucked together).
+    pub no_kerning: bool,
+    /// Spread out the words of each line a caption wraps from, so the line reaches both sides of the region. The last
+    /// line of each paragraph is left as it is.
+    pub justify: bool,
+    /// Split words which do not fit at the end of a line with a hyphen, between the syllables `hyphenation_points`
+    /// finds with the patterns of this language, so lines are filled instead of the whole word wrapping. Only applies
+    /// when the caption is fit to a width.
+    pub hyphenate: Option<hypher::Lang>,
+    /// Move the lines a caption wraps onto right by this many pixels, as a hanging indent. Lines after a line break in
+    /// the caption are not indented.
+    pub wrap_indent: f32,
+    /// Scale the distance between the lines of a caption by this factor. `None` uses the font's own line spacing.
+    pub line_spacing: Option<f32>,
+    /// Move each glyph to a whole pixel of the final image before drawing it, so every glyph on a line sits on the
+    /// same baseline and (when supersampling) lines up with the pixel grid. `None` leaves glyphs where the layout puts
+    /// them.
+    pub pixel_snap: Option<PixelSnap>,
+    /// Render captions at this many times the image's resolution, then scale them down with a Lanczos filter, for
+    /// smoother edges. `None` renders at the image's resolution.
+    pub supersample: Option<u32>,
+    /// Shrink the font (down to `MIN_FONT_SCALE` of its size) until each caption wraps to at most this many lines, then
+    /// cut off the end of the caption with an ellipsis if it still does not fit.
+    pub max_lines: Option<usize>,
+    /// Make the caption text heavier by growing its glyphs by a pixel, for fonts without a bold weight.
+    pub bold: bool,
+    /// The color of the caption text, instead of `CAPTION_COLOR` (white). Template regions with their own color keep
+    /// it.
+    pub text_color: Option<Color>,
+    /// Draw the caption text and its outline with this opacity, from 0 (invisible) to 1 (opaque), for watermarks.
+    /// Color emoji are drawn opaque. `None` is opaque.
+    pub caption_opacity: Option<f32>,
+    /// Blur the image behind each caption region with a Gaussian blur of this sigma in pixels. Zero does not blur.
+    pub caption_bg_blur: f32,
+    /// Where captions sit vertically within their region. By default, the top caption hugs the top of the image and
+    /// the bottom caption hugs the bottom.
+    pub region_valign: Option<RegionAlign>,
+    /// Draw an outline around the caption text.
+    pub outline: Option<Outline>,
+    /// Which way caption lines read. Right-to-left captions are aligned to the right of their region unless a
+    /// `TemplateRegion` or `gravity` aligns them, and the runs of each line are put in the order the Unicode
+    /// Bidirectional Algorithm gives for a paragraph in that direction.
+    pub text_direction: TextDirection,
+    /// Draw only the outline of the caption text, in the text's color (not the outline's), leaving the insides of the
+    /// glyphs clear. The outline is `outline` if there is one, or `HOLLOW_TEXT_OUTLINE` if not.
+    pub hollow_text: bool,
+    /// Which dimensions of its region a caption is laid out to fit in.
+    pub fit: CaptionFit,
+    /// Mirror the image. Unless `flip_text` is set, only the base image is mirrored and the captions read normally.
+    pub flip: Option<Flip>,
+    /// Mirror the captions along with the image.
+    pub flip_text: bool,
+    /// Look for a face in the image and put the whole caption in the taller of the spaces above and below it, instead
+    /// of splitting it between the top and bottom. Captions go at the top and bottom as usual if no face is found.
+    pub smart_placement: bool,
+    /// Put the whole caption at this point of the image, instead of splitting it between the top and bottom. The
+    /// caption's region is a quarter of the image tall, like the top and bottom regions.
+    pub gravity: Option<Gravity>,
+    /// Keep a caption placed by `gravity` this many pixels away from the edges of the image.
+    pub gravity_margin: u32,
+    /// Draw a border around each caption region (`DEBUG_TOP_REGION_COLOR` and `DEBUG_BOTTOM_REGION_COLOR`), to show
+    /// where the captions can go.
+    pub debug_regions: bool,
+    /// Put the caption in these regions instead of at the top and bottom, like a meme template. Each line of the
+    /// caption goes in the next region, and the last region gets every line left over. Regions are cut off at the
+    /// edges of the image.
+    pub template: Vec<TemplateRegion>,
+}
+
+impl RenderOptions {
+    /// The factor captions are rendered larger by before being scaled down to the image, which is 1 without
+    /// `supersample`.
+    fn supersample_factor(&self) -> u32 {
+        self.supersample.unwrap_or(1).max(1)
+    }
+
+    /// The source of the default font: `font`, or the built-in Anton without it.
+    fn font_source(&self) -> &[u8] {
+        self.font.as_deref().unwrap_or(ANTON_REGULAR_SOURCE)
+    }
+}
+
+/// How `RenderOptions::pixel_snap` moves glyphs onto whole pixels.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PixelSnap {
+    /// Move glyphs up and left to the pixel they start in.
+    Floor,
+    /// Move glyphs to the nearest pixel.
+    Round,
+}
+
+impl PixelSnap {
+    /// Snap the position `value` to a multiple of `grid`.
+    fn apply(self, value: f32, grid: f32) -> f32 {
+        match self {
+            Self::Floor => (value / grid).floor() * grid,
+            Self::Round => (value / grid).round() * grid,
+        }
+    }
+}
+
+/// An outline drawn around caption text, made by dilating the text's mask.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Outline {
+    /// How far the outline extends past the text, in pixels.
+    pub width: u32,
+    pub style: OutlineStyle,
+    pub color: Color,
+}
+
+/// The shape the text's mask is dilated with, which shows at the corners of thick outlines.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum OutlineStyle {
+    /// Dilate with a square, giving sharp corners.
+    Square,
+    /// Dilate with a circle, giving rounded corners.
+    #[default]
+    Round,
+}
+
+/// The directions an image can be mirrored in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Flip {
+    /// Swap left and right.
+    Horizontal,
+    /// Swap top and bottom.
+    Vertical,
+    /// Swap both, which is the same as turning the image upside down.
+    Both,
+}
+
+impl Flip {
+    fn apply(self, image: &mut image::RgbaImage) {
+        if matches!(self, Self::Horizontal | Self::Both) {
+            image::imageops::flip_horizontal_in_place(image);
+        }
+        if matches!(self, Self::Vertical | Self::Both) {
+            image::imageops::flip_vertical_in_place(image);
+        }
+    }
+
+    /// Get the SVG transform which mirrors an image of `width` and `height` like `apply` does.
+    fn svg_transform(self, width: u32, height: u32) -> String {
+        match self {
+            Self::Horizontal => format!("matrix(-1 0 0 1 {width} 0)"),
+            Self::Vertical => format!("matrix(1 0 0 -1 0 {height})"),
+            Self::Both => format!("matrix(-1 0 0 -1 {width} {height})"),
+        }
+    }
+}
+
+/// How the lines of a caption in a `TemplateRegion` line up.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TextAlign {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+impl TextAlign {
+    fn horizontal_align(self) -> layout::HorizontalAlign {
+        match self {
+            Self::Left => layout::HorizontalAlign::Left,
+            Self::Center => layout::HorizontalAlign::Center,
+            Self::Right => layout::HorizontalAlign::Right,
+        }
+    }
+}
+
+/// Which way the lines of a caption read, for `RenderOptions::text_direction`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TextDirection {
+    /// Read right to left if the first letter with a direction (by the Unicode Bidirectional Algorithm) is from a
+    /// right-to-left script, like Arabic or Hebrew, and left to right if not.
+    #[default]
+    Auto,
+    Ltr,
+    Rtl,
+}
+
+impl TextDirection {
+    /// Check if `text` reads right to left in this direction.
+    pub fn is_rtl(self, text: &str) -> bool {
+        match self {
+            Self::Auto => unicode_bidi::get_base_direction_full(text) == unicode_bidi::Direction::Rtl,
+            Self::Ltr => false,
+            Self::Rtl => true,
+        }
+    }
+}
+
+/// A region of a template for `RenderOptions::template`, which a caption is laid out in like the top caption is in
+/// its region: starting at the top, wrapped to the width, and with a font half as tall as the region.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TemplateRegion {
+    pub rect: Rect,
+    pub align: TextAlign,
+    /// The color of the caption's text, instead of `RenderOptions::text_color`. SVG output draws every caption in
+    /// `RenderOptions::text_color`.
+    pub color: Option<Color>,
+}
+
+/// Which dimensions of its region a caption is laid out to fit in. A caption which is not held to the width of its
+/// region is not wrapped, and one which is not held to the height can grow past its region and over the image.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CaptionFit {
+    /// Fit both the width and the height of the region.
+    #[default]
+    Region,
+    /// Wrap to the width of the region, but let the caption grow as tall as it needs to.
+    Width,
+    /// Keep the caption to the height of the region, without wrapping it.
+    Height,
+}
+
+impl CaptionFit {
+    fn constrains_width(self) -> bool {
+        matches!(self, Self::Region | Self::Width)
+    }
+
+    fn constrains_height(self) -> bool {
+        matches!(self, Self::Region | Self::Height)
+    }
+}
+
+/// The vertical alignment of a caption within its region, which is a quarter of the image tall.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RegionAlign {
+    Top,
+    Center,
+    Bottom,
+}
+
+impl RegionAlign {
+    fn vertical_align(self) -> layout::VerticalAlign {
+        match self {
+            Self::Top => layout::VerticalAlign::Top,
+            Self::Center => layout::VerticalAlign::Middle,
+            Self::Bottom => layout::VerticalAlign::Bottom,
+        }
+    }
+}
+
+/// A point of the image a caption can be put at with `RenderOptions::gravity`: a corner, the middle of an edge, or the
+/// center.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Gravity {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Gravity {
+    fn horizontal_align(self) -> layout::HorizontalAlign {
+        match self {
+            Self::TopLeft | Self::Left | Self::BottomLeft => layout::HorizontalAlign::Left,
+            Self::Top | Self::Center | Self::Bottom => layout::HorizontalAlign::Center,
+            Self::TopRight | Self::Right | Self::BottomRight => layout::HorizontalAlign::Right,
+        }
+    }
+
+    /// Get the anchor of a caption region at this point, `margin` pixels in from the edges of the image.
+    fn anchor(self, margin: u32) -> Anchor {
+        match self {
+            Self::TopLeft | Self::Top | Self::TopRight => Anchor::Top(margin as i64),
+            Self::Left | Self::Center | Self::Right => Anchor::Middle,
+            Self::BottomLeft | Self::Bottom | Self::BottomRight => Anchor::Bottom(-(margin as i64)),
+        }
+    }
+}
+
+/// Where a caption region goes vertically. Regions in the middle or at the bottom of the image are placed by the
+/// height of their caption, so they hug it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Anchor {
+    /// The region starts at this row.
+    Top(i64),
+    /// The caption is centered between the top and bottom of the image.
+    Middle,
+    /// The caption ends this many rows below the bottom of the image (or above it, if negative).
+    Bottom(i64),
+}
+
+impl Anchor {
+    /// Get the row a caption region with this anchor starts at on an image `height` rows tall, for a caption which is
+    /// `text_height` rows tall.
+    fn y(self, height: u32, text_height: i64) -> i64 {
+        match self {
+            Self::Top(y) => y,
+            Self::Middle => (height as i64 - text_height) / 2,
+            Self::Bottom(offset) => height as i64 - text_height + offset,
+        }
+    }
+}
+
+/// A caption and the region of the image it is laid out in.
+struct CaptionRegion<'t> {
+    text: Cow<'t, str>,
+    /// The column the region starts at.
+    x: u32,
+    size: SizeDim,
+    anchor: Anchor,
+    /// The size of the caption's font before any shrinking to fit, which is half the height of a default region.
+    font_size: f32,
+    /// How the lines of the caption line up, instead of how `RenderOptions::gravity` and `RenderOptions::centering`
+    /// put them.
+    align: Option<TextAlign>,
+    /// The color of the caption's text.
+    color: Color,
+    /// The color of the region's border with `RenderOptions::debug_regions`.
+    debug_color: Color,
+}
+
+/// The color of caption text, unless `RenderOptions::text_color` or its `TemplateRegion` has another.
+const CAPTION_COLOR: Color = image::Rgba([255, 255, 255, 255]);
+/// The usual color of an `Outline`, to stand out from `CAPTION_COLOR`.
+pub const OUTLINE_COLOR: Color = image::Rgba([0, 0, 0, 255]);
+/// The colors `parse_color` knows by name, from CSS.
+const NAMED_COLORS: &[(&str, [u8; 3])] = &[
+    ("black", [0, 0, 0]),
+    ("white", [255, 255, 255]),
+    ("gray", [128, 128, 128]),
+    ("grey", [128, 128, 128]),
+    ("red", [255, 0, 0]),
+    ("orange", [255, 165, 0]),
+    ("yellow", [255, 255, 0]),
+    ("green", [0, 128, 0]),
+    ("lime", [0, 255, 0]),
+    ("cyan", [0, 255, 255]),
+    ("blue", [0, 0, 255]),
+    ("navy", [0, 0, 128]),
+    ("purple", [128, 0, 128]),
+    ("magenta", [255, 0, 255]),
+    ("pink", [255, 192, 203]),
+];
+
+/// The color of the border `RenderOptions::debug_regions` draws around the top caption region.
+pub const DEBUG_TOP_REGION_COLOR: Color = image::Rgba([255, 0, 0, 255]);
+/// The color of the border `RenderOptions::debug_regions` draws around the bottom caption region.
+pub const DEBUG_BOTTOM_REGION_COLOR: Color = image::Rgba([0, 0, 255, 255]);
+/// The thickness of the borders drawn by `RenderOptions::debug_regions`, in pixels.
+const DEBUG_REGION_BORDER: u32 = 2;
+
+/// The outline drawn for `RenderOptions::hollow_text` when there is no `RenderOptions::outline`.
+const HOLLOW_TEXT_OUTLINE: Outline = Outline { width: 2, style: OutlineStyle::Round, color: OUTLINE_COLOR };
+
+/// The smallest fraction of the normal font size that `RenderOptions::max_lines` shrinks the font to.
+const MIN_FONT_SCALE: f32 = 0.5;
+
+/// Load a font from its TrueType or OpenType `source`.
+pub fn load_font(source: &[u8]) -> Result<Font, String> {
+    Font::from_bytes(source, fontdue::FontSettings::default()).map_err(|e| e.to_owned())
+}
+
+/// Parse a color written as `#rrggbb`, `#rrggbbaa`, or the name of one of the `NAMED_COLORS` (in any case).
+pub fn parse_color(text: &str) -> Result<Color, String> {
+    let invalid = || format!("invalid color \"{text}\" (expected #rrggbb, #rrggbbaa, or a name like white)");
+    if let Some(&(_, [r, g, b])) = NAMED_COLORS.iter().find(|(name, _)| name.eq_ignore_ascii_case(text)) {
+        return Ok(image::Rgba([r, g, b, 255]));
+    }
+    let digits = text.strip_prefix('#').ok_or_else(invalid)?;
+    if !matches!(digits.len(), 6 | 8) || !digits.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut color = [255; 4];
+    for (channel, index) in color.iter_mut().zip((0..digits.len()).step_by(2)) {
+        *channel = u8::from_str_radix(&digits[index..index + 2], 16).map_err(|_| invalid())?;
+    }
+    Ok(image::Rgba(color))
+}
+
+/// Create an overlay image for the rendered text of `region`.
+///
+/// # Return
+/// The coverage mask of the text, along with an image of its color emoji if it has any.
 fn render_text(
     renderer: &GlyphGenerator,
     layout: &mut Layout,
-    font: &Font,
+    fonts: &CaptionFonts,
     font_size: f32,
-    size: SizeDim,
-    text: &str,
-) -> image::GrayImage {
+    region: &CaptionRegion,
+    options: &RenderOptions,
+) -> (image::GrayImage, Option<image::RgbaImage>) {
+    let factor = options.supersample_factor();
+    let font_size = font_size * factor as f32;
+    let size = SizeDim(region.size.width() * factor, region.size.height() * factor);
+
+    let (glyphs, size, offset_x) = layout_caption(fonts, layout, font_size, size, &region.text, region.align, options);
     let mut gray_image =
         image::GrayImage::from_vec(size.width(), size.height(), vec![0; size.area()]).unwrap();
 
-    let glyphs = get_filling_glyphs(size, &font, layout, font_size, text);
-
-    render_glyphs(glyphs, renderer, |x, y, coverage| {
+    render_glyphs(&glyphs, renderer, offset_x, size, |x, y, coverage| {
         gray_image.put_pixel(x, y, image::Luma([coverage]));
     });
+    if options.bold {
+        // Grow by a pixel of the final image, which is `factor` pixels here when supersampling
+        gray_image = dilate(&gray_image, Outline { width: factor, ..HOLLOW_TEXT_OUTLINE });
+    }
 
-    gray_image
+    let color = options
+        .color_emoji
+        .as_deref()
+        .and_then(|source| render_color_emoji(source, fonts, layout, &glyphs, size, offset_x));
+
+    if factor == 1 {
+        return (gray_image, color);
+    }
+    let (width, height) = (size.width() / factor, size.height().div_ceil(factor));
+    let filter = image::imageops::FilterType::Lanczos3;
+    (
+        image::imageops::resize(&gray_image, width, height, filter),
+        color.map(|color| image::imageops::resize(&color, width, height, filter)),
+    )
 }
 
-fn get_filling_glyphs<'a>(
-    size: SizeDim,
-    font: &Font,
-    layout: &'a mut Layout,
+/// Lay out the caption `text` to fill a region of `size`, shrinking and cutting it to `RenderOptions::max_lines`. The
+/// lines line up as `align` says, if it is given.
+///
+/// # Return
+/// The glyphs of the caption, the size of the area they are drawn in (which is taller than `size` when the caption is
+/// not held to the height of its region), and how far right the glyphs need to move to be centered.
+fn layout_caption(
+    fonts: &CaptionFonts,
+    layout: &mut Layout,
     font_size: f32,
+    size: SizeDim,
     text: &str,
-) -> &'a [layout::GlyphPosition] {
-    let max_width = size.width() as f32;
-    let max_height = size.height() as f32;
+    align: Option<TextAlign>,
+    options: &RenderOptions,
+) -> (Vec<layout::GlyphPosition>, SizeDim, i64) {
+    let (font_size, text) = match options.max_lines {
+        Some(_) => fit_caption(fonts, layout, font_size, size, text, align, options),
+        None => (font_size, Cow::Borrowed(text)),
+    };
+    let text = options.centering.prepare(&text);
+    let glyphs = get_filling_glyphs(size, fonts, layout, font_size, &text, align, options);
 
-    layout.reset(&layout::LayoutSettings {
-        max_height: Some(max_height),
-        max_width: Some(max_width),
-        horizontal_align: layout::HorizontalAlign::Center,
-        vertical_align: layout::VerticalAlign::Top,
-        wrap_style: layout::WrapStyle::Word,
-        wrap_hard_breaks: true,
-        ..Default::default()
-    });
-    layout.append(
-        &[font],
-        &layout::TextStyle {
-            text,
-            px: font_size,
-            font_index: 0,
-            user_data: (),
-        },
-    );
+    // A caption which is not held to the height of its region gets an area tall enough for all of it
+    let size = match options.fit.constrains_height() {
+        true => size,
+        false => size.map_height(|h| h.max(layout.height().ceil() as u32)),
+    };
 
-    layout.glyphs()
+    // Lines are already where the gravity or alignment puts them, so they are not centered as a block
+    let offset_x = match options.centering {
+        CaptionCentering::Columns if options.gravity.is_none() && align.is_none() => {
+            block_centering_offset(&glyphs, size)
+        }
+        _ => 0,
+    };
+    (glyphs, size, offset_x)
 }
 
-fn render_glyphs(
+/// Draw the color bitmaps of the emoji in `glyphs` from `source`, the font for `ScriptClass::Emoji`, on an image of
+/// `size`. This returns `None` if no emoji had a color bitmap.
+fn render_color_emoji(
+    source: &[u8],
+    fonts: &CaptionFonts,
+    layout: &Layout,
     glyphs: &[layout::GlyphPosition],
-    renderer: &GlyphGenerator,
-    mut put_pixel: impl FnMut(u32, u32, u8),
-) {
-    for glyph in glyphs.iter().filter(|x| !x.char_data.is_control()) {
-        let (ref metrics, ref bytes) = renderer.glyph(glyph.key);
+    size: SizeDim,
+    offset_x: i64,
+) -> Option<image::RgbaImage> {
+    let emoji_font = fonts.scripts.iter().position(|class| *class == ScriptClass::Emoji)? + 1;
+    let face = ttf_parser::Face::from_slice(source, 0).ok()?;
 
-        for x in 0..metrics.width {
-            for y in 0..metrics.height {
-                let coverage = bytes[x + y * metrics.width];
-                let x = x as u32 + glyph.x as u32;
-                let y = y as u32 + glyph.y as u32;
-                put_pixel(x, y, coverage);
-            }
+    let mut canvas = image::RgbaImage::new(size.width(), size.height());
+    let mut drawn = false;
+    for line in layout.lines().into_iter().flatten() {
+        let line_glyphs = glyphs.get(line.glyph_start..=line.glyph_end).unwrap_or_default();
+        for glyph in line_glyphs.iter().filter(|glyph| glyph.font_index == emoji_font) {
+            let px = glyph.key.px;
+            let Some(raster) = face.glyph_raster_image(ttf_parser::GlyphId(glyph.key.glyph_index), px.round() as u16)
+            else {
+                continue;
+            };
+            let Ok(bitmap) = image::load_from_memory_with_format(raster.data, image::ImageFormat::Png) else {
+                continue;
+            };
+
+            // Raster glyphs are drawn for a size in pixels per em, so scale them to the caption's font size
+            let scale = px / raster.pixels_per_em.max(1) as f32;
+            let width = (raster.width as f32 * scale).round().max(1.) as u32;
+            let height = (raster.height as f32 * scale).round().max(1.) as u32;
+            let filter = image::imageops::FilterType::Triangle;
+            let bitmap = image::imageops::resize(&bitmap.into_rgba8(), width, height, filter);
+
+            // The raster's `y` is the offset of its bottom edge above the baseline
+            let left = glyph.x as i64 + offset_x + (raster.x as f32 * scale).round() as i64;
+            let top = (line.baseline_y - (raster.y as f32 + raster.height as f32) * scale).round() as i64;
+            image::imageops::overlay(&mut canvas, &bitmap, left, top);
+            drawn = true;
         }
     }
+
+    drawn.then_some(canvas)
 }
 
-fn merge_image(
-    image: &mut image::RgbaImage,
-    mask: &image::GrayImage,
-    color: Color,
-    base_position: Vec2<u32>
-) {
-    for mask_x in 0..mask.width() {
-        for mask_y in 0..mask.height() {
-            let x = base_position.x() + mask_x;
-            let y = base_position.y() + mask_y;
+/// Find a font size and text for the caption `text` so it wraps to at most `RenderOptions::max_lines` lines. The font
+/// is shrunk in steps down to `MIN_FONT_SCALE` of `font_size`; if the caption still has too many lines at that size,
+/// words are cut from the end and replaced with an ellipsis.
+fn fit_caption<'t>(
+    fonts: &CaptionFonts,
+    layout: &mut Layout,
+    font_size: f32,
+    size: SizeDim,
+    text: &'t str,
+    align: Option<TextAlign>,
+    options: &RenderOptions,
+) -> (f32, Cow<'t, str>) {
+    let max_lines = options.max_lines.unwrap_or(usize::MAX).max(1);
+    let mut fits = |font_size: f32, text: &str| {
+        let text = options.centering.prepare(text);
+        get_filling_glyphs(
+            size,
+            fonts,
+            layout,
+            font_size,
+            &text,
+            align,
+            options,
+        );
+        layout.lines().map_or(0, Vec::len) <= max_lines
+    };
 
-            if (0..image.width()).contains(&x) && (0..image.height()).contains(&y) {
-                let mask = mask.get_pixel(mask_x, mask_y).0[0];
-                let mask = Color::from([color.0[0], color.0[1], color.0[2], mask]);
+    const STEPS: u32 = 10;
+    for step in 0..=STEPS {
+        let scaled = font_size * (1. - (1. - MIN_FONT_SCALE) * step as f32 / STEPS as f32);
+        if fits(scaled, text) {
+            return (scaled, Cow::Borrowed(text));
+        }
+    }
 
-                let mut pixel = image.get_pixel(x, y).clone();
-                pixel.blend(&mask);
+    let font_size = font_size * MIN_FONT_SCALE;
+    let ellipsis = if fonts.fonts[0].lookup_glyph_index('…') != 0 { "…" } else { "..." };
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    for keep in (1..words.len()).rev() {
+        let truncated = format!("{}{ellipsis}", words[..keep].join(" "));
+        if fits(font_size, &truncated) {
+            return (font_size, Cow::Owned(truncated));
+        }
+    }
 
-                image.put_pixel(x, y, pixel);
+    // Even a single word is too long, so there is nothing better to do than let it wrap
+    (font_size, Cow::Borrowed(text))
+}
+
+/// Get the horizontal offset needed to center the block of left-aligned `glyphs` within `size`.
+fn block_centering_offset(glyphs: &[layout::GlyphPosition], size: SizeDim) -> i64 {
+    let visible = glyphs.iter().filter(|glyph| !glyph.char_data.is_control());
+    let (left, right) = visible.fold((f32::MAX, f32::MIN), |(left, right), glyph| {
+        (left.min(glyph.x), right.max(glyph.x + glyph.width as f32))
+    });
+
+    if left > right {
+        0
+    } else {
+        ((size.width() as f32 - (right - left)) / 2. - left) as i64
+    }
+}
+
+fn get_filling_glyphs(
+    size: SizeDim,
+    fonts: &CaptionFonts,
+    layout: &mut Layout,
+    font_size: f32,
+    text: &str,
+    align: Option<TextAlign>,
+    options: &RenderOptions,
+) -> Vec<layout::GlyphPosition> {
+    let max_width = size.width() as f32;
+    let max_height = size.height() as f32;
+    let rtl = options.text_direction.is_rtl(text);
+    let horizontal_align = match (align, options.gravity) {
+        (Some(align), _) => align.horizontal_align(),
+        (None, Some(gravity)) => gravity.horizontal_align(),
+        (None, None) if rtl => layout::HorizontalAlign::Right,
+        (None, None) => options.centering.horizontal_align(),
+    };
+    let vertical_align = options.region_valign.map_or(layout::VerticalAlign::Top, RegionAlign::vertical_align);
+    // Everything is laid out at the supersampled size, so the spacing is scaled up along with the font
+    let letter_spacing = options.letter_spacing * options.supersample_factor() as f32;
+    let wrap_indent = options.wrap_indent * options.supersample_factor() as f32;
+    let fit = options.fit;
+    let line_height = options.line_spacing.unwrap_or(1.);
+
+    let lay_out = |layout: &mut Layout, wrap_width: f32| {
+        let text = match options.hyphenate {
+            Some(lang) if fit.constrains_width() => {
+                Cow::Owned(hyphenate(fonts, font_size, text, lang, wrap_width, max_width, letter_spacing))
+            }
+            _ => Cow::Borrowed(text),
+        };
+        layout.reset(&layout::LayoutSettings {
+            max_height: fit.constrains_height().then_some(max_height),
+            max_width: fit.constrains_width().then_some(wrap_width),
+            horizontal_align,
+            vertical_align,
+            line_height,
+            wrap_style: layout::WrapStyle::Word,
+            wrap_hard_breaks: true,
+            ..Default::default()
+        });
+        for (text, font_index) in fonts.runs(&text) {
+            layout.append(
+                &fonts.fonts,
+                &layout::TextStyle {
+                    text,
+                    px: font_size,
+                    font_index,
+                    user_data: (),
+                },
+            );
+        }
+    };
+
+    let mut layout_width = max_width;
+    lay_out(layout, layout_width);
+    if letter_spacing > 0. {
+        // fontdue does not know about the extra spacing, so wrap again with room left for the widest line's spacing
+        let extra = spaced_lines(layout)
+            .map(|(_, count)| count.saturating_sub(1) as f32 * letter_spacing)
+            .fold(0., f32::max);
+        if extra > 0. {
+            layout_width = (max_width - extra).max(font_size);
+            lay_out(layout, layout_width);
+        }
+    }
+
+    let mut glyphs = layout.glyphs().clone();
+    let centered = matches!(horizontal_align, layout::HorizontalAlign::Center);
+    if !options.no_kerning {
+        apply_kerning(layout, &mut glyphs, fonts, options.font_source(), horizontal_align);
+    }
+    if letter_spacing != 0. {
+        for (range, count) in spaced_lines(layout) {
+            // Keep centered lines centered in the full width, not the narrower width they were laid out in
+            let recenter = if centered && fit.constrains_width() {
+                (max_width - layout_width - count.saturating_sub(1) as f32 * letter_spacing) / 2.
+            } else {
+                0.
+            };
+            let visible = glyphs[range].iter_mut().filter(|glyph| !glyph.char_data.is_control());
+            for (index, glyph) in visible.enumerate() {
+                glyph.x += index as f32 * letter_spacing + recenter;
+            }
+        }
+    }
+
+    if centered && !fit.constrains_width() {
+        // Without a width to center in, fontdue puts every line at the left, so center them in the region here
+        for (range, _) in spaced_lines(layout) {
+            let (left, right) = glyphs[range.clone()]
+                .iter()
+                .filter(|glyph| !glyph.char_data.is_control())
+                .fold((f32::MAX, f32::MIN), |(left, right), glyph| {
+                    (left.min(glyph.x), right.max(glyph.x + glyph.width as f32))
+                });
+            if left <= right {
+                let shift = (max_width - (right - left)) / 2. - left;
+                glyphs[range].iter_mut().for_each(|glyph| glyph.x += shift);
             }
         }
     }
+
+    // Whether each line wraps onto the next, rather than ending its paragraph with a line break or the end of the
+    // caption
+    let lines = spaced_lines(layout).map(|(range, _)| range).collect::<Vec<_>>();
+    let wrapped = lines.iter().enumerate()
+        .map(|(index, line)| index + 1 < lines.len() && glyphs[line.end - 1].parent != '\n')
+        .collect::<Vec<_>>();
+
+    if options.justify && fit.constrains_width() {
+        for (line, _) in lines.iter().zip(&wrapped).filter(|(_, &wraps)| wraps) {
+            justify_line(&mut glyphs[line.clone()], max_width);
+        }
+    }
+
+    if wrap_indent != 0. {
+        for (line, _) in lines.iter().skip(1).zip(&wrapped).filter(|(_, &wraps)| wraps) {
+            glyphs[line.clone()].iter_mut().for_each(|glyph| glyph.x += wrap_indent);
+        }
+    }
+
+    for line in &lines {
+        reorder_line(&mut glyphs[line.clone()], fonts, rtl);
+    }
+
+    if let Some(snap) = options.pixel_snap {
+        // Snap to pixels of the final image, which are `factor` pixels apart when supersampling
+        let grid = options.supersample_factor() as f32;
+        for glyph in &mut glyphs {
+            glyph.x = snap.apply(glyph.x, grid);
+            glyph.y = snap.apply(glyph.y, grid);
+        }
+    }
+
+    glyphs
 }
 
-pub fn generate_image(
-    top_text: Option<&str>,
-    bottom_text: Option<&str>,
-) -> image::RgbaImage {
-    let mut image = image::load_from_memory_with_format(MOCKING_SPONGEBOB_SOURCE, image::ImageFormat::Jpeg)
-        .expect("Failed to load built-in image")
-        .into_rgba8();
+/// Move the glyphs of a line into their visual order by the Unicode Bidirectional Algorithm, for a paragraph which
+/// reads right to left if `rtl` is set, so runs of right-to-left text read right to left. fontdue lays every glyph out
+/// left to right in the order of the text, so the line keeps the space it was given and each glyph keeps the space up
+/// to the glyph after it, with the glyphs in their new order.
+fn reorder_line(glyphs: &mut [layout::GlyphPosition], fonts: &CaptionFonts, rtl: bool) {
+    let visible = (0..glyphs.len()).filter(|&index| !glyphs[index].char_data.is_control()).collect::<Vec<_>>();
+    let mut text = String::new();
+    let starts = visible.iter().map(|&index| {
+        let start = text.len();
+        text.push(glyphs[index].parent);
+        start
+    }).collect::<Vec<_>>();
 
-    let font = fontdue::Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default())
-        .expect("Failed to load built-in font");
-    let mut font_layout = fontdue::layout::Layout::new(fontdue::layout::CoordinateSystem::PositiveYDown);
+    let level = if rtl { unicode_bidi::Level::rtl() } else { unicode_bidi::Level::ltr() };
+    let bidi = unicode_bidi::ParagraphBidiInfo::new(&text, Some(level));
+    if !rtl && !bidi.has_rtl() {
+        return;
+    }
+    let (levels, runs) = bidi.visual_runs(0..text.len());
+    let order = runs.iter().flat_map(|run| {
+        let run_glyphs = starts.iter().enumerate().filter(|(_, start)| run.contains(start)).map(|(index, _)| index);
+        let run_glyphs = run_glyphs.collect::<Vec<_>>();
+        if levels[run.start].is_rtl() { run_glyphs.into_iter().rev().collect() } else { run_glyphs }
+    }).collect::<Vec<_>>();
+    if order.iter().copied().eq(0..visible.len()) {
+        return;
+    }
 
-    let rasterer = GlyphGenerator::with_capacity(&font, 1024);
+    // Where each glyph's pen position is, and how far it is to the next one
+    let metrics = |glyph: &layout::GlyphPosition| {
+        fonts.fonts[glyph.font_index].metrics_indexed(glyph.key.glyph_index, glyph.key.px)
+    };
+    let pens = visible.iter().map(|&index| glyphs[index].x - metrics(&glyphs[index]).bounds.xmin).collect::<Vec<_>>();
+    let advances = (0..visible.len()).map(|index| match pens.get(index + 1) {
+        Some(next) => next - pens[index],
+        None => metrics(&glyphs[visible[index]]).advance_width,
+    }).collect::<Vec<_>>();
 
-    let font_size = image.height() as f32 / 8.;
-    let size = SizeDim(image.width(), image.height());
-    let text_color = Color::from([255, 255, 255, 255]);
+    let mut pen = pens[0];
+    for index in order {
+        glyphs[visible[index]].x += pen - pens[index];
+        pen += advances[index];
+    }
+}
 
-    if let Some(text) = top_text {
-        let mask = render_text(
-            &rasterer,
-            &mut font_layout,
-            &font,
-            font_size,
-            size.map_height(|h| h / 4),
-            text,
-        );
+/// Move `glyphs` (the glyphs of `layout`) by the kerning between each pair of neighboring glyphs from the same font,
+/// since fontdue only lays glyphs out by their advances. Lines are moved back to keep their `horizontal_align`.
+///
+/// `default_source` is the source of the default font, whose `GPOS` kerning is used when it has no `kern` pair.
+fn apply_kerning(
+    layout: &Layout,
+    glyphs: &mut [layout::GlyphPosition],
+    fonts: &CaptionFonts,
+    default_source: &[u8],
+    horizontal_align: layout::HorizontalAlign,
+) {
+    // Fonts like the built-in one keep their kerning in `GPOS`, which fontdue does not read
+    let face = ttf_parser::Face::from_slice(default_source, 0).ok();
+    let kerning = |left: &layout::GlyphPosition, right: &layout::GlyphPosition| {
+        let font = fonts.fonts[left.font_index];
+        font.horizontal_kern_indexed(left.key.glyph_index, right.key.glyph_index, left.key.px).or_else(|| {
+            let face = face.as_ref().filter(|_| left.font_index == 0)?;
+            let kern = gpos_kerning(face, left.key.glyph_index, right.key.glyph_index)?;
+            Some(kern as f32 * left.key.px / face.units_per_em() as f32)
+        })
+    };
 
-        merge_image(
-            &mut image,
-            &mask,
-            text_color,
-            Vec2::new(0, 0),
-        );
+    for (range, _) in spaced_lines(layout) {
+        let mut shift = 0.;
+        let mut previous: Option<layout::GlyphPosition> = None;
+        for glyph in &mut glyphs[range.clone()] {
+            if let Some(previous) = previous.filter(|previous| previous.font_index == glyph.font_index) {
+                shift += kerning(&previous, glyph).unwrap_or(0.);
+            }
+            previous = Some(*glyph);
+            glyph.x += shift;
+        }
+
+        let realign = match horizontal_align {
+            layout::HorizontalAlign::Left => 0.,
+            layout::HorizontalAlign::Center => -shift / 2.,
+            layout::HorizontalAlign::Right => -shift,
+        };
+        glyphs[range].iter_mut().for_each(|glyph| glyph.x += realign);
     }
+}
 
-    if let Some(text) = bottom_text {
-        let mask = render_text(
-            &rasterer,
-            &mut font_layout,
-            &font,
-            font_size,
-            size.map_height(|h| h / 4),
-            text,
-        );
+/// Get the kerning between the glyphs `left` and `right` from the pair adjustments of the `kern` feature in `face`'s
+/// `GPOS` table, in font units.
+fn gpos_kerning(face: &ttf_parser::Face, left: u16, right: u16) -> Option<i16> {
+    use ttf_parser::{gpos::{PairAdjustment, PositioningSubtable}, GlyphId};
 
-        let text_y = image.height() - font_layout.height() as u32;
-        merge_image(
-            &mut image,
-            &mask,
-            text_color,
-            Vec2::new(0, text_y),
-        );
+    let gpos = face.tables().gpos?;
+    let feature = gpos.features.find(ttf_parser::Tag::from_bytes(b"kern"))?;
+    let (left, right) = (GlyphId(left), GlyphId(right));
+    feature.lookup_indices.into_iter().filter_map(|index| gpos.lookups.get(index)).find_map(|lookup| {
+        // The first subtable which covers the pair decides its kerning
+        lookup.subtables.into_iter::<PositioningSubtable>().find_map(|subtable| match subtable {
+            PositioningSubtable::Pair(PairAdjustment::Format1 { coverage, sets }) => {
+                let set = sets.get(coverage.get(left)?)?;
+                set.get(right).map(|(first, _)| first.x_advance)
+            }
+            PositioningSubtable::Pair(PairAdjustment::Format2 { coverage, classes, matrix }) => {
+                coverage.contains(left).then_some(())?;
+                matrix.get((classes.0.get(left), classes.1.get(right))).map(|(first, _)| first.x_advance)
+            }
+            _ => None,
+        })
+    })
+}
+
+/// Spread the words of a line of `glyphs` apart so the line runs from the left edge of a region `width` pixels wide to
+/// its right edge. A line with a single word is left as it is.
+fn justify_line(glyphs: &mut [layout::GlyphPosition], width: f32) {
+    let is_word = |glyph: &layout::GlyphPosition| !glyph.parent.is_whitespace();
+    let (Some(first), Some(last)) = (glyphs.iter().position(is_word), glyphs.iter().rposition(is_word)) else {
+        return;
+    };
+    let words = &mut glyphs[first..=last];
+
+    let gaps = words.windows(2).filter(|pair| !is_word(&pair[0]) && is_word(&pair[1])).count();
+    if gaps == 0 {
+        return;
     }
+    let (left, right) = words.iter().filter(|glyph| is_word(glyph)).fold((f32::MAX, f32::MIN), |(left, right), glyph| {
+        (left.min(glyph.x), right.max(glyph.x + glyph.width as f32))
+    });
+    let extra = (width - (right - left)) / gaps as f32;
 
-    image
+    let mut gap = 0;
+    let mut after_word = true;
+    for glyph in words {
+        if is_word(glyph) && !after_word {
+            gap += 1;
+        }
+        after_word = is_word(glyph);
+        glyph.x += gap as f32 * extra - left;
+    }
+}
+
+/// Get the places `word` can be split with a hyphen, as the byte index of the text after each split. These are the
+/// syllables of the TeX hyphenation patterns `hypher` embeds for `lang`, which also leave the few letters `lang` needs
+/// on either side of every split.
+fn hyphenation_points(word: &str, lang: hypher::Lang) -> impl Iterator<Item = usize> + '_ {
+    let ends = hypher::hyphenate(word, lang).scan(0, |end, syllable| {
+        *end += syllable.len();
+        Some(*end)
+    });
+    ends.filter(move |&end| end < word.len())
+}
+
+/// A grapheme cluster of text being hyphenated, with its width as fontdue measures it and the number of glyphs in it
+/// which letter spacing applies to.
+#[derive(Clone, Copy)]
+struct Cluster<'t> {
+    text: &'t str,
+    advance: f32,
+    glyphs: usize,
+}
+
+/// Put hyphens into `text` where fontdue would otherwise wrap a whole word onto the next line (or cut a word wider
+/// than `wrap_width` wherever it reaches the edge), so the word is split there instead. Words are split between the
+/// syllables of `lang` where they can be, and only ever between grapheme clusters.
+///
+/// Lines are measured the way fontdue fills them, with the rounded-up advance of each character including the spaces
+/// between words, against the `wrap_width` it is given. fontdue breaks lines after a hyphen, so the text it is given
+/// only needs the hyphens and not breaks. fontdue does not know about `letter_spacing`, so the part of a word before a
+/// hyphen must also fit in `region_width` once the spacing between its glyphs is added.
+fn hyphenate(
+    fonts: &CaptionFonts,
+    font_size: f32,
+    text: &str,
+    lang: hypher::Lang,
+    wrap_width: f32,
+    region_width: f32,
+    letter_spacing: f32,
+) -> String {
+    let advance = |font_index: usize, c: char| {
+        if c.is_control() { 0. } else { fonts.fonts[font_index].metrics(c, font_size).advance_width.ceil() }
+    };
+    let hyphen = Cluster { text: "-", advance: advance(0, '-'), glyphs: 1 };
+    let clusters = fonts
+        .runs(text)
+        .into_iter()
+        .flat_map(|(run, font_index)| {
+            run.graphemes(true).map(move |text| Cluster {
+                text,
+                advance: text.chars().map(|c| advance(font_index, c)).sum(),
+                glyphs: text.chars().filter(|c| !c.is_control()).count(),
+            })
+        })
+        .collect::<Vec<_>>();
+    let width = |clusters: &[Cluster]| clusters.iter().map(|cluster| cluster.advance).sum::<f32>();
+    let glyphs = |clusters: &[Cluster]| clusters.iter().map(|cluster| cluster.glyphs).sum::<usize>();
+    let is_whitespace = |cluster: &Cluster| cluster.text.starts_with(char::is_whitespace);
+
+    let mut hyphenated = String::with_capacity(text.len());
+    let (mut line_width, mut line_glyphs) = (0., 0);
+    let mut rest = &clusters[..];
+    while let Some(cluster) = rest.first() {
+        if is_whitespace(cluster) {
+            hyphenated.push_str(cluster.text);
+            (line_width, line_glyphs) = match cluster.text.contains('\n') {
+                true => (0., 0),
+                false => (line_width + cluster.advance, line_glyphs + cluster.glyphs),
+            };
+            rest = &rest[1..];
+            continue;
+        }
+
+        let (mut word, after) = rest.split_at(rest.iter().position(is_whitespace).unwrap_or(rest.len()));
+        rest = after;
+        while line_width + width(word) > wrap_width {
+            let starts = word.iter().scan(0, |start, cluster| {
+                Some(std::mem::replace(start, *start + cluster.text.len()))
+            });
+            let starts = starts.collect::<Vec<_>>();
+            let word_text = word.iter().map(|cluster| cluster.text).collect::<String>();
+            let fits = |at: &usize| {
+                let before = line_width + width(&word[..*at]) + hyphen.advance;
+                let spacing = (line_glyphs + glyphs(&word[..*at])) as f32 * letter_spacing;
+                before <= wrap_width && before + spacing <= region_width
+            };
+            // Splits inside a grapheme cluster are not at the start of any cluster, so they are skipped
+            let syllables = hyphenation_points(&word_text, lang)
+                .filter_map(|end| starts.iter().position(|&start| start == end));
+            let split = syllables.filter(fits).last().or_else(|| {
+                // A word too wide for a line of its own is split wherever it has to be, as fontdue would
+                (line_width == 0.).then(|| (1..word.len()).rfind(fits)).flatten()
+            });
+
+            match split {
+                Some(at) => {
+                    hyphenated.extend(word[..at].iter().map(|cluster| cluster.text));
+                    hyphenated.push_str(hyphen.text);
+                    word = &word[at..];
+                }
+                // Nothing fits on this line, so the whole word wraps onto the next one
+                None if line_width > 0. => {}
+                None => break,
+            }
+            (line_width, line_glyphs) = (0., 0);
+        }
+        hyphenated.extend(word.iter().map(|cluster| cluster.text));
+        line_width += width(word);
+        line_glyphs += glyphs(word);
+    }
+    hyphenated
+}
+
+/// Get the range of glyph indices of each line in `layout` along with the number of glyphs in it which letter spacing
+/// applies to.
+fn spaced_lines(layout: &Layout) -> impl Iterator<Item = (std::ops::Range<usize>, usize)> + '_ {
+    let glyphs = layout.glyphs();
+    layout.lines().into_iter().flatten().map(|line| {
+        let range = line.glyph_start..line.glyph_end + 1;
+        let count = glyphs[range.clone()].iter().filter(|glyph| !glyph.char_data.is_control()).count();
+        (range, count)
+    })
+}
+
+/// Call `put_pixel` with the coverage of each pixel of `glyphs`, moved right by `offset_x`. Pixels outside of `size`
+/// are skipped, since the layout can put glyphs past the edges of their region (or left of it, at negative positions).
+fn render_glyphs(
+    glyphs: &[layout::GlyphPosition],
+    renderer: &GlyphGenerator,
+    offset_x: i64,
+    size: SizeDim,
+    mut put_pixel: impl FnMut(u32, u32, u8),
+) {
+    for glyph in glyphs.iter().filter(|x| !x.char_data.is_control()) {
+        let (ref metrics, ref bytes) = renderer.glyph(glyph.font_index, glyph.key);
+        let (left, top) = (glyph.x.floor() as i64 + offset_x, glyph.y.floor() as i64);
+
+        for x in 0..metrics.width {
+            for y in 0..metrics.height {
+                let (image_x, image_y) = (left + x as i64, top + y as i64);
+                if (0..size.width() as i64).contains(&image_x) && (0..size.height() as i64).contains(&image_y) {
+                    put_pixel(image_x as u32, image_y as u32, bytes[x + y * metrics.width]);
+                }
+            }
+        }
+    }
+}
+
+fn merge_image(
+    image: &mut image::RgbaImage,
+    mask: &image::GrayImage,
+    color: Color,
+    base_position: Vec2<i64>
+) {
+    for mask_x in 0..mask.width() {
+        for mask_y in 0..mask.height() {
+            let x = base_position.x() + mask_x as i64;
+            let y = base_position.y() + mask_y as i64;
+
+            if (0..image.width() as i64).contains(&x) && (0..image.height() as i64).contains(&y) {
+                let (x, y) = (x as u32, y as u32);
+                // The mask's coverage is scaled by the color's own alpha
+                let mask = mask.get_pixel(mask_x, mask_y).0[0] as u16 * color.0[3] as u16 / 255;
+                let mask = Color::from([color.0[0], color.0[1], color.0[2], mask as u8]);
+
+                let mut pixel = *image.get_pixel(x, y);
+                pixel.blend(&mask);
+
+                image.put_pixel(x, y, pixel);
+            }
+        }
+    }
+}
+
+/// Where the caption text lands on the image.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum CaptionPlacement {
+    /// Split the caption between the top and bottom of the image. Multi-line captions are split by lines; a single
+    /// line is split by words.
+    #[default]
+    Split,
+    /// Put the entire caption at the top of the image.
+    TopOnly,
+    /// Put the entire caption at the bottom of the image.
+    BottomOnly,
+}
+
+impl CaptionPlacement {
+    /// Route `text` into the `(top_text, bottom_text)` pair that `generate_image` expects.
+    pub fn split(self, text: &str) -> (Option<String>, Option<String>) {
+        let text = text.trim();
+        if text.is_empty() {
+            return (None, None);
+        }
+
+        match self {
+            Self::TopOnly => (Some(text.to_owned()), None),
+            Self::BottomOnly => (None, Some(text.to_owned())),
+            Self::Split => {
+                let lines = text.lines().filter(|line| !line.trim().is_empty()).collect::<Vec<_>>();
+                let (parts, separator) = if lines.len() > 1 {
+                    (lines, "\n")
+                } else {
+                    (text.split_whitespace().collect(), " ")
+                };
+
+                if parts.len() < 2 {
+                    return (Some(text.to_owned()), None);
+                }
+
+                let (top, bottom) = parts.split_at(parts.len().div_ceil(2));
+                (Some(top.join(separator)), Some(bottom.join(separator)))
+            }
+        }
+    }
+}
+
+/// Grow `mask` by `outline.width` pixels in every direction. Each pixel becomes the strongest coverage of the pixels
+/// within the structuring element (a square or a circle) around it.
+fn dilate(mask: &image::GrayImage, outline: Outline) -> image::GrayImage {
+    // An element wider than the mask covers all of it from any pixel, so a larger one only costs more
+    let radius = (outline.width as i64).min(mask.width().max(mask.height()) as i64);
+    let offsets = (-radius..=radius)
+        .flat_map(|dy| (-radius..=radius).map(move |dx| (dx, dy)))
+        .filter(|(dx, dy)| match outline.style {
+            OutlineStyle::Square => true,
+            OutlineStyle::Round => dx * dx + dy * dy <= radius * radius,
+        })
+        .collect::<Vec<_>>();
+
+    let mut dilated = mask.clone();
+    let (width, height) = (mask.width() as i64, mask.height() as i64);
+    // Stamping the element onto covered pixels is much cheaper than a full sliding window, since most of a caption
+    // region is empty
+    for (x, y, pixel) in mask.enumerate_pixels().filter(|(_, _, pixel)| pixel.0[0] > 0) {
+        for (dx, dy) in offsets.iter() {
+            let (x, y) = (x as i64 + dx, y as i64 + dy);
+            if (0..width).contains(&x) && (0..height).contains(&y) {
+                let target = dilated.get_pixel_mut(x as u32, y as u32);
+                target.0[0] = target.0[0].max(pixel.0[0]);
+            }
+        }
+    }
+    dilated
+}
+
+/// Draw the caption `mask` onto `image` in `fill` at `position`, along with its background blur, outline, and `color`
+/// emoji.
+fn composite_caption(
+    image: &mut image::RgbaImage,
+    mask: &image::GrayImage,
+    fill: Color,
+    color: Option<&image::RgbaImage>,
+    position: Vec2<i64>,
+    options: &RenderOptions,
+) {
+    let opacity = options.caption_opacity.unwrap_or(1.).clamp(0., 1.);
+    let faded = |color: Color| {
+        let [r, g, b, a] = color.0;
+        Color::from([r, g, b, (a as f32 * opacity).round() as u8])
+    };
+    let fill = faded(fill);
+    blur_region(image, position.y(), mask.height(), options.caption_bg_blur);
+    if options.hollow_text {
+        // Only the ring the dilation adds around the text is drawn
+        let mut ring = dilate(mask, options.outline.unwrap_or(HOLLOW_TEXT_OUTLINE));
+        for (ring, text) in ring.pixels_mut().zip(mask.pixels()) {
+            ring.0[0] = ring.0[0].saturating_sub(text.0[0]);
+        }
+        merge_image(image, &ring, fill, position);
+    } else {
+        if let Some(outline) = options.outline {
+            merge_image(image, &dilate(mask, outline), faded(outline.color), position);
+        }
+        merge_image(image, mask, fill, position);
+    }
+    if let Some(color) = color {
+        image::imageops::overlay(image, color, position.x(), position.y());
+    }
+}
+
+/// Blur the full-width band of `image` starting at row `y` which is `height` rows tall, clipped to the image.
+fn blur_region(image: &mut image::RgbaImage, y: i64, height: u32, sigma: f32) {
+    if sigma <= 0. {
+        return;
+    }
+
+    let top = y.clamp(0, image.height() as i64) as u32;
+    let bottom = (y + height as i64).clamp(0, image.height() as i64) as u32;
+    if top == bottom {
+        return;
+    }
+
+    let region = image::imageops::crop_imm(image, 0, top, image.width(), bottom - top).to_image();
+    let blurred = image::imageops::blur(&region, sigma);
+    image::imageops::replace(image, &blurred, 0, top as i64);
+}
+
+/// Draw a `color` border just inside the region of `image` starting at column `x` and row `y` which is `size`. The
+/// region is clipped to the image first, so the border is always visible.
+fn draw_region_border(image: &mut image::RgbaImage, x: u32, y: i64, size: SizeDim, color: Color) {
+    let top = y.clamp(0, image.height() as i64) as u32;
+    let bottom = (y + size.height() as i64).clamp(0, image.height() as i64) as u32;
+    let left = x.min(image.width());
+    let right = x.saturating_add(size.width()).min(image.width());
+
+    for py in top..bottom {
+        for px in left..right {
+            let on_edge = py < top + DEBUG_REGION_BORDER
+                || py + DEBUG_REGION_BORDER >= bottom
+                || px < left + DEBUG_REGION_BORDER
+                || px + DEBUG_REGION_BORDER >= right;
+            if on_edge {
+                image.put_pixel(px, py, color);
+            }
+        }
+    }
+}
+
+/// Make the corners of `image` transparent outside of circles with the given `radius`. Pixels on the edge of a circle
+/// are partially transparent to avoid jagged edges.
+fn round_corners(image: &mut image::RgbaImage, radius: u32) {
+    let radius = radius.min(image.width() / 2).min(image.height() / 2);
+    let r = radius as f32;
+
+    for y in 0..radius {
+        for x in 0..radius {
+            // Distance from the center of this pixel to the center of the top-left corner's circle
+            let dx = r - (x as f32 + 0.5);
+            let dy = r - (y as f32 + 0.5);
+            let coverage = (r - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0., 1.);
+            if coverage >= 1. {
+                continue;
+            }
+
+            let mirrored_x = image.width() - 1 - x;
+            let mirrored_y = image.height() - 1 - y;
+            for (x, y) in [(x, y), (mirrored_x, y), (x, mirrored_y), (mirrored_x, mirrored_y)] {
+                let pixel = image.get_pixel_mut(x, y);
+                pixel.0[3] = (pixel.0[3] as f32 * coverage) as u8;
+            }
+        }
+    }
+}
+
+/// Check if `text` has nothing to draw, so its caption can be skipped instead of rendering a blank mask.
+pub fn is_empty_caption(text: &str) -> bool {
+    text.trim().is_empty()
+}
+
+/// Get the captions to draw on `image` and the regions they go in. Empty captions (see `is_empty_caption`) are left
+/// out.
+fn caption_regions<'t>(
+    top_text: Option<&'t str>,
+    bottom_text: Option<&'t str>,
+    image: &image::RgbaImage,
+    options: &RenderOptions,
+    timings: &mut Timings,
+) -> Vec<CaptionRegion<'t>> {
+    let top_text = top_text.filter(|text| !is_empty_caption(text));
+    let bottom_text = bottom_text.filter(|text| !is_empty_caption(text));
+    if top_text.is_none() && bottom_text.is_none() {
+        return Vec::new();
+    }
+
+    let (width, height) = image.dimensions();
+    let text_color = options.text_color.unwrap_or(CAPTION_COLOR);
+    // Tall, narrow images would fit only a few letters on a line at an eighth of their height
+    let font_size = (height as f32 / 8.).min(width as f32 / 4.);
+    let joined = || Cow::Owned(top_text.into_iter().chain(bottom_text).collect::<Vec<_>>().join("\n"));
+
+    if !options.template.is_empty() {
+        let joined = top_text.into_iter().chain(bottom_text).collect::<Vec<_>>().join("\n");
+        let mut lines = joined.lines();
+        let last = options.template.len() - 1;
+        return options
+            .template
+            .iter()
+            .enumerate()
+            .filter_map(|(index, region)| {
+                let text = match index == last {
+                    true => lines.by_ref().collect::<Vec<_>>().join("\n"),
+                    false => lines.next().unwrap_or_default().to_owned(),
+                };
+                // Regions are cut off at the edges of the image, and ones entirely outside of it are left out
+                let region_width = region.rect.width.min(width.saturating_sub(region.rect.x));
+                let region_height = region.rect.height.min(height.saturating_sub(region.rect.y));
+                if region_width == 0 || region_height == 0 {
+                    return None;
+                }
+                Some(CaptionRegion {
+                    text: Cow::Owned(text),
+                    x: region.rect.x,
+                    size: SizeDim(region_width, region_height),
+                    anchor: Anchor::Top(region.rect.y as i64),
+                    font_size: region_height as f32 / 2.,
+                    align: Some(region.align),
+                    color: region.color.unwrap_or(text_color),
+                    debug_color: match index % 2 {
+                        0 => DEBUG_TOP_REGION_COLOR,
+                        _ => DEBUG_BOTTOM_REGION_COLOR,
+                    },
+                })
+            })
+            .filter(|region| !is_empty_caption(&region.text))
+            .collect();
+    }
+
+    if let Some(gravity) = options.gravity {
+        let margin = options.gravity_margin.min(width.saturating_sub(1) / 2);
+        return vec![CaptionRegion {
+            text: joined(),
+            x: margin,
+            size: SizeDim(width - 2 * margin, height / 4),
+            anchor: gravity.anchor(margin),
+            font_size,
+            align: None,
+            color: text_color,
+            debug_color: DEBUG_TOP_REGION_COLOR,
+        }];
+    }
+
+    let face_band = match options.smart_placement {
+        true => timings.time(Stage::Composite, || face::detect_face(image))
+            .map(|face| face::caption_band(face, height))
+            .filter(|&(_, height)| height > 0),
+        false => None,
+    };
+    if let Some((band_y, band_height)) = face_band {
+        return vec![CaptionRegion {
+            text: joined(),
+            x: 0,
+            size: SizeDim(width, band_height),
+            anchor: Anchor::Top(band_y as i64 + options.top_offset as i64),
+            font_size,
+            align: None,
+            color: text_color,
+            debug_color: DEBUG_TOP_REGION_COLOR,
+        }];
+    }
+
+    let region = |text, anchor, debug_color| CaptionRegion {
+        text: Cow::Borrowed(text),
+        x: 0,
+        size: SizeDim(width, height / 4),
+        anchor,
+        font_size,
+        align: None,
+        color: text_color,
+        debug_color,
+    };
+    let top = top_text.map(|text| region(text, Anchor::Top(options.top_offset as i64), DEBUG_TOP_REGION_COLOR));
+    let bottom =
+        bottom_text.map(|text| region(text, Anchor::Bottom(options.bottom_offset as i64), DEBUG_BOTTOM_REGION_COLOR));
+    top.into_iter().chain(bottom).collect()
+}
+
+/// Get the height of the caption last laid out in `layout`, in pixels of the final image, for placing its region with
+/// `Anchor::y`. The region the caption was drawn in is `region_height` pixels tall.
+fn caption_height(layout: &Layout, region_height: u32, options: &RenderOptions) -> i64 {
+    // With a region alignment, the text is already placed within the region, so the whole region is placed
+    match options.region_valign {
+        Some(_) => region_height as i64,
+        None => (layout.height() / options.supersample_factor() as f32) as i64,
+    }
+}
+
+/// Get the image captions are drawn on: the `RenderOptions::base_image` or the built-in one, flipped unless the
+/// captions are flipped along with it.
+fn base_image(options: &RenderOptions, timings: &mut Timings) -> image::RgbaImage {
+    let mut image = match options.base_image {
+        Some(ref base_image) => base_image.clone(),
+        None => timings.time(Stage::ImageLoad, || {
+            image::load_from_memory_with_format(MOCKING_SPONGEBOB_SOURCE, image::ImageFormat::Jpeg)
+                .expect("Failed to load built-in image")
+                .into_rgba8()
+        }),
+    };
+    if let Some(flip) = options.flip.filter(|_| !options.flip_text) {
+        timings.time(Stage::Composite, || flip.apply(&mut image));
+    }
+    image
+}
+
+/// Draw the captions on the image as `options` describe.
+///
+/// # Errors
+/// If `RenderOptions::font` is not a font fontdue can read.
+pub fn generate_image(
+    top_text: Option<&str>,
+    bottom_text: Option<&str>,
+    options: &RenderOptions,
+    timings: &mut Timings,
+) -> Result<image::RgbaImage, String> {
+    let mut image = base_image(options, timings);
+
+    let font = timings.time(Stage::FontLoad, || load_font(options.font_source()))?;
+    let fonts = CaptionFonts::new(&font, &options.script_fonts);
+    let mut font_layout = fontdue::layout::Layout::new(fontdue::layout::CoordinateSystem::PositiveYDown);
+
+    let rasterer = GlyphGenerator::with_capacity(&fonts.fonts, 1024);
+
+    let mut drawn = Vec::new();
+
+    for region in caption_regions(top_text, bottom_text, &image, options, timings) {
+        let (mask, color) = timings.time(Stage::Rasterize, || {
+            render_text(&rasterer, &mut font_layout, &fonts, region.font_size, &region, options)
+        });
+
+        let y = region.anchor.y(image.height(), caption_height(&font_layout, mask.height(), options));
+        let position = Vec2::new(region.x as i64, y);
+        timings.time(Stage::Composite, || {
+            composite_caption(&mut image, &mask, region.color, color.as_ref(), position, options)
+        });
+        drawn.push((region.x, y, SizeDim(mask.width(), mask.height()), region.debug_color));
+    }
+
+    if options.debug_regions {
+        for (x, y, size, color) in drawn {
+            draw_region_border(&mut image, x, y, size, color);
+        }
+    }
+
+    if let Some(flip) = options.flip.filter(|_| options.flip_text) {
+        timings.time(Stage::Composite, || flip.apply(&mut image));
+    }
+
+    if let Some(radius) = options.corner_radius {
+        timings.time(Stage::Composite, || round_corners(&mut image, radius));
+    }
+
+    Ok(image)
+}
+
+/// Check if `format` can represent transparency.
+pub fn supports_alpha(format: image::ImageFormat) -> bool {
+    !matches!(format, image::ImageFormat::Jpeg | image::ImageFormat::Pnm)
+}
+
+/// Check if `format` is lossy, meaning its encoder takes a quality setting.
+pub fn is_lossy(format: image::ImageFormat) -> bool {
+    matches!(format, image::ImageFormat::Jpeg)
+}
+
+/// Encode `image` as `format`.
+///
+/// The `quality` is in `1..=100` and only affects lossy formats (see `is_lossy`); when it is `None`, the encoder's
+/// default is used. Formats without an alpha channel get the image without it.
+pub fn encode_image(
+    image: &image::RgbaImage,
+    format: image::ImageFormat,
+    quality: Option<u8>,
+) -> image::ImageResult<Vec<u8>> {
+    let mut encoded = io::Cursor::new(Vec::new());
+
+    match format {
+        image::ImageFormat::Jpeg => {
+            let rgb = image::DynamicImage::ImageRgba8(image.clone()).into_rgb8();
+            let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, quality.unwrap_or(75));
+            encoder.encode_image(&rgb)?;
+        }
+        _ => image.write_to(&mut encoded, format)?,
+    }
+
+    Ok(encoded.into_inner())
+}
+
+/// Lay out the captions like `generate_image` does, but draw them as SVG instead of pixels, so they stay sharp at any
+/// size. Glyphs from the default font are drawn as `<path>`s of their outlines. Glyphs from other fonts are drawn as
+/// `<text>`, since only the default font's outlines are at hand. The base image is embedded as a PNG, unless
+/// `embed_image` is false, which leaves just the captions.
+///
+/// Effects which only make sense for pixels are left out: `caption_bg_blur`, `supersample`, and the color bitmaps of
+/// `color_emoji` (emoji are left to the viewer's fonts instead).
+pub fn generate_svg(
+    top_text: Option<&str>,
+    bottom_text: Option<&str>,
+    options: &RenderOptions,
+    embed_image: bool,
+    timings: &mut Timings,
+) -> Result<String, String> {
+    let image = base_image(options, timings);
+    let (width, height) = image.dimensions();
+
+    let (font, outlines) = timings.time(Stage::FontLoad, || {
+        let font = load_font(options.font_source())?;
+        let outlines = ttf_parser::Face::from_slice(options.font_source(), 0).map_err(|e| e.to_string())?;
+        Ok::<_, String>((font, outlines))
+    })?;
+    let fonts = CaptionFonts::new(&font, &options.script_fonts);
+    let mut font_layout = fontdue::layout::Layout::new(fontdue::layout::CoordinateSystem::PositiveYDown);
+    // There are no pixels to supersample, so captions are laid out at the image's size
+    let options = &RenderOptions { supersample: None, ..options.clone() };
+
+    let mut glyph_elements = String::new();
+    let mut drawn = Vec::new();
+    for region in caption_regions(top_text, bottom_text, &image, options, timings) {
+        let (glyphs, area, offset_x) = timings.time(Stage::Rasterize, || {
+            layout_caption(&fonts, &mut font_layout, region.font_size, region.size, &region.text, region.align, options)
+        });
+        let y = region.anchor.y(height, caption_height(&font_layout, area.height(), options));
+        let offset_x = offset_x + region.x as i64;
+        drawn.push((region.x, y, area, region.debug_color));
+
+        for glyph in glyphs.iter().filter(|glyph| !glyph.char_data.is_control()) {
+            // Glyph positions are the top left of their bitmaps, but outlines are drawn from the glyph's origin
+            let bounds = fonts.fonts[glyph.font_index].metrics_indexed(glyph.key.glyph_index, glyph.key.px).bounds;
+            let x = glyph.x + offset_x as f32 - bounds.xmin;
+            let baseline = y as f32 + glyph.y + bounds.height + bounds.ymin;
+
+            if glyph.font_index == 0 {
+                let mut path = SvgPath {
+                    data: String::new(),
+                    scale: glyph.key.px / outlines.units_per_em() as f32,
+                    x,
+                    y: baseline,
+                };
+                outlines.outline_glyph(ttf_parser::GlyphId(glyph.key.glyph_index), &mut path);
+                if !path.data.is_empty() {
+                    glyph_elements.push_str(&format!("<path d=\"{}\"/>\n", path.data));
+                }
+            } else if !glyph.parent.is_whitespace() {
+                glyph_elements.push_str(&format!(
+                    "<text x=\"{x:.2}\" y=\"{baseline:.2}\" font-size=\"{:.2}\" font-family=\"sans-serif\">{}</text>\n",
+                    glyph.key.px,
+                    escape_xml(&glyph.parent.to_string()),
+                ));
+            }
+        }
+    }
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         viewBox=\"0 0 {width} {height}\">\n"
+    );
+    svg.push_str(&format!("<defs>\n<g id=\"captions\">\n{glyph_elements}</g>\n"));
+    if let Some(radius) = options.corner_radius {
+        let radius = radius.min(width / 2).min(height / 2);
+        svg.push_str(&format!(
+            "<clipPath id=\"corners\"><rect width=\"{width}\" height=\"{height}\" rx=\"{radius}\"/></clipPath>\n"
+        ));
+        svg.push_str("</defs>\n<g clip-path=\"url(#corners)\">\n");
+    } else {
+        svg.push_str("</defs>\n<g>\n");
+    }
+    match options.flip.filter(|_| options.flip_text) {
+        Some(flip) => svg.push_str(&format!("<g transform=\"{}\">\n", flip.svg_transform(width, height))),
+        None => svg.push_str("<g>\n"),
+    }
+
+    if embed_image {
+        let png = timings.time(Stage::Encode, || encode_image(&image, image::ImageFormat::Png, None))
+            .expect("Failed to encode base image");
+        svg.push_str(&format!(
+            "<image width=\"{width}\" height=\"{height}\" href=\"data:image/png;base64,{}\"/>\n",
+            base64(&png)
+        ));
+    }
+
+    let opacity = options.caption_opacity.unwrap_or(1.).clamp(0., 1.);
+    let text_color = svg_color(options.text_color.unwrap_or(CAPTION_COLOR));
+    let outline_use = |stroke: &str, width: u32, join: &str, fill: &str| {
+        format!(
+            "<use href=\"#captions\" fill=\"{fill}\" stroke=\"{stroke}\" stroke-width=\"{width}\" \
+             stroke-linejoin=\"{join}\" opacity=\"{opacity}\"/>\n"
+        )
+    };
+    let join = |outline: Outline| match outline.style {
+        OutlineStyle::Square => "miter",
+        OutlineStyle::Round => "round",
+    };
+    if options.hollow_text {
+        // A stroke is centered on the edge of the glyph rather than outside of it, so this only comes close to the
+        // ring `generate_image` draws
+        let outline = options.outline.unwrap_or(HOLLOW_TEXT_OUTLINE);
+        svg.push_str(&outline_use(&text_color, outline.width, join(outline), "none"));
+    } else {
+        if let Some(outline) = options.outline {
+            // Strokes are centered on the outline of the glyph, so half of the stroke is covered by the text
+            let color = svg_color(outline.color);
+            svg.push_str(&outline_use(&color, outline.width * 2, join(outline), &color));
+        }
+        match options.bold {
+            true => svg.push_str(&outline_use(&text_color, 2, "round", &text_color)),
+            false => svg.push_str(&format!("<use href=\"#captions\" fill=\"{text_color}\" opacity=\"{opacity}\"/>\n")),
+        }
+    }
+
+    if options.debug_regions {
+        // Like `draw_region_border`, the border is drawn just inside the region after clipping it to the image
+        let border = DEBUG_REGION_BORDER;
+        for (x, y, size, color) in drawn {
+            let top = y.clamp(0, height as i64) as u32;
+            let bottom = (y + size.height() as i64).clamp(0, height as i64) as u32;
+            let left = x.min(width);
+            let right = x.saturating_add(size.width()).min(width);
+            if bottom - top < border * 2 || right - left < border * 2 {
+                continue;
+            }
+            let [r, g, b, _] = color.0;
+            svg.push_str(&format!(
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#{r:02x}{g:02x}{b:02x}\" \
+                 stroke-width=\"{border}\"/>\n",
+                left as f32 + border as f32 / 2.,
+                top as f32 + border as f32 / 2.,
+                right - left - border,
+                bottom - top - border,
+            ));
+        }
+    }
+
+    svg.push_str("</g>\n</g>\n</svg>\n");
+    Ok(svg)
+}
+
+/// Writes the outline of a glyph as SVG path data, scaled from font units to pixels with its origin at `x` and `y`.
+struct SvgPath {
+    data: String,
+    scale: f32,
+    x: f32,
+    y: f32,
+}
+
+impl SvgPath {
+    fn point(&self, x: f32, y: f32) -> (f32, f32) {
+        // Font units have Y pointing up, but SVG has it pointing down
+        (self.x + x * self.scale, self.y - y * self.scale)
+    }
+}
+
+impl ttf_parser::OutlineBuilder for SvgPath {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.data.push_str(&format!("M{x:.2} {y:.2}"));
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let (x, y) = self.point(x, y);
+        self.data.push_str(&format!("L{x:.2} {y:.2}"));
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let ((x1, y1), (x, y)) = (self.point(x1, y1), self.point(x, y));
+        self.data.push_str(&format!("Q{x1:.2} {y1:.2} {x:.2} {y:.2}"));
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let ((x1, y1), (x2, y2), (x, y)) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.data.push_str(&format!("C{x1:.2} {y1:.2} {x2:.2} {y2:.2} {x:.2} {y:.2}"));
+    }
+
+    fn close(&mut self) {
+        self.data.push('Z');
+    }
+}
+
+/// Lay out the captions like `generate_svg` does, but describe where each glyph goes as JSON instead of drawing it, for
+/// debugging layouts or drawing captions some other way. The JSON has the `width` and `height` of the image and a
+/// `glyphs` array with the `char`, the `x` and `y` of the top left corner, and the `width` and `height` in pixels of
+/// each glyph. Control characters (like line breaks) take no space, so they are left out.
+///
+/// Positions are before `flip_text` mirrors the captions, and like SVG, captions are laid out without `supersample`.
+pub fn export_layout(
+    top_text: Option<&str>,
+    bottom_text: Option<&str>,
+    options: &RenderOptions,
+    timings: &mut Timings,
+) -> Result<String, String> {
+    let image = base_image(options, timings);
+    let (width, height) = image.dimensions();
+
+    let font = timings.time(Stage::FontLoad, || load_font(options.font_source()))?;
+    let fonts = CaptionFonts::new(&font, &options.script_fonts);
+    let mut font_layout = fontdue::layout::Layout::new(fontdue::layout::CoordinateSystem::PositiveYDown);
+    let options = &RenderOptions { supersample: None, ..options.clone() };
+
+    let mut glyphs_out = Vec::new();
+    for region in caption_regions(top_text, bottom_text, &image, options, timings) {
+        let (glyphs, area, offset_x) = timings.time(Stage::Rasterize, || {
+            layout_caption(&fonts, &mut font_layout, region.font_size, region.size, &region.text, region.align, options)
+        });
+        let y = region.anchor.y(height, caption_height(&font_layout, area.height(), options));
+        let offset_x = offset_x + region.x as i64;
+
+        for glyph in glyphs.iter().filter(|glyph| !glyph.char_data.is_control()) {
+            glyphs_out.push(LayoutGlyph {
+                char: glyph.parent,
+                x: round_hundredths(glyph.x + offset_x as f32),
+                y: round_hundredths(glyph.y + y as f32),
+                width: glyph.width,
+                height: glyph.height,
+            });
+        }
+    }
+
+    let layout = ExportedLayout { width, height, glyphs: glyphs_out };
+    serde_json::to_string(&layout).map(|json| json + "\n").map_err(|e| e.to_string())
+}
+
+/// The JSON written by `export_layout`.
+#[derive(Serialize)]
+struct ExportedLayout {
+    width: u32,
+    height: u32,
+    glyphs: Vec<LayoutGlyph>,
+}
+
+/// The placement of one glyph in an `ExportedLayout`.
+#[derive(Serialize)]
+struct LayoutGlyph {
+    char: char,
+    x: f32,
+    y: f32,
+    width: usize,
+    height: usize,
+}
+
+fn round_hundredths(value: f32) -> f32 {
+    (value * 100.).round() / 100.
+}
+
+/// Write `color` for SVG, as `#rrggbb`, or `#rrggbbaa` if it is not opaque.
+fn svg_color(color: Color) -> String {
+    let [r, g, b, a] = color.0;
+    match a {
+        255 => format!("#{r:02x}{g:02x}{b:02x}"),
+        _ => format!("#{r:02x}{g:02x}{b:02x}{a:02x}"),
+    }
+}
+
+/// Escape the characters of `text` which are special in XML.
+fn escape_xml(text: &str) -> Cow<'_, str> {
+    if !text.contains(['&', '<', '>', '"']) {
+        return Cow::Borrowed(text);
+    }
+    Cow::Owned(
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;"),
+    )
+}
+
+/// Encode `bytes` as Base64, for embedding images in SVG.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, &byte)| bits | (byte as u32) << (16 - 8 * index));
+        for index in 0..4 {
+            match index <= chunk.len() {
+                true => encoded.push(ALPHABET[(bits >> (18 - 6 * index)) as usize & 63] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_image() -> image::RgbaImage {
+        image::load_from_memory_with_format(MOCKING_SPONGEBOB_SOURCE, image::ImageFormat::Jpeg)
+            .unwrap()
+            .into_rgba8()
+    }
+
+    /// A region of `size` for `text` at the top left of the image, like the top caption's.
+    fn region(text: &str, size: SizeDim) -> CaptionRegion<'_> {
+        CaptionRegion {
+            text: Cow::Borrowed(text),
+            x: 0,
+            size,
+            anchor: Anchor::Top(0),
+            font_size: size.height() as f32 / 2.,
+            align: None,
+            color: CAPTION_COLOR,
+            debug_color: DEBUG_TOP_REGION_COLOR,
+        }
+    }
+
+    /// Check if any pixel in the rows `rows` differs between `lhs` and `rhs`.
+    fn rows_differ(lhs: &image::RgbaImage, rhs: &image::RgbaImage, rows: std::ops::Range<u32>) -> bool {
+        rows.flat_map(|y| (0..lhs.width()).map(move |x| (x, y)))
+            .any(|(x, y)| lhs.get_pixel(x, y) != rhs.get_pixel(x, y))
+    }
+
+    #[test]
+    fn caption_placement_split_words() {
+        assert_eq!(
+            CaptionPlacement::Split.split("TaCo TrUcK tImE"),
+            (Some("TaCo TrUcK".to_owned()), Some("tImE".to_owned()))
+        );
+        assert_eq!(CaptionPlacement::Split.split("TaCo"), (Some("TaCo".to_owned()), None));
+    }
+
+    #[test]
+    fn caption_placement_split_lines() {
+        assert_eq!(
+            CaptionPlacement::Split.split("TaCo TrUcK\ntImE\n"),
+            (Some("TaCo TrUcK".to_owned()), Some("tImE".to_owned()))
+        );
+    }
+
+    #[test]
+    fn caption_placement_only() {
+        assert_eq!(
+            CaptionPlacement::TopOnly.split("TaCo TrUcK"),
+            (Some("TaCo TrUcK".to_owned()), None)
+        );
+        assert_eq!(
+            CaptionPlacement::BottomOnly.split("TaCo TrUcK"),
+            (None, Some("TaCo TrUcK".to_owned()))
+        );
+    }
+
+    #[test]
+    fn caption_centering_columns_pads_lines() {
+        assert_eq!(CaptionCentering::Advance.prepare("漢字漢字\nab"), "漢字漢字\nab");
+        assert_eq!(CaptionCentering::Columns.prepare("漢字漢字\nab"), "漢字漢字\n   ab");
+    }
+
+    /// Get the horizontal offset of the first visible glyph of the second line relative to the first line.
+    fn second_line_offset(centering: CaptionCentering, font: &Font, text: &str) -> f32 {
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let text = centering.prepare(text);
+        let glyphs = get_filling_glyphs(
+            SizeDim(800, 400),
+            &CaptionFonts::new(font, &[]),
+            &mut layout,
+            40.,
+            &text,
+            None,
+            &RenderOptions {
+                centering,
+                ..Default::default()
+            },
+        );
+
+        let first_line = glyphs[0].x;
+        let second_line = glyphs
+            .iter()
+            .skip_while(|glyph| glyph.parent != '\n')
+            .find(|glyph| !glyph.char_data.is_whitespace())
+            .unwrap()
+            .x;
+        second_line - first_line
+    }
+
+    #[test]
+    fn caption_centering_columns_offsets_cjk() {
+        let font = Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let space = font.metrics(' ', 40.).advance_width;
+
+        let advance = second_line_offset(CaptionCentering::Advance, &font, "漢字漢字\nab");
+        let columns = second_line_offset(CaptionCentering::Columns, &font, "漢字漢字\nab");
+
+        assert!((advance - columns).abs() > 1., "advance={advance} columns={columns}");
+        assert!((columns - 3. * space).abs() < 4., "columns={columns} space={space}");
+    }
+
+    #[test]
+    fn caption_fonts_runs() {
+        let font = load_font(ANTON_REGULAR_SOURCE).unwrap();
+        let script_fonts = [(ScriptClass::Cjk, font.clone())];
+
+        let fonts = CaptionFonts::new(&font, &[]);
+        assert_eq!(fonts.runs("ab 漢字!"), vec![("ab 漢字!", 0)]);
+
+        let fonts = CaptionFonts::new(&font, &script_fonts);
+        assert_eq!(fonts.runs("ab 漢字! cd"), vec![("ab ", 0), ("漢字! ", 1), ("cd", 0)]);
+        assert_eq!(fonts.runs("1 漢"), vec![("1 漢", 1)]);
+        assert_eq!(fonts.runs(""), vec![]);
+    }
+
+    #[test]
+    fn caption_fonts_mixed_script_render() {
+        let font = load_font(ANTON_REGULAR_SOURCE).unwrap();
+        let script_fonts = [(ScriptClass::Latin, font.clone()), (ScriptClass::Cjk, font.clone())];
+        let fonts = CaptionFonts::new(&font, &script_fonts);
+        let renderer = GlyphGenerator::with_capacity(&fonts.fonts, 16);
+
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let glyphs = get_filling_glyphs(
+            SizeDim(800, 400),
+            &fonts,
+            &mut layout,
+            40.,
+            "ab漢字",
+            None,
+            &RenderOptions {
+                centering: CaptionCentering::Columns,
+                ..Default::default()
+            },
+        );
+
+        let font_indices = glyphs.iter().map(|glyph| glyph.font_index).collect::<Vec<_>>();
+        assert_eq!(font_indices, vec![1, 1, 2, 2]);
+        for glyph in glyphs {
+            let (_, coverage) = renderer.glyph(glyph.font_index, glyph.key);
+            assert!(coverage.iter().any(|x| *x > 0), "{:?} has no coverage", glyph.parent);
+        }
+    }
+
+    #[test]
+    fn bundled_fonts_lists_anton() {
+        let fonts = bundled_fonts();
+        let anton = fonts.iter().find(|font| font.family == "Anton").unwrap();
+        assert_eq!(anton.scripts, vec![ScriptClass::Latin]);
+    }
+
+    #[test]
+    fn top_only_populates_top_region() {
+        let base = base_image();
+        let quarter = base.height() / 4;
+
+        let (top, bottom) = CaptionPlacement::TopOnly.split("TaCo TrUcK");
+        let mut timings = Timings::new();
+        let image = generate_image(top.as_deref(), bottom.as_deref(), &RenderOptions::default(), &mut timings).unwrap();
+
+        assert!(rows_differ(&base, &image, 0..quarter));
+        assert!(!rows_differ(&base, &image, quarter..base.height()));
+    }
+
+    /// Get the bounding box of the pixels which differ between `base` and `image`, as the left, top, right, and bottom.
+    fn changed_bounds(base: &image::RgbaImage, image: &image::RgbaImage) -> (u32, u32, u32, u32) {
+        let changed = image.enumerate_pixels().filter(|(x, y, pixel)| base.get_pixel(*x, *y) != *pixel);
+        changed.fold((u32::MAX, u32::MAX, 0, 0), |(left, top, right, bottom), (x, y, _)| {
+            (left.min(x), top.min(y), right.max(x), bottom.max(y))
+        })
+    }
+
+    #[test]
+    fn gravity_places_caption_in_corner() {
+        let base = image::RgbaImage::from_pixel(400, 400, image::Rgba([40, 40, 40, 255]));
+        let render = |gravity| {
+            let options = RenderOptions {
+                base_image: Some(base.clone()),
+                gravity: Some(gravity),
+                gravity_margin: 10,
+                ..Default::default()
+            };
+            generate_image(Some("TaCo"), None, &options, &mut Timings::new()).unwrap()
+        };
+
+        let (left, top, right, bottom) = changed_bounds(&base, &render(Gravity::BottomRight));
+        assert!(left >= 200 && top >= 200, "caption starts at ({left}, {top})");
+        assert!(right < 390 && right > 370, "caption ends at column {right}");
+        assert!(bottom < 390 && bottom > 370, "caption ends at row {bottom}");
+
+        let (left, top, right, bottom) = changed_bounds(&base, &render(Gravity::TopLeft));
+        assert!(right < 200 && bottom < 200, "caption ends at ({right}, {bottom})");
+        assert!((10..30).contains(&left) && (10..30).contains(&top), "caption starts at ({left}, {top})");
+
+        let (left, top, right, bottom) = changed_bounds(&base, &render(Gravity::Center));
+        assert!((200 - left).abs_diff(right - 200) <= 4, "caption spans columns {left} to {right}");
+        assert!((200 - top).abs_diff(bottom - 200) <= 20, "caption spans rows {top} to {bottom}");
+    }
+
+    #[test]
+    fn template_renders_each_region() {
+        let base = image::RgbaImage::from_pixel(400, 400, image::Rgba([40, 40, 40, 255]));
+        let rect = |x, y, width, height| Rect { x, y, width, height };
+        let options = RenderOptions {
+            base_image: Some(base.clone()),
+            template: vec![
+                TemplateRegion {
+                    rect: rect(0, 0, 200, 100),
+                    align: TextAlign::Left,
+                    color: Some(image::Rgba([255, 0, 0, 255])),
+                },
+                // Without a color of its own, the region uses the text color
+                TemplateRegion { rect: rect(200, 250, 200, 100), align: TextAlign::Right, color: None },
+            ],
+            text_color: Some(image::Rgba([0, 255, 0, 255])),
+            ..Default::default()
+        };
+        let image = generate_image(Some("TaCo"), Some("TrUcK"), &options, &mut Timings::new()).unwrap();
+
+        // Each caption is drawn in its region's color, and nothing is drawn outside of the regions
+        let inside = |rect: Rect, x, y| {
+            (rect.x..rect.x + rect.width).contains(&x) && (rect.y..rect.bottom()).contains(&y)
+        };
+        let (mut red, mut green) = (0, 0);
+        for (x, y, pixel) in image.enumerate_pixels().filter(|&(x, y, pixel)| pixel != base.get_pixel(x, y)) {
+            let [r, g, _, _] = pixel.0;
+            if inside(options.template[0].rect, x, y) {
+                assert!(r > g, "{pixel:?} at ({x}, {y}) is not red");
+                red += 1;
+            } else {
+                assert!(inside(options.template[1].rect, x, y), "changed at ({x}, {y}) outside the captions");
+                assert!(g > r, "{pixel:?} at ({x}, {y}) is not green");
+                green += 1;
+            }
+        }
+        assert!(red > 0 && green > 0, "{red} red pixels, {green} green pixels");
+    }
+
+    #[test]
+    fn template_regions_cut_off_at_image() {
+        let base = image::RgbaImage::from_pixel(400, 400, image::Rgba([40, 40, 40, 255]));
+        let rect = |x, y, width, height| Rect { x, y, width, height };
+        let region = |rect| TemplateRegion { rect, align: TextAlign::Center, color: None };
+        let options = RenderOptions {
+            base_image: Some(base.clone()),
+            template: vec![region(rect(0, 300, u32::MAX, u32::MAX)), region(rect(500, 0, 100, 100))],
+            ..Default::default()
+        };
+
+        let regions = caption_regions(Some("TaCo"), Some("TrUcK"), &base, &options, &mut Timings::new());
+        assert_eq!(regions.len(), 1);
+        assert_eq!((regions[0].x, regions[0].size.width(), regions[0].size.height()), (0, 400, 100));
+
+        let image = generate_image(Some("TaCo"), Some("TrUcK"), &options, &mut Timings::new()).unwrap();
+        let changed = image.enumerate_pixels().filter(|&(x, y, pixel)| pixel != base.get_pixel(x, y));
+        assert!(changed.map(|(_, y, _)| y).min().is_some_and(|y| y >= 300));
+    }
+
+    #[test]
+    fn bottom_only_populates_bottom_region() {
+        let base = base_image();
+        let quarter = base.height() / 4;
+
+        let (top, bottom) = CaptionPlacement::BottomOnly.split("TaCo TrUcK");
+        let mut timings = Timings::new();
+        let image = generate_image(top.as_deref(), bottom.as_deref(), &RenderOptions::default(), &mut timings).unwrap();
+
+        assert!(!rows_differ(&base, &image, 0..(base.height() - quarter)));
+        assert!(rows_differ(&base, &image, (base.height() - quarter)..base.height()));
+    }
+
+    #[test]
+    fn round_corners_transparent() {
+        let mut image = image::RgbaImage::from_pixel(100, 60, image::Rgba([10, 20, 30, 255]));
+        round_corners(&mut image, 20);
+
+        for (x, y) in [(0, 0), (99, 0), (0, 59), (99, 59), (2, 2)] {
+            assert_eq!(image.get_pixel(x, y).0[3], 0, "({x}, {y}) should be transparent");
+        }
+        for (x, y) in [(50, 30), (50, 0), (0, 30), (20, 20), (10, 10)] {
+            assert_eq!(image.get_pixel(x, y).0, [10, 20, 30, 255], "({x}, {y}) should be opaque");
+        }
+    }
+
+    #[test]
+    fn encode_image_jpeg_quality() {
+        let base = base_image();
+
+        let low = encode_image(&base, image::ImageFormat::Jpeg, Some(10)).unwrap();
+        let high = encode_image(&base, image::ImageFormat::Jpeg, Some(90)).unwrap();
+
+        assert!(low.len() < high.len(), "low={} high={}", low.len(), high.len());
+        assert_eq!(image::guess_format(&low).unwrap(), image::ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn encode_image_webp_lossless() {
+        let mut base = base_image();
+        base.put_pixel(0, 0, image::Rgba([10, 20, 30, 40]));
+
+        let webp = encode_image(&base, image::ImageFormat::WebP, None).unwrap();
+        assert_eq!(image::guess_format(&webp).unwrap(), image::ImageFormat::WebP);
+        let decoded = image::load_from_memory_with_format(&webp, image::ImageFormat::WebP).unwrap();
+        assert!(decoded.to_rgba8() == base, "WebP should keep every pixel");
+    }
+
+    /// Get the first row of `image` which differs from `base`.
+    fn first_changed_row(base: &image::RgbaImage, image: &image::RgbaImage) -> u32 {
+        (0..base.height()).find(|&y| rows_differ(base, image, y..y + 1)).unwrap()
+    }
+
+    #[test]
+    fn top_offset_moves_caption_down() {
+        let base = base_image();
+        let render = |top_offset| {
+            let options = RenderOptions {
+                top_offset,
+                ..Default::default()
+            };
+            generate_image(Some("TaCo"), None, &options, &mut Timings::new()).unwrap()
+        };
+
+        let unmoved = first_changed_row(&base, &render(0));
+        assert_eq!(first_changed_row(&base, &render(40)), unmoved + 40);
+    }
+
+    #[test]
+    fn negative_offset_clips_caption() {
+        let base = base_image();
+        let options = RenderOptions {
+            top_offset: -10_000,
+            bottom_offset: 10_000,
+            ..Default::default()
+        };
+
+        let image = generate_image(Some("TaCo"), Some("TrUcK"), &options, &mut Timings::new()).unwrap();
+        assert!(!rows_differ(&base, &image, 0..base.height()));
+    }
+
+    /// Get the horizontal extent of the visible glyphs of `text` laid out with `letter_spacing`.
+    fn rendered_width(letter_spacing: f32, text: &str) -> f32 {
+        rendered_width_with(&RenderOptions { letter_spacing, ..Default::default() }, text)
+    }
+
+    /// Get the horizontal extent of the visible glyphs of `text` laid out with `options`.
+    fn rendered_width_with(options: &RenderOptions, text: &str) -> f32 {
+        let font = Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let glyphs = get_filling_glyphs(
+            SizeDim(800, 400),
+            &CaptionFonts::new(&font, &[]),
+            &mut layout,
+            40.,
+            text,
+            None,
+            options,
+        );
+
+        let left = glyphs.iter().map(|glyph| glyph.x).fold(f32::MAX, f32::min);
+        let right = glyphs.iter().map(|glyph| glyph.x + glyph.width as f32).fold(f32::MIN, f32::max);
+        right - left
+    }
+
+    #[test]
+    fn letter_spacing_changes_width() {
+        let unspaced = rendered_width(0., "TaCo");
+        assert!((rendered_width(10., "TaCo") - (unspaced + 30.)).abs() < 0.01);
+        assert!(rendered_width(-2., "TaCo") < unspaced);
+    }
+
+    #[test]
+    fn kerning_tucks_pairs_together() {
+        let kerned = rendered_width_with(&RenderOptions::default(), "AV");
+        let unkerned = rendered_width_with(&RenderOptions { no_kerning: true, ..Default::default() }, "AV");
+        assert!(kerned < unkerned - 0.5, "kerned {kerned}, unkerned {unkerned}");
+
+        // Pairs the font does not kern are spaced the same either way
+        let kerned = rendered_width_with(&RenderOptions::default(), "HH");
+        let unkerned = rendered_width_with(&RenderOptions { no_kerning: true, ..Default::default() }, "HH");
+        assert_eq!(kerned, unkerned);
+    }
+
+    #[test]
+    fn letter_spacing_wraps_within_region() {
+        let font = Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let glyphs = get_filling_glyphs(
+            SizeDim(400, 400),
+            &CaptionFonts::new(&font, &[]),
+            &mut layout,
+            40.,
+            "TaCo TrUcK TaCo TrUcK TaCo TrUcK",
+            None,
+            &RenderOptions {
+                letter_spacing: 8.,
+                ..Default::default()
+            },
+        );
+
+        for glyph in glyphs.iter().filter(|glyph| !glyph.char_data.is_whitespace()) {
+            assert!(glyph.x >= 0. && glyph.x + glyph.width as f32 <= 400., "{:?} is outside", glyph.parent);
+        }
+    }
+
+    #[test]
+    fn rtl_caption_aligns_right() {
+        let font = Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let text = "مرحبا 123";
+        let x_of = |glyphs: &[layout::GlyphPosition], c: char| glyphs.iter().find(|glyph| glyph.parent == c).unwrap().x;
+
+        // An Arabic caption reads right to left under `auto`, with its number still reading left to right
+        let options = RenderOptions::default();
+        let glyphs = get_filling_glyphs(SizeDim(400, 400), &fonts, &mut layout, 40., text, None, &options);
+        let right = glyphs.iter().map(|glyph| glyph.x + glyph.width as f32).fold(f32::MIN, f32::max);
+        assert!(right > 390., "the caption ends at {right}");
+        assert!(x_of(&glyphs, 'م') > x_of(&glyphs, 'ا'));
+        assert!(x_of(&glyphs, 'ا') > x_of(&glyphs, '3'));
+        assert!(x_of(&glyphs, '3') > x_of(&glyphs, '1'));
+
+        // Left to right keeps the caption centered, with the Arabic still reading right to left
+        let options = RenderOptions { text_direction: TextDirection::Ltr, ..Default::default() };
+        let glyphs = get_filling_glyphs(SizeDim(400, 400), &fonts, &mut layout, 40., "taco مرحبا", None, &options);
+        let right = glyphs.iter().map(|glyph| glyph.x + glyph.width as f32).fold(f32::MIN, f32::max);
+        assert!(right < 350., "the caption ends at {right}");
+        assert!(x_of(&glyphs, 't') < x_of(&glyphs, 'ا'));
+        assert!(x_of(&glyphs, 'ا') < x_of(&glyphs, 'م'));
+
+        // A left-to-right caption can be made to read right to left
+        let options = RenderOptions { text_direction: TextDirection::Rtl, ..Default::default() };
+        let glyphs = get_filling_glyphs(SizeDim(400, 400), &fonts, &mut layout, 40., "TaCo", None, &options);
+        assert!(x_of(&glyphs, 'T') < x_of(&glyphs, 'o'));
+        assert!(glyphs.iter().map(|glyph| glyph.x + glyph.width as f32).fold(f32::MIN, f32::max) > 390.);
+    }
+
+    #[test]
+    fn justify_reaches_both_edges() {
+        let font = Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let options = RenderOptions { justify: true, ..Default::default() };
+        let glyphs = get_filling_glyphs(
+            SizeDim(300, 400),
+            &fonts,
+            &mut layout,
+            40.,
+            "TaCo TrUcK tAcO tRuCk TaCo TrUcK tAcO tRuCk",
+            None,
+            &options,
+        );
+
+        let extents = spaced_lines(&layout)
+            .map(|(range, _)| {
+                glyphs[range].iter().filter(|glyph| !glyph.parent.is_whitespace()).fold(
+                    (f32::MAX, f32::MIN),
+                    |(left, right), glyph| (left.min(glyph.x), right.max(glyph.x + glyph.width as f32)),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert!(extents.len() > 2, "the caption did not wrap enough");
+
+        let (last, interior) = extents.split_last().unwrap();
+        for (left, right) in interior {
+            assert!(left.abs() < 0.01 && (right - 300.).abs() < 0.01, "line spans {left} to {right}");
+        }
+        // The last line is not stretched
+        assert!(last.1 - last.0 < 290., "the last line spans {} to {}", last.0, last.1);
+    }
+
+    #[test]
+    fn hyphenation_points_between_syllables() {
+        let points = |word: &str, lang| hyphenation_points(word, lang).collect::<Vec<_>>();
+        assert_eq!(points("TaCo", hypher::Lang::English), [0usize; 0]);
+        assert_eq!(points("hyphenation", hypher::Lang::English), [2, 6]);
+        assert_eq!(points("extensive", hypher::Lang::English), [2, 5]);
+        assert!(points("truck,", hypher::Lang::English).is_empty());
+        // Each language splits by its own patterns
+        assert_eq!(points("Silbentrennung", hypher::Lang::German), [3, 6, 10]);
+    }
+
+    #[test]
+    fn hyphenate_splits_long_words() {
+        let font = Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let text = "TaCo TrAnSmOgRiFiCaTiOn";
+        let mut lines = |hyphenate| {
+            let options = RenderOptions { hyphenate, ..Default::default() };
+            let glyphs = get_filling_glyphs(SizeDim(400, 400), &fonts, &mut layout, 40., text, None, &options);
+            spaced_lines(&layout)
+                .map(|(range, _)| glyphs[range].iter().map(|glyph| glyph.parent).collect::<String>())
+                .collect::<Vec<_>>()
+        };
+
+        // Without hyphenation, the long word wraps onto a line of its own
+        assert_eq!(lines(None), ["TaCo ", "TrAnSmOgRiFiCaTiOn"]);
+        let hyphenated = lines(Some(hypher::Lang::English));
+        assert_eq!(hyphenated.len(), 2);
+        assert!(hyphenated[0].starts_with("TaCo TrAn") && hyphenated[0].ends_with('-'), "{hyphenated:?}");
+        assert_eq!(hyphenated.concat().replace('-', ""), text);
+        for glyph in layout.glyphs() {
+            assert!(glyph.x + glyph.width as f32 <= 400., "{:?} is outside", glyph.parent);
+        }
+
+        // Letter spacing counts toward the width of the part before the hyphen
+        for letter_spacing in [4., 12.] {
+            let options = RenderOptions {
+                hyphenate: Some(hypher::Lang::English),
+                letter_spacing,
+                ..Default::default()
+            };
+            let glyphs = get_filling_glyphs(SizeDim(400, 400), &fonts, &mut layout, 40., text, None, &options);
+            assert!(glyphs.iter().any(|glyph| glyph.parent == '-'));
+            for glyph in glyphs.iter().filter(|glyph| !glyph.char_data.is_control()) {
+                assert!(glyph.x + glyph.width as f32 <= 400., "{:?} is outside with {letter_spacing}", glyph.parent);
+            }
+        }
+    }
+
+    #[test]
+    fn hyphenate_keeps_grapheme_clusters() {
+        let font = Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let text = "e\u{301}".repeat(40);
+
+        // The word is too wide for a line, so it is split wherever it has to be, but only between clusters
+        let hyphenated = hyphenate(&fonts, 40., &text, hypher::Lang::English, 300., 300., 0.);
+        assert!(hyphenated.contains('-'));
+        assert!(!hyphenated.contains("-\u{301}"), "{hyphenated:?}");
+        assert_eq!(hyphenated.replace('-', ""), text);
+    }
+
+    #[test]
+    fn wrap_indent_shifts_continuation_lines() {
+        let font = Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let mut line_starts = |text, wrap_indent| {
+            let options = RenderOptions { wrap_indent, ..Default::default() };
+            let glyphs = get_filling_glyphs(SizeDim(400, 400), &fonts, &mut layout, 40., text, None, &options);
+            spaced_lines(&layout).map(|(range, _)| glyphs[range.start].x).collect::<Vec<_>>()
+        };
+
+        let text = "TaCo TrUcK TaCo TrUcK TaCo TrUcK";
+        let plain = line_starts(text, 0.);
+        assert!(plain.len() > 1, "the caption did not wrap");
+        let indented = line_starts(text, 20.);
+        assert_eq!(indented[0], plain[0]);
+        for (indented, plain) in indented.iter().zip(&plain).skip(1) {
+            assert_eq!(*indented, plain + 20.);
+        }
+
+        // Lines after a line break are not continuations
+        assert_eq!(line_starts("TaCo\nTrUcK", 20.), line_starts("TaCo\nTrUcK", 0.));
+    }
+
+    /// Fit `text` into `max_lines` lines of an 800 pixel wide region with a 100 pixel font.
+    fn fit_caption_lines(text: &str, max_lines: usize) -> (f32, String, usize) {
+        let font = load_font(ANTON_REGULAR_SOURCE).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let options = RenderOptions { max_lines: Some(max_lines), ..Default::default() };
+
+        let (font_size, text) = fit_caption(&fonts, &mut layout, 100., SizeDim(800, 200), text, None, &options);
+        get_filling_glyphs(SizeDim(800, 200), &fonts, &mut layout, font_size, &text, None, &options);
+        (font_size, text.into_owned(), layout.lines().map_or(0, Vec::len))
+    }
+
+    #[test]
+    fn caption_max_lines_shrinks_font() {
+        let text = "TaCo TrUcK TiMe TaCo TrUcK TiMe TaCo TrUcK";
+        let (_, _, unfitted_lines) = fit_caption_lines(text, usize::MAX);
+        assert!(unfitted_lines > 2);
+
+        let (font_size, fitted, lines) = fit_caption_lines(text, 2);
+        assert!(font_size < 100.);
+        assert_eq!(fitted, text);
+        assert!(lines <= 2);
+    }
+
+    #[test]
+    fn caption_max_lines_truncates() {
+        let text = "TaCo TrUcK ".repeat(20);
+        let (font_size, fitted, lines) = fit_caption_lines(&text, 2);
+
+        assert_eq!(font_size, 100. * MIN_FONT_SCALE);
+        assert!(fitted.ends_with('…') || fitted.ends_with("..."), "{fitted}");
+        assert!(fitted.len() < text.len());
+        assert!(lines <= 2);
+    }
+
+    #[test]
+    fn caption_bg_blur_only_blurs_regions() {
+        let base = base_image();
+        let quarter = base.height() / 4;
+        let render = |caption_bg_blur| {
+            let options = RenderOptions {
+                caption_bg_blur,
+                ..Default::default()
+            };
+            generate_image(Some("TaCo"), Some("TrUcK"), &options, &mut Timings::new()).unwrap()
+        };
+
+        let sharp = render(0.);
+        let blurred = render(8.);
+        assert!(rows_differ(&sharp, &blurred, 0..quarter));
+        assert!(!rows_differ(&base, &blurred, quarter..(base.height() - quarter)));
+    }
+
+    /// Get the range of rows of the top region of `image` which differ from `base`.
+    fn changed_rows(base: &image::RgbaImage, image: &image::RgbaImage, region: std::ops::Range<u32>) -> (u32, u32) {
+        let changed = region.filter(|&y| rows_differ(base, image, y..y + 1)).collect::<Vec<_>>();
+        (changed[0], *changed.last().unwrap())
+    }
+
+    #[test]
+    fn region_valign_centers_caption() {
+        let base = base_image();
+        let quarter = base.height() / 4;
+        let render = |region_valign| {
+            let options = RenderOptions {
+                region_valign,
+                ..Default::default()
+            };
+            generate_image(Some("TaCo"), Some("TrUcK"), &options, &mut Timings::new()).unwrap()
+        };
+
+        let (top, bottom) = changed_rows(&base, &render(Some(RegionAlign::Center)), 0..quarter);
+        let middle = (top + bottom) / 2;
+        assert!(middle.abs_diff(quarter / 2) < quarter / 10, "caption is centered at {middle} of {quarter}");
+
+        let (default_top, _) = changed_rows(&base, &render(None), 0..quarter);
+        let (aligned_top, _) = changed_rows(&base, &render(Some(RegionAlign::Top)), 0..quarter);
+        assert_eq!(default_top, aligned_top);
+        assert!(top > default_top);
+
+        let region = (base.height() - quarter)..base.height();
+        let (_, default_bottom) = changed_rows(&base, &render(None), region.clone());
+        let (_, aligned_bottom) = changed_rows(&base, &render(Some(RegionAlign::Bottom)), region);
+        assert!(aligned_bottom.abs_diff(default_bottom) < quarter / 10);
+    }
+
+    #[test]
+    fn outline_style_corners() {
+        let mut mask = image::GrayImage::new(11, 11);
+        mask.put_pixel(5, 5, image::Luma([255]));
+
+        let square = dilate(&mask, Outline { width: 3, style: OutlineStyle::Square, color: OUTLINE_COLOR });
+        let round = dilate(&mask, Outline { width: 3, style: OutlineStyle::Round, color: OUTLINE_COLOR });
+
+        // Both reach straight out to the full width
+        for (x, y) in [(2, 5), (8, 5), (5, 2), (5, 8)] {
+            assert_eq!(square.get_pixel(x, y).0[0], 255);
+            assert_eq!(round.get_pixel(x, y).0[0], 255);
+        }
+        // Only the square reaches the corners
+        for (x, y) in [(2, 2), (8, 8), (2, 8), (8, 2)] {
+            assert_eq!(square.get_pixel(x, y).0[0], 255);
+            assert_eq!(round.get_pixel(x, y).0[0], 0);
+        }
+        assert_eq!(square.get_pixel(1, 5).0[0], 0);
+    }
+
+    /// Build a minimal font with one glyph for `emoji`, which only has an `sbix` color bitmap of `bitmap`.
+    fn sbix_font(emoji: char, bitmap: &image::RgbaImage) -> Vec<u8> {
+        let mut png = io::Cursor::new(Vec::new());
+        bitmap.write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+        let units_per_em = 128u16;
+
+        let mut head = Vec::new();
+        head.extend(0x00010000u32.to_be_bytes()); // version
+        head.extend([0; 8]); // font revision, checksum adjustment
+        head.extend(0x5F0F3CF5u32.to_be_bytes()); // magic number
+        head.extend(0u16.to_be_bytes()); // flags
+        head.extend(units_per_em.to_be_bytes());
+        head.extend([0; 16]); // created, modified
+        for bound in [0i16, -16, 128, 112] {
+            head.extend(bound.to_be_bytes());
+        }
+        head.extend([0; 10]); // mac style, lowest PPEM, direction hint, location format, glyph data format
+
+        let mut hhea = Vec::new();
+        hhea.extend(0x00010000u32.to_be_bytes());
+        for metric in [112i16, -16, 0] {
+            hhea.extend(metric.to_be_bytes()); // ascender, descender, line gap
+        }
+        hhea.extend([0; 24]);
+        hhea.extend(2u16.to_be_bytes()); // number of metrics
+
+        let mut maxp = 0x00005000u32.to_be_bytes().to_vec();
+        maxp.extend(2u16.to_be_bytes()); // .notdef and the emoji
+
+        let mut hmtx = Vec::new();
+        for advance in [units_per_em, units_per_em] {
+            hmtx.extend(advance.to_be_bytes());
+            hmtx.extend(0i16.to_be_bytes());
+        }
+
+        let mut cmap = Vec::new();
+        cmap.extend([0, 0, 0, 1]); // version, one table
+        cmap.extend([0, 3, 0, 10, 0, 0, 0, 12]); // Windows full Unicode, at offset 12
+        cmap.extend([0, 12, 0, 0, 0, 0, 0, 28, 0, 0, 0, 0, 0, 0, 0, 1]); // format 12 with one group
+        for value in [emoji as u32, emoji as u32, 1] {
+            cmap.extend(value.to_be_bytes());
+        }
+
+        // One strike at the bitmap's size, where glyph 0 is empty and glyph 1 is the PNG, drawn from the baseline
+        let mut sbix = Vec::new();
+        sbix.extend([0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 12]); // version, flags, one strike at offset 12
+        sbix.extend((bitmap.height() as u16).to_be_bytes());
+        sbix.extend(72u16.to_be_bytes());
+        for offset in [16u32, 16, 16 + 8 + png.len() as u32] {
+            sbix.extend(offset.to_be_bytes());
+        }
+        sbix.extend([0; 4]);
+        sbix.extend(b"png ");
+        sbix.extend(&png);
+
+        let tables = [
+            (b"cmap", cmap),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"maxp", maxp),
+            (b"sbix", sbix),
+        ];
+        let mut font = Vec::new();
+        font.extend(0x00010000u32.to_be_bytes());
+        font.extend((tables.len() as u16).to_be_bytes());
+        font.extend([0; 6]); // search range, entry selector, range shift
+        let mut offset = 12 + 16 * tables.len();
+        for (tag, data) in &tables {
+            font.extend(*tag);
+            font.extend([0; 4]); // checksum
+            font.extend((offset as u32).to_be_bytes());
+            font.extend((data.len() as u32).to_be_bytes());
+            offset += data.len().next_multiple_of(4);
+        }
+        for (_, data) in &tables {
+            font.extend(data);
+            font.resize(font.len().next_multiple_of(4), 0);
+        }
+        font
+    }
+
+    #[test]
+    fn color_emoji_are_drawn_in_color() {
+        let red = image::RgbaImage::from_pixel(32, 32, image::Rgba([255, 0, 0, 255]));
+        let source = sbix_font('😀', &red);
+        let options = RenderOptions {
+            script_fonts: vec![(ScriptClass::Emoji, load_font(&source).unwrap())],
+            color_emoji: Some(source),
+            ..Default::default()
+        };
+
+        let is_red = |pixel: &image::Rgba<u8>| pixel.0[0] > 200 && pixel.0[1] < 50 && pixel.0[2] < 50;
+        let base = base_image();
+        let quarter = base.height() / 4;
+        let red_pixels = |image: &image::RgbaImage| {
+            (0..quarter)
+                .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+                .filter(|&(x, y)| is_red(image.get_pixel(x, y)) && !is_red(base.get_pixel(x, y)))
+                .count()
+        };
+
+        let image = generate_image(Some("TaCo 😀"), None, &options, &mut Timings::new()).unwrap();
+        assert!(red_pixels(&image) > 1000, "only {} red pixels", red_pixels(&image));
+
+        // Without the color font source, the emoji glyph has no outline and nothing is drawn for it
+        let options = RenderOptions { color_emoji: None, ..options };
+        let image = generate_image(Some("TaCo 😀"), None, &options, &mut Timings::new()).unwrap();
+        assert_eq!(red_pixels(&image), 0);
+    }
+
+    #[test]
+    fn flip_mirrors_base_image() {
+        let base = base_image();
+        let (right, bottom) = (base.width() - 1, base.height() - 1);
+        let flipped = |flip| {
+            let options = RenderOptions { flip: Some(flip), ..Default::default() };
+            generate_image(None, None, &options, &mut Timings::new()).unwrap()
+        };
+
+        let horizontal = flipped(Flip::Horizontal);
+        for (x, y) in [(0, 0), (10, 500), (right, bottom), (base.width() / 3, 100)] {
+            assert_eq!(horizontal.get_pixel(x, y), base.get_pixel(right - x, y));
+        }
+        assert_eq!(flipped(Flip::Vertical).get_pixel(10, 0), base.get_pixel(10, bottom));
+        assert_eq!(flipped(Flip::Both).get_pixel(10, 0), base.get_pixel(right - 10, bottom));
+    }
+
+    #[test]
+    fn flip_text_mirrors_captions() {
+        let render = |flip_text| {
+            let options = RenderOptions { flip: Some(Flip::Horizontal), flip_text, ..Default::default() };
+            generate_image(Some("TaCo"), None, &options, &mut Timings::new()).unwrap()
+        };
+        let plain = generate_image(Some("TaCo"), None, &RenderOptions::default(), &mut Timings::new()).unwrap();
+
+        let (captions_kept, captions_flipped) = (render(false), render(true));
+        let right = plain.width() - 1;
+        let mirrored = |image: &image::RgbaImage| (0..plain.height() / 4).all(|y| {
+            (0..plain.width()).all(|x| image.get_pixel(x, y) == plain.get_pixel(right - x, y))
+        });
+        assert!(mirrored(&captions_flipped));
+        assert!(!mirrored(&captions_kept));
+    }
+
+    #[test]
+    fn line_spacing_spreads_lines() {
+        let font = fontdue::Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let mut line_gap = |line_spacing| {
+            let options = RenderOptions { line_spacing, ..Default::default() };
+            get_filling_glyphs(SizeDim(800, 400), &fonts, &mut layout, 50., "TaCo\nTrUcK", None, &options);
+            match layout.lines().unwrap()[..] {
+                [first, second] => second.baseline_y - first.baseline_y,
+                ref lines => panic!("expected two lines, got {}", lines.len()),
+            }
+        };
+
+        let default = line_gap(None);
+        assert_eq!(line_gap(Some(1.)), default);
+        let spread = line_gap(Some(1.5));
+        assert!((spread - default * 1.5).abs() < 1., "{spread} is not 1.5 times {default}");
+    }
+
+    #[test]
+    fn fit_width_grows_taller() {
+        let font = fontdue::Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let rasterer = GlyphGenerator::with_capacity(&fonts.fonts, 64);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let text = "tHe QuIcK bRoWn FoX jUmPs OvEr ThE lAzY dOg AnD tHeN sOmE mOrE";
+        let render = |layout: &mut Layout, fit| {
+            let options = RenderOptions { fit, ..Default::default() };
+            render_text(&rasterer, layout, &fonts, 50., &region(text, SizeDim(400, 100)), &options).0
+        };
+
+        let region = render(&mut layout, CaptionFit::Region);
+        assert_eq!(region.height(), 100);
+
+        let width = render(&mut layout, CaptionFit::Width);
+        assert!(width.height() > region.height(), "{} is not taller", width.height());
+        assert!((100..width.height()).any(|y| (0..400).any(|x| width.get_pixel(x, y).0[0] > 0)));
+
+        // Without wrapping, the caption is a single line which runs off both sides of the region
+        let height = render(&mut layout, CaptionFit::Height);
+        assert_eq!(height.height(), 100);
+        assert_eq!(layout.lines().map_or(0, Vec::len), 1);
+        let options = RenderOptions { fit: CaptionFit::Height, ..Default::default() };
+        let (glyphs, _, offset_x) = layout_caption(&fonts, &mut layout, 50., SizeDim(400, 100), text, None, &options);
+        let (first, last) = (glyphs.first().unwrap(), glyphs.last().unwrap());
+        assert!(first.x as i64 + offset_x < 0);
+        assert!(last.x as i64 + offset_x + last.width as i64 > 400);
+        // What is left of the line is still drawn right up to the left edge
+        assert!((0..100).any(|y| (0..4).any(|x| height.get_pixel(x, y).0[0] > 0)));
+        assert!((0..100).any(|y| height.get_pixel(399, y).0[0] > 0));
+    }
+
+    #[test]
+    fn pixel_snap_moves_glyphs_to_whole_pixels() {
+        let font = fontdue::Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let mut glyphs = |supersample, pixel_snap| {
+            let options = RenderOptions { supersample, pixel_snap, letter_spacing: 0.3, ..Default::default() };
+            let factor = options.supersample_factor();
+            let size = SizeDim(400 * factor, 100 * factor);
+            get_filling_glyphs(size, &fonts, &mut layout, 37.3 * factor as f32, "TaCo TrUcK", None, &options)
+        };
+
+        let unsnapped = glyphs(Some(4), None);
+        assert!(unsnapped.iter().any(|glyph| glyph.x.fract() != 0. || glyph.y.fract() != 0.));
+        for snap in [PixelSnap::Floor, PixelSnap::Round] {
+            // Positions are whole pixels of the final image, which are 4 pixels apart when supersampling by 4
+            let snapped = glyphs(Some(4), Some(snap));
+            for (glyph, original) in snapped.iter().zip(&unsnapped) {
+                assert_eq!(glyph.x % 4., 0., "{:?} is at {}", glyph.parent, glyph.x);
+                assert_eq!(glyph.y % 4., 0., "{:?} is at {}", glyph.parent, glyph.y);
+                assert!((glyph.x - original.x).abs() < 4. && (glyph.y - original.y).abs() < 4.);
+            }
+            for glyph in glyphs(None, Some(snap)) {
+                assert_eq!((glyph.x.fract(), glyph.y.fract()), (0., 0.));
+            }
+        }
+    }
+
+    #[test]
+    fn pixel_snap_floor_matches_default_render() {
+        // Glyphs are drawn at the pixel they start in, so flooring them first draws the same thing
+        let font = fontdue::Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let rasterer = GlyphGenerator::with_capacity(&fonts.fonts, 64);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let mut render = |pixel_snap| {
+            let options = RenderOptions { pixel_snap, letter_spacing: 0.3, ..Default::default() };
+            render_text(&rasterer, &mut layout, &fonts, 37.3, &region("TaCo TrUcK", SizeDim(400, 100)), &options).0
+        };
+        assert_eq!(render(None), render(Some(PixelSnap::Floor)));
+    }
+
+    #[test]
+    fn render_glyphs_skips_pixels_outside() {
+        let font = fontdue::Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let rasterer = GlyphGenerator::with_capacity(&fonts.fonts, 64);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        layout.append(&fonts.fonts, &layout::TextStyle::new("TaCo TrUcK", 40., 0));
+
+        // Glyphs left of the region (at negative positions) and past its right and bottom edges are cut off
+        let mut whole = Vec::new();
+        render_glyphs(layout.glyphs(), &rasterer, 0, SizeDim(1000, 100), |x, y, _| whole.push((x as i64 - 30, y)));
+        let mut cut = Vec::new();
+        render_glyphs(layout.glyphs(), &rasterer, -30, SizeDim(40, 20), |x, y, _| cut.push((x as i64, y)));
+        whole.retain(|&(x, y)| (0..40).contains(&x) && y < 20);
+        assert!(!cut.is_empty());
+        assert_eq!(cut, whole);
+
+        // Long captions on tiny images are cut off the same way
+        for letter_spacing in [0., -20.] {
+            let options = RenderOptions {
+                base_image: Some(image::RgbaImage::new(6, 5)),
+                letter_spacing,
+                wrap_indent: -10.,
+                fit: CaptionFit::Width,
+                ..Default::default()
+            };
+            let caption = "TaCo TrUcK ".repeat(20);
+            let image = generate_image(Some(&caption), Some(&caption), &options, &mut Timings::new()).unwrap();
+            assert_eq!(image.dimensions(), (6, 5));
+        }
+    }
+
+    #[test]
+    fn supersample_smooths_edges() {
+        let font = fontdue::Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let rasterer = GlyphGenerator::with_capacity(&fonts.fonts, 64);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let mut render = |supersample| {
+            let options = RenderOptions { supersample, ..Default::default() };
+            render_text(&rasterer, &mut layout, &fonts, 50., &region("TaCo TrUcK", SizeDim(400, 100)), &options).0
+        };
+        let edge_pixels = |mask: &image::GrayImage| {
+            mask.pixels().filter(|pixel| (1..255).contains(&pixel.0[0])).count()
+        };
+
+        let plain = render(None);
+        let supersampled = render(Some(2));
+        assert_eq!(supersampled.dimensions(), plain.dimensions());
+        assert!(
+            edge_pixels(&supersampled) > edge_pixels(&plain),
+            "{} edge pixels supersampled, {} without",
+            edge_pixels(&supersampled),
+            edge_pixels(&plain)
+        );
+        assert_eq!(render(Some(1)), plain);
+    }
+
+    #[test]
+    fn bold_covers_more() {
+        let font = fontdue::Font::from_bytes(ANTON_REGULAR_SOURCE, fontdue::FontSettings::default()).unwrap();
+        let fonts = CaptionFonts::new(&font, &[]);
+        let rasterer = GlyphGenerator::with_capacity(&fonts.fonts, 64);
+        let mut layout = Layout::new(layout::CoordinateSystem::PositiveYDown);
+        let mut covered = |bold| {
+            let options = RenderOptions { bold, ..Default::default() };
+            let region = region("TaCo TrUcK", SizeDim(400, 100));
+            let (mask, _) = render_text(&rasterer, &mut layout, &fonts, 50., &region, &options);
+            mask.pixels().filter(|pixel| pixel.0[0] > 0).count()
+        };
+
+        let (regular, bold) = (covered(false), covered(true));
+        assert!(bold > regular, "{bold} covered pixels bold, {regular} regular");
+    }
+
+    #[test]
+    fn caption_opacity_blends() {
+        let mask = image::GrayImage::from_pixel(4, 1, image::Luma([255]));
+        let composite = |background, caption_opacity| {
+            let mut image = image::RgbaImage::from_pixel(4, 1, image::Rgba(background));
+            let options = RenderOptions { caption_opacity, ..Default::default() };
+            composite_caption(&mut image, &mask, CAPTION_COLOR, None, Vec2::new(0, 0), &options);
+            image.get_pixel(0, 0).0
+        };
+        let is_half = |value: u8| (value as i32 - 128).abs() <= 1;
+
+        let black = [0, 0, 0, 255];
+        assert_eq!(composite(black, None), [255, 255, 255, 255]);
+        assert_eq!(composite(black, Some(1.)), [255, 255, 255, 255]);
+        assert_eq!(composite(black, Some(0.)), black);
+        let [red, green, blue, alpha] = composite(black, Some(0.5));
+        assert!(is_half(red), "{red} is not half of 255");
+        assert_eq!((green, blue), (red, red));
+        assert!(alpha >= 254, "blending over an opaque pixel left it {alpha} opaque");
+
+        let [red, green, blue, _] = composite([255, 0, 0, 255], Some(0.5));
+        assert_eq!(red, 255);
+        assert!(is_half(green) && is_half(blue), "({green}, {blue}) are not half of 255");
+    }
+
+    #[test]
+    fn hollow_text_draws_only_the_ring() {
+        let mask = image::GrayImage::from_fn(20, 20, |x, y| {
+            image::Luma([if (5..15).contains(&x) && (5..15).contains(&y) { 255 } else { 0 }])
+        });
+        let black = image::Rgba([0, 0, 0, 255]);
+        let white = image::Rgba([255, 255, 255, 255]);
+        let mut image = image::RgbaImage::from_pixel(20, 20, black);
+        let options = RenderOptions { hollow_text: true, ..Default::default() };
+        composite_caption(&mut image, &mask, CAPTION_COLOR, None, Vec2::new(0, 0), &options);
+
+        // The inside of the "glyph" shows the image behind it, and the ring around it is the text's color
+        assert_eq!(*image.get_pixel(10, 10), black);
+        assert_eq!(*image.get_pixel(5, 10), black);
+        assert_eq!(*image.get_pixel(4, 10), white);
+        assert_eq!(*image.get_pixel(10, 3), white);
+        assert_eq!(*image.get_pixel(10, 17), black);
+        assert_eq!(*image.get_pixel(0, 0), black);
+
+        // The ring is as wide as the outline, when there is one
+        let mut image = image::RgbaImage::from_pixel(20, 20, black);
+        let options = RenderOptions {
+            hollow_text: true,
+            outline: Some(Outline { width: 4, style: OutlineStyle::Square, color: OUTLINE_COLOR }),
+            ..Default::default()
+        };
+        composite_caption(&mut image, &mask, CAPTION_COLOR, None, Vec2::new(0, 0), &options);
+        assert_eq!(*image.get_pixel(10, 10), black);
+        assert_eq!(*image.get_pixel(1, 1), white);
+        assert_eq!(*image.get_pixel(0, 0), black);
+    }
+
+    #[test]
+    fn caption_colors() {
+        let mask = image::GrayImage::from_fn(20, 20, |x, y| {
+            image::Luma([if (5..15).contains(&x) && (5..15).contains(&y) { 255 } else { 0 }])
+        });
+        let gray = image::Rgba([128, 128, 128, 255]);
+        let yellow = parse_color("yellow").unwrap();
+        let red = parse_color("#ff0000").unwrap();
+        let mut image = image::RgbaImage::from_pixel(20, 20, gray);
+        let outline = Outline { width: 2, style: OutlineStyle::Square, color: red };
+        let options = RenderOptions { outline: Some(outline), ..Default::default() };
+        composite_caption(&mut image, &mask, yellow, None, Vec2::new(0, 0), &options);
+        assert_eq!(*image.get_pixel(10, 10), yellow);
+        assert_eq!(*image.get_pixel(4, 10), red);
+        assert_eq!(*image.get_pixel(0, 0), gray);
+
+        // SVG draws the same colors
+        let options = RenderOptions { text_color: Some(yellow), outline: Some(outline), ..Default::default() };
+        let svg = generate_svg(Some("TaCo"), None, &options, false, &mut Timings::new()).unwrap();
+        assert!(svg.contains("fill=\"#ff0000\" stroke=\"#ff0000\""), "{svg}");
+        assert!(svg.contains("fill=\"#ffff00\""), "{svg}");
+    }
+
+    #[test]
+    fn parse_colors() {
+        assert_eq!(parse_color("White"), Ok(image::Rgba([255, 255, 255, 255])));
+        assert_eq!(parse_color("#1a2B3c"), Ok(image::Rgba([0x1a, 0x2b, 0x3c, 255])));
+        assert_eq!(parse_color("#1a2b3c80"), Ok(image::Rgba([0x1a, 0x2b, 0x3c, 0x80])));
+        for invalid in ["", "taco", "#fff", "1a2b3c", "#1a2b3g", "#1a€2b3"] {
+            assert!(parse_color(invalid).is_err(), "{invalid} parsed");
+        }
+    }
+
+    #[test]
+    fn empty_captions_are_not_drawn() {
+        let base = image::RgbaImage::from_pixel(200, 200, image::Rgba([90, 140, 220, 255]));
+        let options = RenderOptions {
+            base_image: Some(base.clone()),
+            outline: Some(Outline { width: 3, style: OutlineStyle::Round, color: OUTLINE_COLOR }),
+            ..Default::default()
+        };
+        let mut timings = Timings::new();
+        let image = generate_image(Some("  \t "), Some("\n"), &options, &mut timings).unwrap();
+        assert_eq!(image, base);
+
+        // The other caption is still drawn when only one is empty
+        let image = generate_image(Some(" "), Some("taco"), &options, &mut timings).unwrap();
+        assert_ne!(image, base);
+        assert!(image.rows().take(100).flatten().all(|pixel| pixel == base.get_pixel(0, 0)));
+    }
+
+    #[test]
+    fn smart_placement_avoids_face() {
+        let face = Rect { x: 140, y: 20, width: 120, height: 160 };
+        let sky = image::Rgba([90, 140, 220, 255]);
+        let base = image::RgbaImage::from_fn(400, 400, |x, y| {
+            let inside = (face.x..face.x + face.width).contains(&x) && (face.y..face.bottom()).contains(&y);
+            if inside { image::Rgba([224, 172, 140, 255]) } else { sky }
+        });
+        let render = |base: &image::RgbaImage, smart_placement| {
+            let options = RenderOptions { base_image: Some(base.clone()), smart_placement, ..Default::default() };
+            generate_image(Some("TaCo"), Some("TrUcK"), &options, &mut Timings::new()).unwrap()
+        };
+
+        // Normally, the top caption lands on the face
+        assert!(rows_differ(&base, &render(&base, false), face.y..face.bottom()));
+
+        let image = render(&base, true);
+        assert!(!rows_differ(&base, &image, 0..face.bottom()));
+        assert!(rows_differ(&base, &image, face.bottom()..base.height()));
+
+        // Without a face, captions go at the top and bottom
+        let base = image::RgbaImage::from_pixel(400, 400, sky);
+        assert_eq!(render(&base, true), render(&base, false));
+    }
+
+    #[test]
+    fn generate_svg_draws_glyph_paths() {
+        let options = RenderOptions {
+            base_image: Some(image::RgbaImage::from_pixel(300, 200, image::Rgba([0, 0, 0, 255]))),
+            outline: Some(Outline { width: 2, style: OutlineStyle::Square, color: OUTLINE_COLOR }),
+            ..Default::default()
+        };
+        let svg = generate_svg(Some("tAcO"), Some("tRuCk"), &options, true, &mut Timings::new()).unwrap();
+
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"300\" height=\"200\""));
+        assert!(svg.contains("viewBox=\"0 0 300 200\""));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        assert_eq!(svg.matches("<path d=\"M").count(), 9);
+        assert!(svg.contains("<image width=\"300\" height=\"200\" href=\"data:image/png;base64,iVBORw0KGgo"));
+        assert!(svg.contains("stroke-width=\"4\" stroke-linejoin=\"miter\""));
+
+        // The glyphs are within the image, with the top caption above the bottom one
+        let coordinates = |path: &str| -> Vec<(f32, f32)> {
+            let numbers = path
+                .split(|c: char| c.is_ascii_alphabetic() || c == ' ')
+                .filter(|number| !number.is_empty())
+                .map(|number| number.parse::<f32>().unwrap())
+                .collect::<Vec<_>>();
+            numbers.chunks(2).map(|point| (point[0], point[1])).collect()
+        };
+        let paths = svg
+            .split("<path d=\"")
+            .skip(1)
+            .map(|rest| coordinates(&rest[..rest.find('"').unwrap()]))
+            .collect::<Vec<_>>();
+        for (x, y) in paths.iter().flatten() {
+            assert!((0. ..=300.).contains(x) && (0. ..=200.).contains(y), "({x}, {y}) is outside the image");
+        }
+        let top = paths[..4].iter().flatten().map(|(_, y)| *y).fold(f32::MIN, f32::max);
+        let bottom = paths[4..].iter().flatten().map(|(_, y)| *y).fold(f32::MAX, f32::min);
+        assert!(top < 50. && bottom > 150., "top ends at {top}, bottom starts at {bottom}");
+    }
+
+    #[test]
+    fn generate_svg_without_image() {
+        let svg = generate_svg(Some("taco"), None, &RenderOptions::default(), false, &mut Timings::new()).unwrap();
+        assert!(!svg.contains("<image"));
+        assert!(svg.contains("<path"));
+    }
+
+    #[test]
+    fn export_layout_lists_glyphs() {
+        let options = RenderOptions {
+            base_image: Some(image::RgbaImage::from_pixel(300, 200, image::Rgba([0, 0, 0, 255]))),
+            ..Default::default()
+        };
+        let json = export_layout(Some("taco \"truck\""), Some("time"), &options, &mut Timings::new()).unwrap();
+        let layout: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!((layout["width"].as_u64(), layout["height"].as_u64()), (Some(300), Some(200)));
+
+        // One entry for each character but the line breaks, each within the image
+        let glyphs = layout["glyphs"].as_array().unwrap();
+        assert_eq!(glyphs.len(), "taco \"truck\"time".chars().count());
+        assert_eq!(glyphs[5]["char"], "\"");
+        let field = |glyph: &serde_json::Value, name: &str| glyph[name].as_f64().unwrap();
+        for glyph in glyphs {
+            let (x, y) = (field(glyph, "x"), field(glyph, "y"));
+            assert!((0. ..300.).contains(&x) && (0. ..200.).contains(&y), "{glyph}");
+            assert!(x + field(glyph, "width") <= 300. && y + field(glyph, "height") <= 200., "{glyph}");
+        }
+        // Letters go left to right, and the bottom caption is below the top one
+        assert!(field(&glyphs[0], "x") < field(&glyphs[1], "x"));
+        assert!(field(&glyphs[0], "y") < field(&glyphs[12], "y"));
+
+        let empty = export_layout(None, None, &options, &mut Timings::new()).unwrap();
+        assert_eq!(empty, "{\"width\":300,\"height\":200,\"glyphs\":[]}\n");
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"t"), "dA==");
+        assert_eq!(base64(b"ta"), "dGE=");
+        assert_eq!(base64(b"taco"), "dGFjbw==");
+        assert_eq!(base64(b"taco truck"), "dGFjbyB0cnVjaw==");
+    }
+
+    #[test]
+    fn debug_regions_borders() {
+        let base = base_image();
+        let quarter = base.height() / 4;
+        let options = RenderOptions {
+            debug_regions: true,
+            ..Default::default()
+        };
+        let image = generate_image(Some("TaCo"), Some("TrUcK"), &options, &mut Timings::new()).unwrap();
+
+        let (right, bottom) = (base.width() - 1, base.height() - 1);
+        for (x, y) in [(0, 0), (right, 0), (base.width() / 2, quarter - 1), (0, quarter / 2)] {
+            assert_eq!(*image.get_pixel(x, y), DEBUG_TOP_REGION_COLOR, "at ({x}, {y})");
+        }
+        for (x, y) in [(0, bottom), (right, bottom), (base.width() / 2, bottom), (right, bottom - quarter / 2)] {
+            assert_eq!(*image.get_pixel(x, y), DEBUG_BOTTOM_REGION_COLOR, "at ({x}, {y})");
+        }
+        // Inside the borders, the image is left alone
+        let middle = (base.width() / 2, base.height() / 2);
+        assert_eq!(image.get_pixel(middle.0, middle.1), base.get_pixel(middle.0, middle.1));
+
+        let plain = generate_image(Some("TaCo"), Some("TrUcK"), &Default::default(), &mut Timings::new()).unwrap();
+        assert_ne!(*plain.get_pixel(0, 0), DEBUG_TOP_REGION_COLOR);
+    }
+
+    #[test]
+    fn generate_image_records_timings() {
+        let mut timings = Timings::new();
+        generate_image(Some("TaCo"), Some("TrUcK"), &RenderOptions::default(), &mut timings).unwrap();
+
+        for stage in [Stage::ImageLoad, Stage::FontLoad, Stage::Rasterize, Stage::Composite] {
+            assert!(timings.get(stage).is_some(), "missing {stage}");
+        }
+        assert!(timings.get(Stage::Encode).is_none());
+    }
+
+    #[test]
+    fn font_size_follows_base_image() {
+        let font_size = |width, height| {
+            let image = image::RgbaImage::new(width, height);
+            let regions = caption_regions(Some("TaCo"), None, &image, &RenderOptions::default(), &mut Timings::new());
+            regions[0].font_size
+        };
+        let base = base_image();
+        assert_eq!(font_size(base.width(), base.height()), base.height() as f32 / 8.);
+        assert_eq!(font_size(1600, 120), 15.);
+        assert_eq!(font_size(150, 900), 37.5);
+    }
+
+    #[test]
+    fn custom_font() {
+        let options = RenderOptions {
+            font: Some(ANTON_REGULAR_SOURCE.to_vec()),
+            ..Default::default()
+        };
+        let image = generate_image(Some("TaCo"), Some("TrUcK"), &options, &mut Timings::new()).unwrap();
+        let plain = generate_image(Some("TaCo"), Some("TrUcK"), &Default::default(), &mut Timings::new()).unwrap();
+        assert!(image == plain);
+
+        // A font which does not parse is an error rather than a panic
+        let options = RenderOptions {
+            font: Some(b"not a font".to_vec()),
+            ..Default::default()
+        };
+        assert!(generate_image(Some("TaCo"), None, &options, &mut Timings::new()).is_err());
+        assert!(generate_svg(Some("TaCo"), None, &options, false, &mut Timings::new()).is_err());
+        assert!(export_layout(Some("TaCo"), None, &options, &mut Timings::new()).is_err());
+    }
 }
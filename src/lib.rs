@@ -0,0 +1,208 @@
+//! SpOnGiFy
+//! ========
+//!
+//! The capitalization at the core of the `spongify` program, for SpOnGiFying text from other programs. A
+//! `CapitalizationStrategy` describes a style, like "LiKe tHiS", and creates the `CapitalizationEngine` which decides
+//! the case of each grapheme cluster. For the common case, `spongify` does it all at once:
+//!
+//! ```
+//! assert_eq!(spongify::spongify("your text here", Default::default()), "YoUr tExT HeRe");
+//! ```
+
+pub mod capital;
+pub mod tokenize;
+
+pub use capital::{
+    CapitalizationEngine, CapitalizationStrategy, CaseEngine, Cased, EngineOptions, MappedChars,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// SpOnGiFy `text` in the style of `strategy`, with the default `EngineOptions`. This is `capitalize_str` with a new
+/// engine for `strategy`.
+///
+/// Random strategies use a new random seed each call; create an engine with `EngineOptions::seed` and use
+/// `capitalize_str` for repeatable output.
+pub fn spongify(text: &str, strategy: CapitalizationStrategy) -> String {
+    let mut engine = strategy.create_engine(EngineOptions::default());
+    capitalize_str(engine.as_mut(), text)
+}
+
+/// Change the case of each grapheme cluster of `input` as `engine` decides, continuing from wherever the engine's
+/// pattern is.
+///
+/// The capitalization pattern flows across lines as if the text were one stream, with each line break counting as a
+/// character. Characters are cased with the full Unicode mappings, so the output can be longer than the input (like
+/// "ß", which uppercases to "SS").
+pub fn capitalize_str(engine: &mut dyn CapitalizationEngine, input: &str) -> String {
+    let mut capitalized = String::with_capacity(input.len());
+    for (index, grapheme) in input.graphemes(true).enumerate() {
+        if grapheme == "\n" || grapheme == "\r\n" {
+            engine.line_break();
+            capitalized.push_str(grapheme);
+            continue;
+        }
+
+        capitalize_grapheme(engine, index, grapheme, |_, _, mapped| {
+            capitalized.extend(mapped)
+        });
+    }
+    capitalized
+}
+
+/// Change the case of `grapheme`, the grapheme cluster at `index` (as in `CapitalizationEngine::start_grapheme`), as
+/// `engine` decides. Each character is passed to `cased` in order, along with its byte offset within `grapheme` and the
+/// characters `engine` writes in its place.
+///
+/// The whole grapheme cluster is cased together, so combining marks do not advance the pattern.
+pub fn capitalize_grapheme(
+    engine: &mut dyn CapitalizationEngine,
+    index: usize,
+    grapheme: &str,
+    mut cased: impl FnMut(usize, char, MappedChars),
+) {
+    let first = grapheme.chars().next().unwrap_or_default();
+    engine.start_grapheme(index, first);
+    for (offset, c) in grapheme.char_indices() {
+        cased(offset, c, engine.map_char(c));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spongify_styles() {
+        assert_eq!(
+            spongify("your text here", CapitalizationStrategy::default()),
+            "YoUr tExT HeRe"
+        );
+        assert_eq!(
+            spongify(
+                "your text here",
+                CapitalizationStrategy::AlternatingInitialUppercaseSkipWhitespace
+            ),
+            "YoUr TeXt HeRe"
+        );
+        assert_eq!(
+            spongify(
+                "this is how it looks",
+                CapitalizationStrategy::AlternatingWords
+            ),
+            "this IS how IT looks"
+        );
+        assert_eq!(
+            spongify("leet speak", CapitalizationStrategy::Leet),
+            "L337 5P34k"
+        );
+    }
+
+    #[test]
+    fn every_strategy_exactly() {
+        // The exact output of every strategy, so changes to how engines are driven cannot change it by accident
+        let input = "Taco truck: ß, ᾳ, e\u{301}, İstanbul 😀\nsecond LINE\r\n  end";
+        for (strategy, expected) in [
+            (
+                "LiKe tHiS",
+                "TaCo tRuCk: SS, ᾳ, E\u{301}, i\u{307}StAnBuL 😀\nSeCoNd lInE\r\n  EnD",
+            ),
+            (
+                "lIkE ThIs",
+                "tAcO TrUcK: ß, ᾼ, e\u{301}, İsTaNbUl 😀\nsEcOnD LiNe\r\n  eNd",
+            ),
+            (
+                "LiKe ThIs",
+                "TaCo TrUcK: SS, ᾼ, E\u{301}, İsTaNbUl 😀\nsEcOnD lInE\r\n  eNd",
+            ),
+            (
+                "lIkE tHiS",
+                "tAcO tRuCk: ß, ᾳ, e\u{301}, i\u{307}StAnBuL 😀\nSeCoNd LiNe\r\n  EnD",
+            ),
+            (
+                "like THIS",
+                "taco TRUCK: ß, ᾼ, e\u{301}, İSTANBUL 😀\nsecond LINE\r\n  end",
+            ),
+            (
+                "RAnDOmlY",
+                "TACo tRUCk: SS, ᾳ, e\u{301}, İStANbuL 😀\nSeCoNd LINe\r\n  EnD",
+            ),
+            (
+                "RAnDOmlY pEr WoRD",
+                "TACO TrUCk: ß, ᾳ, E\u{301}, İSTaNBuL 😀\nSecoND line\r\n  EnD",
+            ),
+            (
+                "Like This",
+                "Taco Truck: Ss, ᾼ, E\u{301}, İstanbul 😀\nSecond Line\r\n  End",
+            ),
+            (
+                "leet",
+                "74C0 7RuCk: SS, ᾳ, 3\u{301}, i\u{307}574nBuL 😀\n53C0Nd l1n3\r\n  3nD",
+            ),
+        ] {
+            let strategy = strategy.parse::<CapitalizationStrategy>().unwrap();
+            let mut engine = strategy.create_engine(EngineOptions {
+                seed: Some(7),
+                ..Default::default()
+            });
+            assert_eq!(
+                capitalize_str(engine.as_mut(), input),
+                expected,
+                "for {strategy}"
+            );
+        }
+
+        let strategy = CapitalizationStrategy::FromPattern("uul".parse().unwrap());
+        let mut engine = strategy.create_engine(EngineOptions::default());
+        assert_eq!(
+            capitalize_str(engine.as_mut(), input),
+            "TAcO tRUcK: SS, ᾼ, E\u{301}, İStANbUL 😀\nSeCOnD lINe\r\n  eND"
+        );
+    }
+
+    #[test]
+    fn spongify_across_lines() {
+        // The line break counts as a character, and is kept as it was
+        assert_eq!(
+            spongify("taco\ntruck\r\ntime", CapitalizationStrategy::default()),
+            "TaCo\ntRuCk\r\ntImE"
+        );
+    }
+
+    #[test]
+    fn capitalize_str_continues_pattern() {
+        let mut engine = CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        assert_eq!(capitalize_str(engine.as_mut(), "taco"), "TaCo");
+        assert_eq!(capitalize_str(engine.as_mut(), " truck"), " tRuCk");
+
+        // Case mappings which expand to more than one character still take one step of the pattern
+        engine.reset();
+        assert_eq!(capitalize_str(engine.as_mut(), "straße"), "StRaSSe");
+    }
+
+    #[test]
+    fn spongify_graphemes() {
+        // Combining marks are cased with the letter they are on, and do not advance the pattern
+        assert_eq!(
+            spongify("e\u{301}e\u{301}e", CapitalizationStrategy::default()),
+            "E\u{301}e\u{301}E"
+        );
+        assert_eq!(spongify("ᾳᾳ", CapitalizationStrategy::default()), "ᾼᾳ");
+    }
+
+    #[test]
+    fn capitalize_grapheme_each_char() {
+        let mut engine = CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        let mut cased = Vec::new();
+        capitalize_grapheme(engine.as_mut(), 0, "e\u{301}", |offset, c, mapped| {
+            cased.push((offset, c, mapped.into_iter().collect::<String>()))
+        });
+        assert_eq!(
+            cased,
+            vec![
+                (0, 'e', "E".to_owned()),
+                (1, '\u{301}', "\u{301}".to_owned())
+            ]
+        );
+    }
+}
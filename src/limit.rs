@@ -0,0 +1,194 @@
+//! Input Limits
+//! ============
+//!
+//! Caps on how much input gets processed, set by `--input-limit-lines` and `--input-limit-bytes`. These are meant for
+//! untrusted inputs, where reading everything would take too long or use too much memory.
+
+use clap::ValueEnum;
+use std::io;
+
+/// What to do when the input goes past a limit, for `--input-limit-action`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum LimitAction {
+    /// Fail with an error.
+    #[default]
+    Error,
+    /// Print a warning and process the input up to the limit.
+    Warn,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct InputLimits {
+    /// The maximum number of lines to read.
+    pub lines: Option<usize>,
+    /// The maximum number of bytes to read.
+    pub bytes: Option<u64>,
+    pub action: LimitAction,
+}
+
+impl InputLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.lines.is_none() && self.bytes.is_none()
+    }
+}
+
+/// A reader which stops at the `InputLimits`. Reading past a limit either fails or ends the input, depending on the
+/// `LimitAction`.
+pub struct LimitedReader<R> {
+    inner: R,
+    limits: InputLimits,
+    lines_read: usize,
+    bytes_read: u64,
+    warned: bool,
+}
+
+impl<R: io::BufRead> LimitedReader<R> {
+    pub fn new(inner: R, limits: InputLimits) -> Self {
+        Self {
+            inner,
+            limits,
+            lines_read: 0,
+            bytes_read: 0,
+            warned: false,
+        }
+    }
+
+    /// The number of bytes which can be read before hitting a limit, not counting the line limit.
+    fn bytes_left(&self) -> u64 {
+        self.limits
+            .bytes
+            .map_or(u64::MAX, |limit| limit.saturating_sub(self.bytes_read))
+    }
+
+    /// The number of line breaks which can be read before hitting the line limit.
+    fn lines_left(&self) -> usize {
+        self.limits
+            .lines
+            .map_or(usize::MAX, |limit| limit.saturating_sub(self.lines_read))
+    }
+
+    fn exceeded(&mut self) -> io::Result<&[u8]> {
+        let message = if self.bytes_left() == 0 {
+            format!("input exceeds --input-limit-bytes of {}", self.bytes_read)
+        } else {
+            format!("input exceeds --input-limit-lines of {}", self.lines_read)
+        };
+
+        match self.limits.action {
+            LimitAction::Error => Err(io::Error::other(message)),
+            LimitAction::Warn => {
+                if !self.warned {
+                    self.warned = true;
+                    warn!("{message}, ignoring the rest");
+                }
+                Ok(&[])
+            }
+        }
+    }
+}
+
+impl<R: io::BufRead> io::Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = io::BufRead::fill_buf(self)?;
+        let amt = available.len().min(buf.len());
+        buf[..amt].copy_from_slice(&available[..amt]);
+        io::BufRead::consume(self, amt);
+        Ok(amt)
+    }
+}
+
+impl<R: io::BufRead> io::BufRead for LimitedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let bytes_left = self.bytes_left();
+        let lines_left = self.lines_left();
+        if (bytes_left == 0 || lines_left == 0) && !self.inner.fill_buf()?.is_empty() {
+            return self.exceeded();
+        }
+
+        let buf = self.inner.fill_buf()?;
+        let mut len = buf
+            .len()
+            .min(usize::try_from(bytes_left).unwrap_or(usize::MAX));
+        if let Some((end, _)) = buf[..len]
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| **b == b'\n')
+            .nth(lines_left.saturating_sub(1))
+        {
+            len = end + 1;
+        }
+        Ok(&buf[..len])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The inner buffer is already filled by the `fill_buf` that came before, so this does not do any I/O
+        if let Ok(buf) = self.inner.fill_buf() {
+            self.lines_read += buf[..amt].iter().filter(|b| **b == b'\n').count();
+        }
+        self.bytes_read += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+
+    fn read_limited(input: &str, limits: InputLimits) -> io::Result<String> {
+        io::read_to_string(LimitedReader::new(io::Cursor::new(input), limits))
+    }
+
+    #[test]
+    fn line_limit() {
+        let limits = InputLimits {
+            lines: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(read_limited("one\ntwo\n", limits).unwrap(), "one\ntwo\n");
+
+        let err = read_limited("one\ntwo\nthree", limits).unwrap_err();
+        assert!(err.to_string().contains("--input-limit-lines of 2"));
+
+        let limits = InputLimits {
+            action: LimitAction::Warn,
+            ..limits
+        };
+        assert_eq!(
+            read_limited("one\ntwo\nthree", limits).unwrap(),
+            "one\ntwo\n"
+        );
+    }
+
+    #[test]
+    fn byte_limit() {
+        let limits = InputLimits {
+            bytes: Some(6),
+            ..Default::default()
+        };
+        assert_eq!(read_limited("taco\n", limits).unwrap(), "taco\n");
+
+        let err = read_limited("taco truck", limits).unwrap_err();
+        assert!(err.to_string().contains("--input-limit-bytes of 6"));
+
+        let limits = InputLimits {
+            action: LimitAction::Warn,
+            ..limits
+        };
+        assert_eq!(read_limited("taco truck", limits).unwrap(), "taco t");
+    }
+
+    #[test]
+    fn limited_lines() {
+        let limits = InputLimits {
+            lines: Some(1),
+            action: LimitAction::Warn,
+            ..Default::default()
+        };
+        let lines = LimitedReader::new(io::Cursor::new("one\ntwo\nthree"), limits)
+            .lines()
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(lines, ["one"]);
+    }
+}
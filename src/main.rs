@@ -1,49 +1,150 @@
-mod capital;
+#[macro_use]
+mod trace;
+
+mod batch;
+mod count;
+mod diff;
+mod face;
 mod imagemacro;
+mod limit;
+mod mmap;
+#[cfg(feature = "preview")]
+mod preview;
+mod rect;
+mod stats;
+mod template;
+mod timing;
+mod transform;
+mod url;
 
-use capital::CapitalizationStrategy;
-use clap::{Args, Parser};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use count::{ByteCounts, CountingReader, CountingWriter};
+use imagemacro::{
+    CaptionCentering, CaptionFit, CaptionPlacement, Flip, Gravity, Outline, OutlineStyle,
+    PixelSnap, RegionAlign, RenderOptions, ScriptClass, TextDirection,
+};
+use limit::{InputLimits, LimitAction, LimitedReader};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use rayon::prelude::*;
+use spongify::tokenize::Tokenizer;
+use spongify::{
+    capital::{Pattern, DEFAULT_RANDOM_BIAS},
+    capitalize_grapheme, CapitalizationEngine, CapitalizationStrategy, EngineOptions,
+};
+use stats::Stats;
 use std::{
+    borrow::Cow,
+    ffi::OsString,
     fmt, fs, io,
+    num::NonZeroUsize,
     path::{Path, PathBuf},
     string::ToString,
+    time::{Duration, Instant},
 };
+use timing::{Stage, Timings};
+use transform::{CaseMapping, Pipeline, ShuffleWords, Transform, TransformOrder, TransformStep};
+use unicode_properties::{GeneralCategory, UnicodeEmoji, UnicodeGeneralCategory};
+use unicode_segmentation::UnicodeSegmentation;
+use url::Retry;
 
 type Result<T, E = Box<dyn std::error::Error + Send + Sync>> = std::result::Result<T, E>;
 
 #[derive(Args, Debug)]
 struct InputOpt {
-    /// The meaning of the inline parameter is guessed at by SpOnGiFy. If this names a file, that file is read. If
-    /// specified as `-`, SpOnGiFy will read from standard input. Use `--text` or `--file` to disambiguate.
+    /// The meaning of the inline parameter is guessed at by SpOnGiFy. If this names a file, that file is read (with a
+    /// warning, since the same words are text in another directory), but several words are always text. If specified as
+    /// `-`, SpOnGiFy will read from standard input. Use `--text` or `--file` to say which is meant.
     inline: Vec<String>,
 
     /// The text to SpOnGiFy. This can be useful if your text is `-` or names a file.
     #[arg(long, group = "input")]
     text: Option<String>,
 
-    /// Load text from a file.
+    /// Load text from a file. Give this more than once to read several files one after the other, as if they were one
+    /// file.
     #[arg(long, short, group = "input")]
-    file: Option<PathBuf>,
+    file: Vec<PathBuf>,
 
     /// Read from standard input.
     #[arg(long, group = "input")]
     stdin: bool,
+
+    /// Fetch the text from a URL with an HTTP GET. Redirects are followed, compressed responses are decompressed, and a
+    /// response other than 200 OK is an error.
+    #[arg(long, group = "input")]
+    url: Option<String>,
+
+    /// Read from this open file descriptor, like one set up by the shell with `3< notes.txt`. Descriptor 0 is standard
+    /// input, and the descriptors of standard output and standard error (1 and 2) cannot be read.
+    #[cfg(unix)]
+    #[arg(long, group = "input", value_name = "N")]
+    fd: Option<std::os::fd::RawFd>,
+
+    /// Retry the `--url` fetch up to this many times if it fails with a connection error or a server error (5xx).
+    #[arg(long, requires = "url", default_value_t = 0, value_name = "N")]
+    retry: u32,
+
+    /// How long to wait before the first `--retry`, in milliseconds. The wait doubles after each retry.
+    #[arg(long, requires = "url", default_value_t = 500, value_name = "MS")]
+    retry_delay: u64,
+
+    /// SpOnGiFy the input even if it looks like binary data.
+    #[arg(long)]
+    force_binary: bool,
+
+    /// Stop after reading this many lines.
+    #[arg(long, value_name = "N")]
+    input_limit_lines: Option<usize>,
+
+    /// Stop after reading this many bytes.
+    #[arg(long, value_name = "N")]
+    input_limit_bytes: Option<u64>,
+
+    /// What to do when the input goes past `--input-limit-lines` or `--input-limit-bytes`.
+    #[arg(long, value_enum, default_value_t = LimitAction::Error)]
+    input_limit_action: LimitAction,
+}
+
+impl InputOpt {
+    pub fn limits(&self) -> InputLimits {
+        InputLimits {
+            lines: self.input_limit_lines,
+            bytes: self.input_limit_bytes,
+            action: self.input_limit_action,
+        }
+    }
 }
 
 enum InputSpec {
     Stdin,
     Text(String),
-    File(PathBuf),
+    /// Files read one after the other, as if they were one file.
+    Files(Vec<PathBuf>),
+    Url(String, Retry),
+    #[cfg(unix)]
+    Fd(std::os::fd::RawFd),
 }
 
 impl From<InputOpt> for InputSpec {
     fn from(value: InputOpt) -> Self {
+        #[cfg(unix)]
+        if let Some(fd) = value.fd {
+            // Descriptor 0 is read through `io::stdin` like `--stdin` is, rather than owned and closed
+            return if fd == 0 { Self::Stdin } else { Self::Fd(fd) };
+        }
+
         if value.stdin {
             Self::Stdin
         } else if let Some(text) = value.text {
             Self::Text(text)
-        } else if let Some(file) = value.file {
-            Self::File(file)
+        } else if !value.file.is_empty() {
+            Self::Files(value.file)
+        } else if let Some(url) = value.url {
+            let retry = Retry {
+                retries: value.retry,
+                delay: Duration::from_millis(value.retry_delay),
+            };
+            Self::Url(url, retry)
         } else if value.inline.is_empty() {
             Self::Stdin
         } else if value.inline.len() == 1 {
@@ -53,7 +154,8 @@ impl From<InputOpt> for InputSpec {
             } else {
                 let path = Path::new(&inline);
                 if path.exists() {
-                    Self::File(PathBuf::from(inline))
+                    warn_inline_file(path);
+                    Self::Files(vec![PathBuf::from(inline)])
                 } else {
                     Self::Text(inline.clone())
                 }
@@ -64,42 +166,344 @@ impl From<InputOpt> for InputSpec {
     }
 }
 
+/// Warn that the inline argument `path` is being read as a file because it exists, which could surprise someone who
+/// meant it as text.
+fn warn_inline_file(path: &Path) {
+    warn!(
+        "reading {} as a file because it exists; use --text to SpOnGiFy it as text or --file to silence this",
+        path.display()
+    );
+}
+
 impl InputSpec {
     pub fn into_reader(self) -> Result<Box<dyn io::BufRead + Send + Sync>> {
         match self {
             Self::Stdin => Ok(Box::new(io::BufReader::new(io::stdin()))),
-            Self::Text(text) => Ok(Box::new(io::Cursor::new(text))),
-            Self::File(path) => Ok(Box::new(io::BufReader::new(fs::File::open(path)?))),
+            // Text from the command line is a whole line, so it ends with a newline like a line of a file would
+            Self::Text(text) => Ok(Box::new(io::Cursor::new(text + "\n"))),
+            Self::Files(paths) => {
+                // Every file is opened up front, so a missing one is found before any output is written
+                let mut reader: Box<dyn io::BufRead + Send + Sync> = Box::new(io::empty());
+                for path in paths {
+                    let file = fs::File::open(&path)
+                        .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+                    reader = Box::new(io::Read::chain(reader, io::BufReader::new(file)));
+                }
+                Ok(reader)
+            }
+            Self::Url(url, retry) => url::open_url(&url, retry),
+            #[cfg(unix)]
+            Self::Fd(fd) => {
+                use std::os::fd::FromRawFd;
+
+                // Reading takes ownership of the descriptor and closes it, which would pull standard output or
+                // standard error out from under everything still writing to them
+                if fd == 1 || fd == 2 {
+                    return Err(
+                        format!("file descriptor {fd} is an output and cannot be read").into(),
+                    );
+                }
+                // Taking ownership of a descriptor which is not open would close whatever opens with that number later
+                // SAFETY: `F_GETFD` only reads the descriptor's flags, and fails if it is not open.
+                if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+                    return Err(format!("file descriptor {fd} is not open").into());
+                }
+                // SAFETY: The descriptor is open and is not one of the standard streams (`--fd 0` is read as
+                // `InputSpec::Stdin`), so nothing else in the process uses it; it was handed to SpOnGiFy to be read.
+                let file = unsafe { fs::File::from_raw_fd(fd) };
+                Ok(Box::new(io::BufReader::new(file)))
+            }
+        }
+    }
+}
+
+/// Check that the first chunk of `input` looks like text, without consuming it. Input containing NUL bytes or invalid
+/// UTF-8 is considered binary, unless `lossy_utf8` is set, in which case only NUL bytes are.
+fn refuse_binary(input: &mut dyn io::BufRead, lossy_utf8: bool) -> Result<()> {
+    let chunk = input.fill_buf()?;
+
+    let is_binary = chunk.contains(&0)
+        || !lossy_utf8
+            && match std::str::from_utf8(chunk) {
+                Ok(_) => false,
+                // A multibyte character cut off at the end of the chunk is not a problem
+                Err(e) => e.error_len().is_some(),
+            };
+
+    if is_binary {
+        Err("input appears to be binary (use --force-binary to SpOnGiFy it anyway)".into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Split `input` into lines like `BufRead::lines`. Invalid UTF-8 in a line is an error, unless `lossy_utf8` is set, in
+/// which case it is replaced with U+FFFD REPLACEMENT CHARACTER.
+fn read_lines(
+    input: impl io::BufRead,
+    lossy_utf8: bool,
+) -> impl Iterator<Item = io::Result<String>> {
+    read_terminated_lines(input, lossy_utf8).map(|line| line.map(|(line, _)| line))
+}
+
+/// Split `input` into lines like `read_lines`, along with the terminator each line ended with: `"\n"`, `"\r\n"`, or
+/// nothing for a last line without a newline.
+fn read_terminated_lines(
+    mut input: impl io::BufRead,
+    lossy_utf8: bool,
+) -> impl Iterator<Item = io::Result<(String, &'static str)>> {
+    std::iter::from_fn(move || {
+        let mut line = Vec::new();
+        match input.read_until(b'\n', &mut line) {
+            Ok(0) => return None,
+            Ok(_) => {}
+            Err(e) => return Some(Err(e)),
+        }
+
+        let mut terminator = "";
+        if line.ends_with(b"\n") {
+            line.pop();
+            terminator = "\n";
+            if line.ends_with(b"\r") {
+                line.pop();
+                terminator = "\r\n";
+            }
         }
+        Some(match String::from_utf8(line) {
+            Ok(line) => Ok((line, terminator)),
+            Err(e) if lossy_utf8 => Ok((
+                String::from_utf8_lossy(e.as_bytes()).into_owned(),
+                terminator,
+            )),
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        })
+    })
+}
+
+/// Treat a broken pipe on the output as the end of the output rather than an error. This is what happens when the
+/// output is piped into something like `head`, which stops reading once it has seen enough.
+fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(err) if is_broken_pipe(err.as_ref()) => Ok(()),
+        result => result,
     }
 }
 
+fn is_broken_pipe(err: &(dyn std::error::Error + 'static)) -> bool {
+    std::iter::successors(Some(err), |err| err.source()).any(|err| {
+        err.downcast_ref::<io::Error>()
+            .is_some_and(|err| err.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
+
 #[derive(Args, Debug)]
 struct OutputOpt {
     /// Output to a file.
     #[arg(short, long, group = "output")]
     output_file: Option<PathBuf>,
 
+    /// Write through `--output-file` when it is a symbolic link, replacing the contents of the file it points to. By
+    /// default, output to a symbolic link is refused, so a link planted where the output goes cannot redirect it.
+    #[arg(long, requires = "output_file", overrides_with = "no_follow_symlinks")]
+    follow_symlinks: bool,
+
+    /// Refuse to write through an `--output-file` which is a symbolic link. This is the default, and undoes an earlier
+    /// `--follow-symlinks`.
+    #[arg(long, overrides_with = "follow_symlinks")]
+    no_follow_symlinks: bool,
+
     /// Copy result to the clipboard.
     #[arg(short, long, group = "output")]
     clip: bool,
+
+    /// Render the result as an image macro. The image is written to `--output-file` in the format its extension names,
+    /// or as PNG to standard output.
+    #[arg(long, conflicts_with = "clip")]
+    image: bool,
+
+    /// The format to write the image in, instead of the one the extension of `--output-file` names. SVG draws the
+    /// captions as vector shapes over the embedded base image, so they stay sharp at any size.
+    #[arg(long, requires = "image", value_enum)]
+    image_format: Option<ImageType>,
+
+    /// Write to `--output-file` through a memory map, which can be faster for huge files. This only works when the
+    /// input is an ASCII file; otherwise, SpOnGiFy falls back to normal writes.
+    #[arg(long, requires = "output_file", conflicts_with_all = ["image", "parallel"])]
+    mmap_output: bool,
+
+    /// Compress the text output. Without this, output to an `--output-file` ending in `.gz` or `.zst` is compressed
+    /// to match.
+    #[arg(long, value_enum, conflicts_with_all = ["clip", "image", "mmap_output"])]
+    compress: Option<Compression>,
+
+    /// Write the output back over the `--file` it was read from. The output goes to a temporary file next to it first,
+    /// which then replaces the file with the same permissions, so the file is never left half-written.
+    #[arg(long, group = "output", requires = "file", conflicts_with_all = ["stdin", "image", "compress"])]
+    in_place: bool,
+}
+
+/// The choices for `--compress`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Get the compression named by the extension of `path`, if any.
+    fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "gz" => Some(Self::Gzip),
+            "zst" => Some(Self::Zstd),
+            _ => None,
+        }
+    }
+
+    /// Wrap `writer` in a compressor, which finishes the compressed stream when it is dropped.
+    fn wrap(self, writer: impl io::Write + 'static) -> Result<Box<dyn io::Write>> {
+        match self {
+            Self::Gzip => Ok(Box::new(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::default(),
+            ))),
+            Self::Zstd => Ok(Box::new(zstd::Encoder::new(writer, 0)?.auto_finish())),
+        }
+    }
+}
+
+/// The choices for `--image-format`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum ImageType {
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Tiff,
+    Webp,
+    Svg,
+}
+
+/// The format an image macro is written in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum MacroFormat {
+    /// Pixels, encoded by `image`.
+    Raster(image::ImageFormat),
+    /// Vector captions over the embedded base image (see `imagemacro::generate_svg`).
+    Svg,
+}
+
+impl From<ImageType> for MacroFormat {
+    fn from(value: ImageType) -> Self {
+        match value {
+            ImageType::Png => Self::Raster(image::ImageFormat::Png),
+            ImageType::Jpeg => Self::Raster(image::ImageFormat::Jpeg),
+            ImageType::Gif => Self::Raster(image::ImageFormat::Gif),
+            ImageType::Bmp => Self::Raster(image::ImageFormat::Bmp),
+            ImageType::Tiff => Self::Raster(image::ImageFormat::Tiff),
+            ImageType::Webp => Self::Raster(image::ImageFormat::WebP),
+            ImageType::Svg => Self::Svg,
+        }
+    }
 }
 
 impl OutputOpt {
+    /// Get the format to write images in, from `--image-format` or the extension of `--output-file` (PNG if there is
+    /// neither).
+    pub fn image_format(&self) -> Result<MacroFormat> {
+        if let Some(image_type) = self.image_format {
+            return Ok(image_type.into());
+        }
+        match self.output_file {
+            Some(ref path)
+                if path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("svg")) =>
+            {
+                Ok(MacroFormat::Svg)
+            }
+            Some(ref path) if path.extension().is_some() => {
+                Ok(MacroFormat::Raster(image::ImageFormat::from_path(path)?))
+            }
+            _ => Ok(MacroFormat::Raster(image::ImageFormat::Png)),
+        }
+    }
+
+    /// Get the compression for the text output, from `--compress` or the extension of `--output-file`.
+    pub fn compression(&self) -> Option<Compression> {
+        if self.image {
+            return None;
+        }
+        self.compress
+            .or_else(|| self.output_file.as_deref().and_then(Compression::from_path))
+    }
+
+    /// Get the writer for the output. This does not handle `--clip`, which needs a `ClipWriter` to be finished.
+    ///
     /// # Return
     /// A tuple containing an output to write to and a boolean indicating if a newline should be appended to the output.
     pub fn get_writer(&self) -> Result<(Box<dyn io::Write>, bool)> {
+        let compression = self.compression();
         if let Some(ref path) = self.output_file {
-            let f = fs::File::create(path)?;
-            Ok((Box::new(f), true))
-        } else if self.clip {
-            Ok((Box::new(ClipWriter::new()), false))
+            let f = open_output_file(path, self.follow_symlinks)?;
+            match compression {
+                Some(compression) => Ok((compression.wrap(f)?, true)),
+                None => Ok((Box::new(f), true)),
+            }
         } else {
-            Ok((Box::new(io::stdout()), true))
+            match compression {
+                Some(compression) => Ok((compression.wrap(io::stdout())?, true)),
+                None => Ok((Box::new(io::stdout()), true)),
+            }
+        }
+    }
+}
+
+/// Open `path` for writing. Regular files are created or truncated, but named pipes (FIFOs) are opened as-is, since
+/// there is nothing to truncate and the reader on the other end expects the pipe to stay put.
+///
+/// Unless `follow_symlinks` is set, a `path` which is a symbolic link is an error instead of opening the file it points
+/// to. On Unix, this is checked by the open itself (with `O_NOFOLLOW`), so the link cannot be swapped in after a check.
+fn open_output_file(path: &Path, follow_symlinks: bool) -> Result<fs::File> {
+    let refused = || {
+        format!(
+            "refusing to write through the symbolic link {} (pass --follow-symlinks to write to its target)",
+            path.display()
+        )
+    };
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+
+        if !follow_symlinks {
+            options.custom_flags(libc::O_NOFOLLOW);
+        }
+        let open = |options: &fs::OpenOptions| {
+            options.open(path).map_err(|e| match e.raw_os_error() {
+                Some(libc::ELOOP) if !follow_symlinks => refused().into(),
+                _ => e.into(),
+            })
+        };
+        if fs::metadata(path).is_ok_and(|meta| meta.file_type().is_fifo()) {
+            return open(&options);
         }
+        open(options.create(true).truncate(true))
+    }
+
+    #[cfg(not(unix))]
+    {
+        if !follow_symlinks
+            && fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_symlink())
+        {
+            return Err(refused().into());
+        }
+        Ok(options.create(true).truncate(true).open(path)?)
     }
 }
 
+/// Collects the output for `--clip`, which is copied to the clipboard by `finish`.
 struct ClipWriter {
     contents: Vec<u8>,
 }
@@ -110,6 +514,18 @@ impl ClipWriter {
             contents: Vec::with_capacity(1024),
         }
     }
+
+    /// Copy everything written to the clipboard. The clipboard is picked for the platform and, on Linux and other Unix
+    /// systems, for the display server (X11 or Wayland) that is running. Nothing is copied if this is never called,
+    /// such as when SpOnGiFying fails partway through.
+    pub fn finish(self) -> Result<()> {
+        let mut ctx = copypasta_ext::try_context()
+            .ok_or("could not access clipboard: none is available (--clip needs X11, Wayland, macOS, or Windows)")?;
+        let contents = String::from_utf8_lossy(&self.contents).into_owned();
+        ctx.set_contents(contents)
+            .map_err(|e| format!("could not access clipboard: {e}"))?;
+        Ok(())
+    }
 }
 
 impl io::Write for ClipWriter {
@@ -122,70 +538,4170 @@ impl io::Write for ClipWriter {
     }
 }
 
-impl Drop for ClipWriter {
+/// Get the text on the clipboard, for `--caption-from-clip`. The clipboard is picked like `ClipWriter::finish` picks
+/// it.
+fn read_clipboard() -> Result<String> {
+    let mut ctx = copypasta_ext::try_context().ok_or(
+        "could not access clipboard: none is available (--caption-from-clip needs X11, Wayland, macOS, or Windows)",
+    )?;
+    ctx.get_contents()
+        .map_err(|e| format!("could not read the clipboard: {e}").into())
+}
+
+/// Writes the output for `--in-place` to a temporary file in the same directory as the file being replaced, which
+/// `finish` renames over it. The temporary file is removed if this is dropped without finishing.
+struct InPlaceWriter {
+    path: PathBuf,
+    temp_path: PathBuf,
+    file: io::BufWriter<fs::File>,
+}
+
+impl InPlaceWriter {
+    pub fn new(path: &Path) -> Result<Self> {
+        let permissions = fs::metadata(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?
+            .permissions();
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| format!("--in-place needs a file, not {}", path.display()))?;
+        let temp_path = path.with_file_name(format!(
+            ".{}.spongify-{}",
+            file_name.to_string_lossy(),
+            std::process::id()
+        ));
+
+        let file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+            .map_err(|e| format!("could not create {}: {e}", temp_path.display()))?;
+        file.set_permissions(permissions)?;
+        Ok(Self {
+            path: path.to_owned(),
+            temp_path,
+            file: io::BufWriter::new(file),
+        })
+    }
+
+    /// Replace the file with everything written. Renaming within a directory is atomic, so readers of the file see
+    /// either all of the old contents or all of the new.
+    pub fn finish(mut self) -> Result<()> {
+        io::Write::flush(&mut self.file)?;
+        self.file.get_ref().sync_all()?;
+        fs::rename(&self.temp_path, &self.path)
+            .map_err(|e| format!("could not replace {}: {e}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+impl io::Write for InPlaceWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Drop for InPlaceWriter {
     fn drop(&mut self) {
-        use copypasta_ext::prelude::*;
-        use copypasta_ext::x11_fork::ClipboardContext;
+        // After `finish`, the temporary file has already been renamed away
+        let _ = fs::remove_file(&self.temp_path);
+    }
+}
+
+#[derive(Args, Debug, Default)]
+struct TextOpt {
+    /// Remove emoji and other symbols (like © and ™) before SpOnGiFying.
+    #[arg(long, conflicts_with = "skip_emoji_ticks")]
+    strip_emoji: bool,
+
+    /// Keep emoji and other symbols, but do not let them advance the capitalization pattern.
+    #[arg(long)]
+    skip_emoji_ticks: bool,
+
+    /// Shuffle the words of each line before SpOnGiFying. With the default `--tokenize whitespace`, punctuation stays
+    /// attached to the word it is next to and the whitespace between words becomes a single space. Use `--seed` for a
+    /// repeatable shuffle.
+    #[arg(long)]
+    shuffle_words: bool,
+
+    /// The order to change each line in, as a comma-separated list of transforms: `shuffle-words` and `spongify`,
+    /// which must be named exactly once. Transforms before `spongify` change the line before it is SpOnGiFied, and ones
+    /// after it change the SpOnGiFied line. Naming `shuffle-words` turns it on, in place of `--shuffle-words`.
+    #[arg(long, value_name = "TRANSFORMS", conflicts_with = "shuffle_words")]
+    transform_order: Option<TransformOrder>,
+
+    /// How to find the words in a line for word-based options. `unicode-words` leaves punctuation and spacing where
+    /// they are, and splits text without spaces (like Chinese) into its ideographs.
+    #[arg(long, value_enum, default_value_t = Tokenizer::Whitespace)]
+    tokenize: Tokenizer,
+
+    /// Only SpOnGiFy odd-numbered lines, leaving even-numbered lines as they are. Lines left as they are do not advance
+    /// the capitalization pattern.
+    #[arg(long)]
+    alternate_lines: bool,
+
+    /// With `--alternate-lines`, SpOnGiFy the even-numbered lines instead of the odd-numbered ones.
+    #[arg(long, requires = "alternate_lines")]
+    alternate_even: bool,
+
+    /// Replace invalid UTF-8 in the input with U+FFFD (�) instead of stopping with an error.
+    #[arg(long)]
+    lossy_utf8: bool,
+
+    /// Start the capitalization pattern over at each line, so every line begins the same way. By default, the pattern
+    /// continues from the end of one line to the start of the next, with the line break counting as a character.
+    #[arg(long)]
+    reset_per_line: bool,
+
+    /// How to end each line of the output. `keep` ends each line the way it ended in the input, including leaving off
+    /// the newline if the last line had none; `lf` and `crlf` end every line with that terminator.
+    #[arg(long, value_enum, default_value_t = LineEnding::Keep)]
+    line_ending: LineEnding,
+}
+
+/// The choices for `--line-ending`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+enum LineEnding {
+    Lf,
+    Crlf,
+    #[default]
+    Keep,
+}
+
+impl LineEnding {
+    /// Get the terminator to end a line with, which ended with `original` in the input.
+    fn terminator(self, original: &'static str) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+            Self::Keep => original,
+        }
+    }
+}
+
+impl TextOpt {
+    /// Get the order to change each line in: `--transform-order`, or SpOnGiFying after `--shuffle-words` (if given).
+    fn transform_order(&self) -> TransformOrder {
+        if let Some(ref order) = self.transform_order {
+            return order.clone();
+        }
+        let mut steps = Vec::new();
+        if self.shuffle_words {
+            steps.push(TransformStep::ShuffleWords);
+        }
+        steps.push(TransformStep::Spongify);
+        TransformOrder::new(steps).expect("spongify is named once")
+    }
 
-        let goal = String::from_utf8_lossy(&self.contents[..]).to_string();
-        let mut ctx = ClipboardContext::new().unwrap();
-        ctx.set_contents(goal).unwrap();
+    /// Check if the line at the zero-based `line_index` should be left as it is because of `--alternate-lines`.
+    fn is_verbatim_line(&self, line_index: usize) -> bool {
+        // Line numbers are one-based, so odd-numbered lines have even indices
+        self.alternate_lines && line_index.is_multiple_of(2) == self.alternate_even
     }
 }
 
-#[derive(Parser, Debug)]
-struct Opt {
-    #[command(flatten)]
-    input: InputOpt,
+/// Check if `grapheme` is an emoji or another symbol like © or ™.
+///
+/// ASCII characters which can start emoji sequences (like the digits in keycap sequences) are only considered emoji
+/// when they are part of such a sequence.
+fn is_emoji(grapheme: &str) -> bool {
+    let mut chars = grapheme.chars();
+    let Some(first) = chars.next() else {
+        return false;
+    };
 
-    #[command(flatten)]
-    output: OutputOpt,
+    let is_sequence = grapheme.chars().any(|c| c == '\u{fe0f}' || c == '\u{20e3}');
+    let is_emoji_char = !first.is_ascii() && first.is_emoji_char();
+    is_sequence || is_emoji_char || first.general_category() == GeneralCategory::OtherSymbol
+}
 
-    /// The capitalization style to use. Can be "LiKe tHiS", "LiKe ThIs", "lIkE ThIs", "lIkE tHiS", or "RaNDOmlY"
-    /// (capitalization matters for everything but "raNdOMLy"). Is this an annoying way to specify an argument? Yes.
-    #[arg(long, default_value_t = CapitalizationStrategy::AlternatingInitialUppercase)]
-    style: CapitalizationStrategy,
+/// How the caption of an image is cased, for `--caption-case`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+enum CaptionCase {
+    /// Leave the caption as it was written.
+    AsIs,
+    /// Make the caption all uppercase, for the classic meme look.
+    Upper,
+    /// Make the caption all lowercase.
+    Lower,
+    /// SpOnGiFy the caption with the chosen `--style`.
+    #[default]
+    Spongify,
 }
 
-impl fmt::Display for Opt {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{self:?}")
+/// The choices for `--region-valign`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum RegionValign {
+    Top,
+    Center,
+    Bottom,
+}
+
+impl From<RegionValign> for RegionAlign {
+    fn from(value: RegionValign) -> Self {
+        match value {
+            RegionValign::Top => Self::Top,
+            RegionValign::Center => Self::Center,
+            RegionValign::Bottom => Self::Bottom,
+        }
     }
 }
 
-fn main() -> Result<()> {
-    use io::BufRead;
+/// The choices for `--outline-style`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum OutlineShape {
+    Square,
+    Round,
+}
 
-    let opt = Opt::parse();
+impl From<OutlineShape> for OutlineStyle {
+    fn from(value: OutlineShape) -> Self {
+        match value {
+            OutlineShape::Square => Self::Square,
+            OutlineShape::Round => Self::Round,
+        }
+    }
+}
+
+/// The choices for `--flip`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum FlipDirection {
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl From<FlipDirection> for Flip {
+    fn from(value: FlipDirection) -> Self {
+        match value {
+            FlipDirection::Horizontal => Self::Horizontal,
+            FlipDirection::Vertical => Self::Vertical,
+            FlipDirection::Both => Self::Both,
+        }
+    }
+}
 
-    let input = InputSpec::from(opt.input).into_reader()?;
-    let (mut output, newline) = opt.output.get_writer()?;
-    let mut capitalizer = opt.style.create_engine();
+/// The choices for `--gravity`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum CaptionGravity {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
 
-    let mut first = true;
-    for line in input.lines() {
-        let line = line?;
+impl From<CaptionGravity> for Gravity {
+    fn from(value: CaptionGravity) -> Self {
+        match value {
+            CaptionGravity::TopLeft => Self::TopLeft,
+            CaptionGravity::Top => Self::Top,
+            CaptionGravity::TopRight => Self::TopRight,
+            CaptionGravity::Left => Self::Left,
+            CaptionGravity::Center => Self::Center,
+            CaptionGravity::Right => Self::Right,
+            CaptionGravity::BottomLeft => Self::BottomLeft,
+            CaptionGravity::Bottom => Self::Bottom,
+            CaptionGravity::BottomRight => Self::BottomRight,
+        }
+    }
+}
 
-        if !newline {
-            if first {
-                first = false;
-            } else {
-                write!(output, " ")?;
-            }
+/// The choices for `--supersample`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum Supersample {
+    #[value(name = "1")]
+    X1,
+    #[value(name = "2")]
+    X2,
+    #[value(name = "4")]
+    X4,
+}
+
+impl Supersample {
+    /// How many times the image's resolution captions are rendered at.
+    fn factor(self) -> u32 {
+        match self {
+            Self::X1 => 1,
+            Self::X2 => 2,
+            Self::X4 => 4,
         }
+    }
+}
 
-        for (idx, c) in line.chars().enumerate() {
-            if capitalizer.should_capitalize(idx, c) {
-                write!(output, "{}", c.to_uppercase())?;
-            } else {
-                write!(output, "{}", c.to_lowercase())?;
-            }
+/// The choices for `--fit`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum FitMode {
+    /// Fit both the width and the height of the caption region.
+    Region,
+    /// Wrap to the width of the region, letting the caption grow taller and over the image.
+    Width,
+    /// Keep to the height of the region, without wrapping.
+    Height,
+}
+
+impl From<FitMode> for CaptionFit {
+    fn from(value: FitMode) -> Self {
+        match value {
+            FitMode::Region => Self::Region,
+            FitMode::Width => Self::Width,
+            FitMode::Height => Self::Height,
         }
+    }
+}
 
-        if newline {
-            writeln!(output)?;
+/// The choices for `--text-direction`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum DirectionMode {
+    /// Right to left if the caption starts in a right-to-left script, like Arabic or Hebrew.
+    Auto,
+    /// Left to right.
+    Ltr,
+    /// Right to left.
+    Rtl,
+}
+
+impl From<DirectionMode> for TextDirection {
+    fn from(value: DirectionMode) -> Self {
+        match value {
+            DirectionMode::Auto => Self::Auto,
+            DirectionMode::Ltr => Self::Ltr,
+            DirectionMode::Rtl => Self::Rtl,
         }
     }
+}
 
-    Ok(())
+/// The choices for `--caption-baseline-snap`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum SnapMode {
+    /// Move each glyph up and left to the pixel it starts in.
+    Floor,
+    /// Move each glyph to the nearest pixel.
+    Round,
+}
+
+impl From<SnapMode> for PixelSnap {
+    fn from(value: SnapMode) -> Self {
+        match value {
+            SnapMode::Floor => Self::Floor,
+            SnapMode::Round => Self::Round,
+        }
+    }
+}
+
+/// The choices for `--profile`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum RenderProfile {
+    /// No supersampling, no kerning, and no outline.
+    Fast,
+    /// `--supersample 2`, kerning, and the default 2 pixel `--outline`.
+    Balanced,
+    /// `--supersample 4`, kerning, and a 2 pixel `--outline`.
+    Pretty,
+}
+
+/// The width of the outline drawn around caption text without `--outline`, so the text stands out from light images.
+const DEFAULT_OUTLINE_WIDTH: u32 = 2;
+
+/// The image quality settings a `--profile` stands for, before the flags which override them.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct ProfileSettings {
+    supersample: Option<Supersample>,
+    kerning: bool,
+    /// The width of the outline in pixels, where 0 is no outline.
+    outline: u32,
+}
+
+/// Without a `--profile`, each setting is the default of its flag.
+impl Default for ProfileSettings {
+    fn default() -> Self {
+        Self {
+            supersample: None,
+            kerning: true,
+            outline: DEFAULT_OUTLINE_WIDTH,
+        }
+    }
+}
+
+impl RenderProfile {
+    fn settings(self) -> ProfileSettings {
+        match self {
+            Self::Fast => ProfileSettings {
+                supersample: Some(Supersample::X1),
+                kerning: false,
+                outline: 0,
+            },
+            Self::Balanced => ProfileSettings {
+                supersample: Some(Supersample::X2),
+                kerning: true,
+                outline: DEFAULT_OUTLINE_WIDTH,
+            },
+            Self::Pretty => ProfileSettings {
+                supersample: Some(Supersample::X4),
+                kerning: true,
+                outline: 2,
+            },
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+struct ImageOpt {
+    /// Put the entire caption at the top of the image instead of splitting it between the top and bottom.
+    #[arg(long, requires = "image", conflicts_with = "bottom_only")]
+    top_only: bool,
+
+    /// Put the entire caption at the bottom of the image instead of splitting it between the top and bottom.
+    #[arg(long, requires = "image")]
+    bottom_only: bool,
+
+    /// Use this text as the caption instead of reading it from the input.
+    #[arg(long, requires = "image", group = "caption_source", conflicts_with_all = ["inline", "input"])]
+    caption: Option<String>,
+
+    /// Read the caption from this file instead of from the input.
+    #[arg(long, requires = "image", group = "caption_source", conflicts_with_all = ["inline", "input"])]
+    caption_file: Option<PathBuf>,
+
+    /// Use the text on the clipboard as the caption instead of reading it from the input, like for captioning a
+    /// `--base-image` read from standard input.
+    #[arg(
+        long,
+        visible_alias = "caption-text-from-clipboard",
+        requires = "image",
+        group = "caption_source",
+        conflicts_with_all = ["inline", "input"]
+    )]
+    caption_from_clip: bool,
+
+    /// Use this text as the top caption, instead of splitting a caption from the input between the top and bottom.
+    #[arg(
+        long,
+        requires = "image",
+        conflicts_with_all = ["caption_source", "inline", "input", "top_only", "bottom_only"]
+    )]
+    top: Option<String>,
+
+    /// Use this text as the bottom caption, instead of splitting a caption from the input between the top and bottom.
+    #[arg(
+        long,
+        requires = "image",
+        conflicts_with_all = ["caption_source", "inline", "input", "top_only", "bottom_only"]
+    )]
+    bottom: Option<String>,
+
+    /// Make one image for each of the first N lines of standard input, captioned with that line, instead of one image
+    /// for the whole input. Each image is written to `--output-file` with `{n}` replaced by its line number, or to
+    /// `out-{n}.png` (with the extension of `--image-format`) without one.
+    #[arg(
+        long,
+        requires = "image",
+        value_name = "N",
+        conflicts_with_all = ["caption_source", "top", "bottom", "inline", "text", "file", "url"]
+    )]
+    caption_from_stdin_lines: Option<NonZeroUsize>,
+
+    /// Caption this image instead of the built-in one. Use "-" to read the image from standard input, which needs the
+    /// caption to come from `--caption`, `--caption-file`, or `--caption-from-clip`.
+    #[arg(
+        long,
+        visible_alias = "background",
+        requires = "image",
+        value_name = "PATH"
+    )]
+    base_image: Option<PathBuf>,
+
+    /// How to case the caption. Anything but `spongify` ignores `--style`.
+    #[arg(long, requires = "image", value_enum, default_value_t = CaptionCase::Spongify)]
+    caption_case: CaptionCase,
+
+    /// Keep the caption as it was written instead of SpOnGiFying it, like `--caption-case as-is`.
+    #[arg(long, requires = "image", conflicts_with = "caption_case")]
+    no_spongify_caption: bool,
+
+    /// The quality of lossy image formats like JPEG, from 1 (smallest) to 100 (best looking).
+    #[arg(long, requires = "image", value_parser = clap::value_parser!(u8).range(1..=100))]
+    image_quality: Option<u8>,
+
+    /// Render the caption with this TrueType or OpenType font instead of the built-in Anton. Fonts for scripts (like
+    /// --font-latin) still take over their characters.
+    #[arg(long, requires = "image")]
+    font: Option<PathBuf>,
+
+    /// Render Latin characters in the caption with this TrueType or OpenType font.
+    #[arg(long, requires = "image")]
+    font_latin: Option<PathBuf>,
+
+    /// Render Chinese, Japanese, and Korean characters in the caption with this TrueType or OpenType font.
+    #[arg(long, requires = "image")]
+    font_cjk: Option<PathBuf>,
+
+    /// Render emoji in the caption with this font. Color emoji fonts with bitmap glyphs (like Noto Color Emoji) are
+    /// drawn in color.
+    #[arg(long, requires = "image")]
+    font_emoji: Option<PathBuf>,
+
+    /// Move the top caption down by this many pixels (or up, if negative).
+    #[arg(
+        long,
+        requires = "image",
+        default_value_t = 0,
+        allow_negative_numbers = true,
+        value_name = "PX"
+    )]
+    top_offset: i32,
+
+    /// Move the bottom caption down by this many pixels (or up, if negative).
+    #[arg(
+        long,
+        requires = "image",
+        default_value_t = 0,
+        allow_negative_numbers = true,
+        value_name = "PX"
+    )]
+    bottom_offset: i32,
+
+    /// Add this many pixels of space between the letters of captions. Negative spacing pulls letters closer together.
+    #[arg(
+        long,
+        requires = "image",
+        default_value_t = 0.,
+        allow_negative_numbers = true,
+        value_name = "PX"
+    )]
+    letter_spacing: f32,
+
+    /// Space the caption's letters by their widths alone, without the font's kerning (which tucks pairs like "AV"
+    /// together), for a more even, monospace-like look.
+    #[arg(long, requires = "image")]
+    no_kerning: bool,
+
+    /// Kern the caption even though `--profile fast` turns kerning off.
+    #[arg(long, requires = "image", conflicts_with = "no_kerning")]
+    kerning: bool,
+
+    /// Spread out the words of wrapped caption lines so both sides of the caption line up, like a block of text.
+    #[arg(long, requires = "image")]
+    justify: bool,
+
+    /// Split words which do not fit at the end of a caption line with a hyphen, between syllables where possible,
+    /// instead of moving the whole word down (or cutting it off wherever it reaches the edge, if it is wider than the
+    /// caption). Syllables come from the hyphenation patterns of `--hyphenate-lang`.
+    #[arg(long, requires = "image")]
+    hyphenate: bool,
+
+    /// The language to find syllables in for `--hyphenate`, as a tag like "en-US" or "de". Only the language matters,
+    /// so "en-GB" splits words the same way "en-US" does.
+    #[arg(
+        long,
+        requires = "hyphenate",
+        default_value = "en-US",
+        value_parser = parse_hyphenation_lang,
+        value_name = "LANG"
+    )]
+    hyphenate_lang: hypher::Lang,
+
+    /// Indent the lines a caption wraps onto by this many pixels.
+    #[arg(long, requires = "image", default_value_t = 0., value_name = "PX")]
+    wrap_indent: f32,
+
+    /// Which way the caption reads. Right-to-left captions are aligned to the right edge of the image, with runs of
+    /// left-to-right text (like numbers) kept in their order within the line. `auto` reads right to left if the first
+    /// letter with a direction is from a right-to-left script.
+    #[arg(long, requires = "image", value_enum, default_value_t = DirectionMode::Auto)]
+    text_direction: DirectionMode,
+
+    /// Shrink the font until each caption fits in this many lines. If a caption does not fit even at half the normal
+    /// size, the end of it is cut off with an ellipsis.
+    #[arg(long, requires = "image", value_name = "N")]
+    caption_max_lines: Option<NonZeroUsize>,
+
+    /// Scale the distance between the lines of a caption by this factor. Defaults to 1, the font's own spacing.
+    #[arg(long, requires = "image", value_name = "FACTOR")]
+    line_spacing: Option<f32>,
+
+    /// Choose the image quality settings all at once, trading speed for looks. `fast` turns off supersampling,
+    /// kerning, and the outline; `balanced` uses `--supersample 2` with kerning and the default outline; `pretty` uses
+    /// `--supersample 4` with kerning and a 2 pixel `--outline`. Captions are never dithered, so there is no dithering
+    /// for a profile to set. `--supersample`, `--outline`, `--kerning`, and `--no-kerning` override the profile.
+    #[arg(long, requires = "image", value_enum)]
+    profile: Option<RenderProfile>,
+
+    /// Render captions at this many times the image's resolution and scale them down, for smoother edges.
+    #[arg(long, requires = "image", value_name = "FACTOR")]
+    supersample: Option<Supersample>,
+
+    /// Move each glyph of the caption to a whole pixel of the image before drawing it. With `--supersample`, this lines
+    /// glyphs up with the image's pixels instead of the larger render's. By default, glyphs stay where they are laid
+    /// out.
+    #[arg(long, requires = "image", value_enum)]
+    caption_baseline_snap: Option<SnapMode>,
+
+    /// Make the caption text heavier, for fonts without a bold weight.
+    #[arg(long, requires = "image")]
+    bold: bool,
+
+    /// Draw the caption text with this opacity, from 0.0 (invisible) to 1.0 (opaque), for watermark-style captions.
+    #[arg(long, requires = "image", value_parser = parse_fraction::<f32>, value_name = "OPACITY")]
+    caption_opacity: Option<f32>,
+
+    /// Blur the image behind the captions with this radius in pixels, to make them easier to read.
+    #[arg(long, requires = "image", default_value_t = 0., value_name = "RADIUS")]
+    caption_bg_blur: f32,
+
+    /// Where captions sit vertically within their region (a quarter of the image tall). By default, the top caption
+    /// hugs the top of the image and the bottom caption hugs the bottom.
+    #[arg(long, requires = "image", value_enum)]
+    region_valign: Option<RegionValign>,
+
+    /// Draw an outline this many pixels wide (up to 64) around the caption text, in the `--outline-color`. Defaults
+    /// to 2 pixels, or to the width of the `--profile`; 0 draws no outline.
+    #[arg(
+        long,
+        visible_alias = "outline-width",
+        requires = "image",
+        value_name = "PX",
+        value_parser = clap::value_parser!(u32).range(0..=64)
+    )]
+    outline: Option<u32>,
+
+    /// The color of the caption text, as `#rrggbb`, `#rrggbbaa`, or a name like "yellow". Defaults to white.
+    #[arg(long, requires = "image", value_parser = imagemacro::parse_color, value_name = "COLOR")]
+    text_color: Option<image::Rgba<u8>>,
+
+    /// The color of `--outline`, written like `--text-color`. Defaults to black. With a `--profile` which has no
+    /// outline, this draws a 2 pixel outline anyway.
+    #[arg(long, requires = "image", value_parser = imagemacro::parse_color, value_name = "COLOR")]
+    outline_color: Option<image::Rgba<u8>>,
+
+    /// The shape of the corners of `--outline`.
+    #[arg(long, requires = "image", value_enum, default_value_t = OutlineShape::Round)]
+    outline_style: OutlineShape,
+
+    /// Draw only the outline of the caption text, in the `--text-color`, leaving the insides of the letters
+    /// see-through. The outline is as wide as `--outline`, or 2 pixels without it.
+    #[arg(long, requires = "image")]
+    hollow_text: bool,
+
+    /// Which dimensions of the caption region (a quarter of the image tall) the caption is laid out to fit in.
+    #[arg(long, requires = "image", value_enum, default_value_t = FitMode::Region)]
+    fit: FitMode,
+
+    /// Mirror the image. The captions still read normally unless `--flip-text` is given.
+    #[arg(long, requires = "image", value_enum)]
+    flip: Option<FlipDirection>,
+
+    /// With `--flip`, mirror the captions along with the image.
+    #[arg(long, requires = "flip")]
+    flip_text: bool,
+
+    /// Put the whole caption in the largest space above or below the face in the image, so it does not cover the face.
+    /// Faces are found by their skin tone. If there is no face, captions go at the top and bottom as usual.
+    #[arg(long, requires = "image", conflicts_with_all = ["top_only", "bottom_only"])]
+    smart_placement: bool,
+
+    /// Put the whole caption at this point of the image, aligned to match: a corner, the middle of an edge, or the
+    /// center. `--gravity top` and `--gravity bottom` are like `--top-only` and `--bottom-only`.
+    #[arg(
+        long,
+        requires = "image",
+        value_enum,
+        conflicts_with_all = ["top_only", "bottom_only", "smart_placement", "center_columns"]
+    )]
+    gravity: Option<CaptionGravity>,
+
+    /// Keep a caption placed with `--gravity` this many pixels away from the edges of the image.
+    #[arg(long, requires = "gravity", default_value_t = 0, value_name = "PX")]
+    gravity_margin: u32,
+
+    /// Print a warning when a caption is empty or only whitespace. Empty captions are never drawn.
+    #[arg(long, requires = "image")]
+    warn_empty_caption: bool,
+
+    /// Write where each glyph of the captions is laid out to this file as JSON: its character, the top left corner of
+    /// it in the image, and its width and height in pixels. This is for debugging layouts or drawing the captions with
+    /// another renderer.
+    #[arg(
+        long,
+        requires = "image",
+        value_name = "PATH",
+        conflicts_with = "caption_from_stdin_lines"
+    )]
+    export_layout: Option<PathBuf>,
+
+    /// Put the caption in the regions of a JSON template instead of at the top and bottom: a list of objects like
+    /// `{"rect": [x, y, width, height], "align": "left", "color": "#ffff00"}`, where `align` and `color` are optional.
+    /// Each line of the caption goes in the next region, and the last region gets the rest.
+    #[arg(
+        long,
+        requires = "image",
+        value_name = "PATH",
+        conflicts_with_all = ["top_only", "bottom_only", "smart_placement", "gravity"]
+    )]
+    template_json: Option<PathBuf>,
+
+    /// Draw borders around the top (red) and bottom (blue) caption regions, to show where the captions can go.
+    #[arg(long, requires = "image")]
+    debug_regions: bool,
+
+    /// Round the corners of the image to this radius in pixels. The corners are made transparent, so this is only
+    /// useful with formats that support transparency, like PNG.
+    #[arg(long, requires = "image", value_name = "RADIUS")]
+    round: Option<u32>,
+
+    /// With `--image-format svg`, leave the base image out, for just the captions.
+    #[arg(long, requires = "image")]
+    svg_no_image: bool,
+
+    /// Center caption lines by their display width in columns, where wide characters (like CJK) count double, rather
+    /// than by the width of the rendered glyphs.
+    #[arg(long, requires = "image")]
+    center_columns: bool,
+
+    /// Draw the image in the terminal. Unless `--output-file` is given, the preview is written instead of the image.
+    #[cfg(feature = "preview")]
+    #[arg(long, requires = "image")]
+    preview_image: bool,
+}
+
+impl ImageOpt {
+    pub fn placement(&self) -> CaptionPlacement {
+        // A caption placed by gravity or a template is kept whole
+        if self.top_only || self.gravity.is_some() || self.template_json.is_some() {
+            CaptionPlacement::TopOnly
+        } else if self.bottom_only {
+            CaptionPlacement::BottomOnly
+        } else {
+            CaptionPlacement::Split
+        }
+    }
+
+    /// Check if the caption was given with `--caption`, `--caption-file`, `--caption-from-clip`, `--top`, or
+    /// `--bottom`, so the input is not read.
+    pub fn has_caption(&self) -> bool {
+        self.caption.is_some()
+            || self.caption_file.is_some()
+            || self.caption_from_clip
+            || self.top.is_some()
+            || self.bottom.is_some()
+    }
+
+    /// How the caption is cased: `--caption-case`, or as it was written with `--no-spongify-caption`.
+    fn case(&self) -> CaptionCase {
+        if self.no_spongify_caption {
+            CaptionCase::AsIs
+        } else {
+            self.caption_case
+        }
+    }
+
+    /// Get the top and bottom captions, cased according to `--caption-case`. These are `--top` and `--bottom` if either
+    /// was given, or else the caption from `--caption`, `--caption-file`, or `input` split by the `placement`.
+    pub fn captions(
+        &self,
+        input: Box<dyn io::BufRead>,
+        engine: &mut LineEngine,
+        stats: &mut Stats,
+        timings: &mut Timings,
+    ) -> Result<(Option<String>, Option<String>)> {
+        if self.top.is_none() && self.bottom.is_none() {
+            let input = self.caption_reader()?.unwrap_or(input);
+            let text = read_caption(input, self.case(), engine, stats, timings)?;
+            return Ok(self.placement().split(&text));
+        }
+
+        let mut caption = |text: &Option<String>| -> Result<Option<String>> {
+            let Some(text) = text else {
+                return Ok(None);
+            };
+            let input = Box::new(io::Cursor::new(text.clone()));
+            let caption = read_caption(input, self.case(), engine, stats, timings)?;
+            Ok(Some(caption.trim_end_matches('\n').to_owned()))
+        };
+        Ok((caption(&self.top)?, caption(&self.bottom)?))
+    }
+
+    /// Get the top and bottom captions for each of `lines`, for `--caption-from-stdin-lines`. Each line is cased
+    /// according to `--caption-case` and split like a caption from the input.
+    pub fn line_captions(
+        &self,
+        lines: &[String],
+        engine: &mut LineEngine,
+        stats: &mut Stats,
+        timings: &mut Timings,
+    ) -> Result<Vec<(Option<String>, Option<String>)>> {
+        lines
+            .iter()
+            .map(|line| {
+                let input = io::Cursor::new(line.as_bytes());
+                let text = read_caption(input, self.case(), engine, stats, timings)?;
+                Ok(self.placement().split(&text))
+            })
+            .collect()
+    }
+
+    /// Get a reader for the caption if it was given with `--caption`, `--caption-file`, or `--caption-from-clip`,
+    /// rather than in the input.
+    pub fn caption_reader(&self) -> Result<Option<Box<dyn io::BufRead>>> {
+        self.caption_reader_with(read_clipboard)
+    }
+
+    /// Like `caption_reader`, but with the clipboard's text for `--caption-from-clip` from `read_clipboard`.
+    fn caption_reader_with(
+        &self,
+        read_clipboard: impl FnOnce() -> Result<String>,
+    ) -> Result<Option<Box<dyn io::BufRead>>> {
+        if self.caption_from_clip {
+            let caption = read_clipboard()?;
+            if caption.trim().is_empty() {
+                return Err(
+                    "--caption-from-clip needs text on the clipboard, but it is empty".into(),
+                );
+            }
+            Ok(Some(Box::new(io::Cursor::new(caption))))
+        } else if let Some(ref caption) = self.caption {
+            Ok(Some(Box::new(io::Cursor::new(caption.clone()))))
+        } else if let Some(ref path) = self.caption_file {
+            let file = fs::File::open(path)
+                .map_err(|e| format!("could not read caption file {}: {e}", path.display()))?;
+            Ok(Some(Box::new(io::BufReader::new(file))))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Load the `--base-image`, if one was given.
+    pub fn load_base_image(&self) -> Result<Option<image::RgbaImage>> {
+        let Some(ref path) = self.base_image else {
+            return Ok(None);
+        };
+
+        let source = if path.as_os_str() == "-" {
+            if !self.has_caption() {
+                return Err(
+                    "--base-image - needs the caption from --caption, --caption-file, --caption-from-clip, --top, or --bottom"
+                        .into(),
+                );
+            }
+            let mut source = Vec::new();
+            io::Read::read_to_end(&mut io::stdin(), &mut source)?;
+            source
+        } else {
+            fs::read(path)
+                .map_err(|e| format!("could not read base image {}: {e}", path.display()))?
+        };
+        let image = image::load_from_memory(&source)
+            .map_err(|e| format!("could not load base image {}: {e}", path.display()))?;
+        Ok(Some(image.into_rgba8()))
+    }
+
+    pub fn render_options(&self) -> Result<RenderOptions> {
+        let mut script_fonts = Vec::new();
+        for (class, path) in [
+            (ScriptClass::Latin, &self.font_latin),
+            (ScriptClass::Cjk, &self.font_cjk),
+        ] {
+            if let Some(path) = path {
+                script_fonts.push((class, load_font(path)?.1));
+            }
+        }
+
+        let mut color_emoji = None;
+        if let Some(ref path) = self.font_emoji {
+            let (source, font) = load_font(path)?;
+            script_fonts.push((ScriptClass::Emoji, font));
+            color_emoji = Some(source);
+        }
+
+        let profile = self
+            .profile
+            .map(RenderProfile::settings)
+            .unwrap_or_default();
+        Ok(RenderOptions {
+            base_image: None,
+            centering: if self.center_columns {
+                CaptionCentering::Columns
+            } else {
+                CaptionCentering::Advance
+            },
+            font: match self.font {
+                Some(ref path) => Some(load_font(path)?.0),
+                None => None,
+            },
+            script_fonts,
+            color_emoji,
+            corner_radius: self.round,
+            top_offset: self.top_offset,
+            bottom_offset: self.bottom_offset,
+            letter_spacing: self.letter_spacing,
+            no_kerning: self.no_kerning || !self.kerning && !profile.kerning,
+            justify: self.justify,
+            hyphenate: self.hyphenate.then_some(self.hyphenate_lang),
+            wrap_indent: self.wrap_indent,
+            line_spacing: self.line_spacing,
+            pixel_snap: self.caption_baseline_snap.map(Into::into),
+            text_direction: self.text_direction.into(),
+            supersample: self
+                .supersample
+                .or(profile.supersample)
+                .map(Supersample::factor),
+            max_lines: self.caption_max_lines.map(NonZeroUsize::get),
+            bold: self.bold,
+            text_color: self.text_color,
+            caption_opacity: self.caption_opacity,
+            caption_bg_blur: self.caption_bg_blur,
+            region_valign: self.region_valign.map(Into::into),
+            outline: Some(match (self.outline, self.outline_color) {
+                (Some(width), _) => width,
+                // A color asks for an outline, even with a profile which has none
+                (None, Some(_)) if profile.outline == 0 => DEFAULT_OUTLINE_WIDTH,
+                (None, _) => profile.outline,
+            })
+            .filter(|&width| width > 0)
+            .map(|width| Outline {
+                width,
+                style: self.outline_style.into(),
+                color: self.outline_color.unwrap_or(imagemacro::OUTLINE_COLOR),
+            }),
+            hollow_text: self.hollow_text,
+            fit: self.fit.into(),
+            flip: self.flip.map(Into::into),
+            flip_text: self.flip_text,
+            smart_placement: self.smart_placement,
+            gravity: self.gravity.map(Into::into),
+            gravity_margin: self.gravity_margin,
+            debug_regions: self.debug_regions,
+            template: match self.template_json {
+                Some(ref path) => template::load_template(path)?,
+                None => Vec::new(),
+            },
+        })
+    }
+}
+
+/// Parse a fraction like `--caption-opacity` or `--random-bias`, which must be between 0 and 1.
+fn parse_fraction<T>(value: &str) -> std::result::Result<T, String>
+where
+    T: std::str::FromStr + PartialOrd + From<u8> + fmt::Display,
+    T::Err: fmt::Display,
+{
+    let fraction = value.parse::<T>().map_err(|e| e.to_string())?;
+    if (T::from(0)..=T::from(1)).contains(&fraction) {
+        Ok(fraction)
+    } else {
+        Err(format!("{fraction} is not between 0.0 and 1.0"))
+    }
+}
+
+/// Parse a language tag like "en-US" for `--hyphenate-lang` into the language whose hyphenation patterns to use.
+fn parse_hyphenation_lang(value: &str) -> std::result::Result<hypher::Lang, String> {
+    let language = value.split(['-', '_']).next().unwrap_or_default();
+    let code = match language.to_ascii_lowercase().as_bytes() {
+        &[first, second] => [first, second],
+        _ => return Err(format!("{value:?} is not a language tag like \"en-US\"")),
+    };
+    hypher::Lang::from_iso(code)
+        .ok_or_else(|| format!("there are no hyphenation patterns for {value:?}"))
+}
+
+/// Load the font file at `path`, returning its source along with the font.
+fn load_font(path: &Path) -> Result<(Vec<u8>, fontdue::Font)> {
+    let source =
+        fs::read(path).map_err(|e| format!("could not read font {}: {e}", path.display()))?;
+    let font = imagemacro::load_font(&source)
+        .map_err(|e| format!("could not load font {}: {e}", path.display()))?;
+    Ok((source, font))
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    after_help = "Run `spongify examples` to see example runs and what they print.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Opt {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    input: InputOpt,
+
+    #[command(flatten)]
+    output: OutputOpt,
+
+    #[command(flatten)]
+    text: TextOpt,
+
+    #[command(flatten)]
+    image: ImageOpt,
+
+    /// The capitalization style to use. Can be "LiKe tHiS", "LiKe ThIs", "lIkE ThIs", "lIkE tHiS", "like THIS",
+    /// "Like This", "RaNDOmlY", "RAnDOmlY pEr WoRD", or "leet" (capitalization matters for everything but the random
+    /// ones and "leet"). "like THIS" alternates whole words instead of letters. "Like This" capitalizes the first
+    /// letter of each word. "RAnDOmlY pEr WoRD" is random, but capitalizes every occurrence of a word the same way.
+    /// These find words as `--tokenize unicode-words` does, so "don't" is one word and "taco-truck" is two. "leet" is
+    /// like "LiKe tHiS", but writes a, e, i, o, s, and t as 4, 3, 1, 0, 5, and 7. Is this an annoying way to specify an
+    /// argument? Yes.
+    #[arg(long, env = "SPONGIFY_STYLE", default_value_t = CapitalizationStrategy::AlternatingInitialUppercase)]
+    style: CapitalizationStrategy,
+
+    /// How likely "RaNDOmlY" is to uppercase each letter, from 0.0 (never) to 1.0 (always). Defaults to 0.5.
+    #[arg(long, value_parser = parse_fraction::<f64>, value_name = "PROBABILITY")]
+    random_bias: Option<f64>,
+
+    /// Capitalize with a custom pattern of `u` (uppercase) and `l` (lowercase), like "uul". The pattern repeats for as
+    /// long as there is text. Overrides `--style`.
+    #[arg(long, group = "pattern_source")]
+    pattern: Option<Pattern>,
+
+    /// Load a `--pattern` from a file. Whitespace and newlines in the file are ignored.
+    #[arg(long, group = "pattern_source")]
+    pattern_file: Option<PathBuf>,
+
+    /// Seed the random number generator, so random choices (like `--shuffle-words` and `--style RaNDOmlY`) are the same
+    /// from run to run.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Read the `--seed` from this file. If the file does not exist, a random seed is used and saved to it, so later
+    /// runs with the same file make the same random choices.
+    #[arg(long, conflicts_with = "seed", value_name = "PATH")]
+    seed_file: Option<PathBuf>,
+
+    /// SpOnGiFy lines in parallel. Every line starts the capitalization pattern fresh, but the output is in the same
+    /// order as the input and, with `--seed`, is the same no matter how many threads are used.
+    #[arg(long)]
+    parallel: bool,
+
+    /// The number of threads to use for `--parallel`. Defaults to the number of CPUs.
+    #[arg(long, requires = "parallel")]
+    engine_threads: Option<NonZeroUsize>,
+
+    /// Print counts of the characters that were changed to standard error.
+    #[arg(long)]
+    stats: bool,
+
+    /// Like `--stats`, but also count words and how they were cased.
+    #[arg(long)]
+    stats_verbose: bool,
+
+    /// How to print `--stats` and `--stats-verbose`.
+    #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+    stats_format: StatsFormat,
+
+    /// Print how long each stage of the run took to standard error.
+    #[arg(long)]
+    timing: bool,
+
+    /// Print how many bytes were read from the input and written to the output to standard error. These can differ,
+    /// since some letters take more bytes in one case than the other. Compressed output is counted before compression.
+    #[arg(long, conflicts_with = "mmap_output")]
+    count_bytes: bool,
+
+    /// Print how many random choices `--style randomly` made and the fraction of them which were to uppercase to
+    /// standard error, to characterize a run.
+    #[arg(long, conflicts_with = "parallel")]
+    entropy_report: bool,
+
+    /// Act as a filter (like a git `clean` or `smudge` filter): SpOnGiFy standard input to standard output, changing
+    /// nothing but the case of letters. Every other byte, including line endings and bytes which are not UTF-8, is kept
+    /// as it is, and input which looks like binary data is passed through untouched. Set the style with
+    /// `SPONGIFY_STYLE`.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "inline", "input", "output", "image", "shuffle_words", "strip_emoji", "parallel", "lossy_utf8"
+        ]
+    )]
+    filter: bool,
+
+    /// Highlight the characters which changed, for `spongify diff`.
+    #[arg(skip)]
+    diff: bool,
+
+    /// Print tracing spans for the stages of the run at this level ("error", "warn", "info", "debug", or "trace") and
+    /// above to standard error. Warnings are events at the "warn" level.
+    #[cfg(feature = "tracing")]
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<tracing::Level>,
+
+    /// Check the options without running: print every problem with them (like `--seed` when nothing is random) to
+    /// standard error, then exit, with an error if there were any. Nothing is read or written.
+    #[arg(long)]
+    validate: bool,
+}
+
+/// The subcommands, which do something other than SpOnGiFy.
+#[derive(Subcommand, Debug)]
+#[command(about = None, long_about = None)]
+enum Command {
+    /// Write a completion script for `shell` to standard output, like `spongify completions zsh > _spongify`.
+    #[command(hide = true)]
+    Completions { shell: clap_complete::Shell },
+    /// Run each job of a JSON manifest, like `[{"input": "a.txt", "output": "b.txt", "style": "lIkE ThIs"}]`, as its
+    /// own SpOnGiFy run. Each job reads its `input` file and writes its `output` file, and can pass any other options
+    /// in `args`. Relative paths are relative to the manifest.
+    Batch { manifest: PathBuf },
+    /// Print the SpOnGiFied text with the characters which changed highlighted: green for ones changed to uppercase
+    /// and yellow for ones changed to lowercase. Colors are left out if `NO_COLOR` is set. This takes the options of a
+    /// normal run, like `spongify diff --style "lIkE ThIs" "your text here"`.
+    #[command(disable_help_flag = true)]
+    Diff {
+        /// The options and text of the run. `spongify diff --help` lists the options.
+        #[arg(
+            trailing_var_arg = true,
+            allow_hyphen_values = true,
+            value_name = "ARGS"
+        )]
+        args: Vec<OsString>,
+    },
+    /// Print example runs of SpOnGiFy for each way of using it and what they print.
+    Examples,
+    /// List the fonts built into SpOnGiFy and the scripts they cover.
+    Fonts,
+}
+
+impl Opt {
+    /// Get the capitalization strategy, taking `--pattern` and `--pattern-file` into account.
+    pub fn strategy(&self) -> Result<CapitalizationStrategy> {
+        if let Some(ref pattern) = self.pattern {
+            Ok(CapitalizationStrategy::FromPattern(pattern.clone()))
+        } else if let Some(ref path) = self.pattern_file {
+            Ok(CapitalizationStrategy::FromPattern(load_pattern(path)?))
+        } else {
+            Ok(self.style.clone())
+        }
+    }
+
+    /// Get the seed for the random number generator from `--seed` or `--seed-file`, if there is one.
+    pub fn seed(&self) -> Result<Option<u64>> {
+        match self.seed_file {
+            Some(ref path) => load_seed(path).map(Some),
+            None => Ok(self.seed),
+        }
+    }
+
+    /// Check for options which would be ignored, like `--seed` when nothing is random. Normal runs warn about these,
+    /// and `--validate` reports them as errors. Options which conflict are already refused when the arguments are
+    /// parsed.
+    ///
+    /// # Return
+    /// A description of each problem.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let random_style = self.pattern.is_none()
+            && self.pattern_file.is_none()
+            && self.style == CapitalizationStrategy::Randomly;
+        let shuffles = self
+            .text
+            .transform_order()
+            .contains(TransformStep::ShuffleWords);
+
+        if !random_style && !shuffles {
+            if self.seed.is_some() {
+                problems
+                    .push("--seed only applies to --style RaNDOmlY and --shuffle-words".to_owned());
+            }
+            if self.seed_file.is_some() {
+                problems.push(
+                    "--seed-file only applies to --style RaNDOmlY and --shuffle-words".to_owned(),
+                );
+            }
+        }
+        if self.random_bias.is_some() && !random_style {
+            problems.push("--random-bias only applies to --style RaNDOmlY".to_owned());
+        }
+        if self.entropy_report && !random_style {
+            problems.push("--entropy-report only reports on --style randomly".to_owned());
+        }
+
+        if self.output.image {
+            #[cfg(feature = "preview")]
+            if self.image.preview_image && self.image.caption_from_stdin_lines.is_some() {
+                problems.push(
+                    "--preview-image does not apply to --caption-from-stdin-lines, ignoring it"
+                        .to_owned(),
+                );
+            }
+            match self.output.image_format() {
+                Ok(MacroFormat::Svg) => {
+                    if self.image.image_quality.is_some() {
+                        problems.push(
+                            "--image-quality only applies to lossy formats, ignoring it for SVG"
+                                .to_owned(),
+                        );
+                    }
+                    #[cfg(feature = "preview")]
+                    if self.image.preview_image {
+                        problems.push(
+                            "--preview-image does not apply to SVG output, ignoring it".to_owned(),
+                        );
+                    }
+                }
+                Ok(MacroFormat::Raster(format)) => {
+                    if self.image.svg_no_image {
+                        problems.push(format!(
+                            "--svg-no-image only applies to SVG output, ignoring it for {format:?}"
+                        ));
+                    }
+                    if self.image.image_quality.is_some() && !imagemacro::is_lossy(format) {
+                        problems.push(format!(
+                            "--image-quality only applies to lossy formats, ignoring it for {format:?}"
+                        ));
+                    }
+                    if self.image.round.is_some() && !imagemacro::supports_alpha(format) {
+                        problems.push(format!(
+                            "{format:?} does not support transparency, so --round corners will not be transparent"
+                        ));
+                    }
+                }
+                // An unknown format is an error once the image is written
+                Err(_) => {}
+            }
+        }
+        problems
+    }
+}
+
+/// Load a seed from the file at `path`, or create the file with a new random seed if it does not exist.
+fn load_seed(path: &Path) -> Result<u64> {
+    match fs::read_to_string(path) {
+        Ok(source) => {
+            let seed = source
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid seed file {}: {e}", path.display()))?;
+            Ok(seed)
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let seed = rand::random();
+            fs::write(path, format!("{seed}\n"))
+                .map_err(|e| format!("could not write seed file {}: {e}", path.display()))?;
+            Ok(seed)
+        }
+        Err(e) => Err(format!("could not read seed file {}: {e}", path.display()).into()),
+    }
+}
+
+/// Load a capitalization `Pattern` from the file at `path`.
+fn load_pattern(path: &Path) -> Result<Pattern> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("could not read pattern file {}: {e}", path.display()))?;
+    let pattern = source
+        .parse()
+        .map_err(|e| format!("invalid pattern file {}: {e}", path.display()))?;
+    Ok(pattern)
+}
+
+/// The choices for `--stats-format`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+enum StatsFormat {
+    /// One count per line, for people.
+    Text,
+    /// A line of JSON, for tools.
+    Json,
+}
+
+impl fmt::Display for Opt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Shuffle the order of the words in `line`, as found by `tokenizer`.
+///
+/// With `Tokenizer::Whitespace`, punctuation stays attached to the word it is written next to, leading whitespace is
+/// kept, and the whitespace between words becomes a single space. Other tokenizers only move the words, leaving
+/// everything between them in place.
+fn shuffle_words(line: &str, tokenizer: Tokenizer, rng: &mut StdRng) -> String {
+    if tokenizer == Tokenizer::Whitespace {
+        let trimmed = line.trim_start();
+        let indent = &line[..line.len() - trimmed.len()];
+
+        let mut words = tokenizer
+            .words(trimmed)
+            .map(|(_, word)| word)
+            .collect::<Vec<_>>();
+        words.shuffle(rng);
+        return format!("{indent}{}", words.join(" "));
+    }
+
+    let (ranges, mut words): (Vec<_>, Vec<_>) = tokenizer.words(line).unzip();
+    words.shuffle(rng);
+
+    let mut shuffled = String::with_capacity(line.len());
+    let mut end = 0;
+    for (range, word) in ranges.into_iter().zip(words) {
+        shuffled.push_str(&line[end..range.start]);
+        shuffled.push_str(word);
+        end = range.end;
+    }
+    shuffled.push_str(&line[end..]);
+    shuffled
+}
+
+/// Copy `line` to the output without SpOnGiFying it, for lines skipped by `--alternate-lines`.
+fn verbatim_line(line: &str, stats: &mut Stats) -> String {
+    stats.unchanged += line.chars().count();
+    stats.record_line(line);
+    line.to_owned()
+}
+
+/// The state of SpOnGiFying an input, which carries over from line to line.
+struct Spongifier<'a> {
+    text: &'a TextOpt,
+    capitalizer: &'a mut dyn CapitalizationEngine,
+    /// The transforms applied to each line before it is SpOnGiFied, like `--shuffle-words`.
+    before: Pipeline<'static>,
+    /// The transforms applied to each line after it is SpOnGiFied, from `--transform-order`.
+    after: Pipeline<'static>,
+    /// The index of the next grapheme cluster, counted across the whole input with each line break counting as one.
+    index: usize,
+    /// The zero-based index of the next line.
+    line_index: usize,
+}
+
+impl<'a> Spongifier<'a> {
+    pub fn new(
+        text: &'a TextOpt,
+        capitalizer: &'a mut dyn CapitalizationEngine,
+        rng: StdRng,
+    ) -> Self {
+        // Each transform is named at most once, so the one which needs the generator can have it
+        let mut rng = Some(rng);
+        let mut pipeline = |steps: &[TransformStep]| {
+            steps
+                .iter()
+                .fold(Pipeline::new(), |pipeline, step| match step {
+                    TransformStep::ShuffleWords => pipeline.then(ShuffleWords {
+                        tokenizer: text.tokenize,
+                        rng: rng.take().expect("shuffle-words is named once"),
+                    }),
+                    TransformStep::Spongify => unreachable!("spongify splits the order"),
+                })
+        };
+        let order = text.transform_order();
+        let (before, after) = order.split();
+        let (before, after) = (pipeline(before), pipeline(after));
+
+        Self {
+            text,
+            capitalizer,
+            before,
+            after,
+            index: 0,
+            line_index: 0,
+        }
+    }
+
+    /// SpOnGiFy a single `line`, which does not include its line break.
+    pub fn spongify_line(&mut self, line: &str, stats: &mut Stats) -> String {
+        self.spongify_mask(line, stats, |_, _| {})
+    }
+
+    /// SpOnGiFy a single `line` like `spongify_line`, also passing each piece of the line to `emit` along with what it
+    /// was SpOnGiFied into. Pieces are single characters, except for emoji and lines left as they are, which are passed
+    /// whole. Emoji removed by `--strip-emoji` are passed with an empty output.
+    pub fn spongify_mask(
+        &mut self,
+        line: &str,
+        stats: &mut Stats,
+        mut emit: impl FnMut(&str, &str),
+    ) -> String {
+        let line_index = self.line_index;
+        self.line_index += 1;
+        let _span = enter_span!(TRACE, "spongify_line", line = line_index);
+        if self.text.reset_per_line {
+            self.capitalizer.reset();
+        }
+        if self.text.is_verbatim_line(line_index) {
+            emit(line, line);
+            return verbatim_line(line, stats);
+        }
+
+        let line = if self.before.is_empty() {
+            Cow::Borrowed(line)
+        } else {
+            Cow::Owned(self.before.apply(line))
+        };
+
+        let mut spongified = String::with_capacity(line.len());
+        for grapheme in line.graphemes(true) {
+            if (self.text.strip_emoji || self.text.skip_emoji_ticks) && is_emoji(grapheme) {
+                if self.text.skip_emoji_ticks {
+                    spongified.push_str(grapheme);
+                    stats.unchanged += grapheme.chars().count();
+                    self.index += 1;
+                    emit(grapheme, grapheme);
+                } else {
+                    emit(grapheme, "");
+                }
+                continue;
+            }
+
+            capitalize_grapheme(
+                self.capitalizer,
+                self.index,
+                grapheme,
+                |offset, c, mapped| {
+                    let char_start = spongified.len();
+                    spongified.extend(mapped);
+                    stats.record_char(c, &spongified[char_start..]);
+                    emit(
+                        &grapheme[offset..offset + c.len_utf8()],
+                        &spongified[char_start..],
+                    );
+                },
+            );
+            self.index += 1;
+        }
+        stats.record_line(&spongified);
+
+        // The line break
+        self.capitalizer.line_break();
+        self.index += 1;
+
+        if self.after.is_empty() {
+            spongified
+        } else {
+            self.after.apply(&spongified)
+        }
+    }
+
+    /// SpOnGiFy each line of `input` into `output`.
+    ///
+    /// If `newline` is set, each line is terminated as `--line-ending` says; otherwise, lines are joined with a space.
+    pub fn spongify_lines(
+        &mut self,
+        input: impl io::BufRead,
+        output: &mut dyn io::Write,
+        newline: bool,
+        stats: &mut Stats,
+        timings: &mut Timings,
+    ) -> Result<()> {
+        let mut lines = read_terminated_lines(input, self.text.lossy_utf8);
+        let mut first = true;
+        while let Some(line) = timings.time(Stage::Read, || lines.next()) {
+            let (line, terminator) = line?;
+            let start = Instant::now();
+
+            if !newline {
+                if first {
+                    first = false;
+                } else {
+                    write!(output, " ")?;
+                }
+            }
+
+            let spongified = self.spongify_line(&line, stats);
+            output.write_all(spongified.as_bytes())?;
+
+            if newline {
+                write!(output, "{}", self.text.line_ending.terminator(terminator))?;
+            }
+
+            timings.add(Stage::Spongify, start.elapsed());
+        }
+
+        Ok(())
+    }
+}
+
+/// SpOnGiFying as a `Transform` works on a single line at a time, like `Spongifier::spongify_line`.
+impl Transform for Spongifier<'_> {
+    fn apply(&mut self, input: &str) -> String {
+        self.spongify_line(input, &mut Stats::new())
+    }
+}
+
+impl Spongifier<'_> {
+    /// SpOnGiFy `input` into `output` for `--filter`, changing nothing but the case of letters with `spongify_bytes`.
+    /// Input which looks like binary data is copied as it is.
+    pub fn spongify_filter(
+        &mut self,
+        mut input: impl io::BufRead,
+        output: &mut dyn io::Write,
+        stats: &mut Stats,
+        timings: &mut Timings,
+    ) -> Result<()> {
+        if refuse_binary(&mut input, false).is_err() {
+            timings.time(Stage::Read, || io::copy(&mut input, output))?;
+            return Ok(());
+        }
+        self.spongify_bytes(input, output, stats, timings)
+    }
+
+    /// SpOnGiFy `input` into `output` as a stream of bytes, changing the case of cased letters and nothing else. Every
+    /// other byte (whitespace, punctuation, control characters, line endings, and bytes which are not UTF-8) is written
+    /// exactly as it was read, and so are letters the engine would turn into something other than a different case of
+    /// themselves (like `--style leet` digits).
+    ///
+    /// Unlike `spongify_lines`, the input is not split into lines first, so a byte which is not UTF-8 only keeps itself
+    /// unchanged rather than its whole line. The pattern advances like it does for lines, including
+    /// `--reset-per-line`, `--alternate-lines`, and `--skip-emoji-ticks`.
+    pub fn spongify_bytes(
+        &mut self,
+        mut input: impl io::BufRead,
+        output: &mut dyn io::Write,
+        stats: &mut Stats,
+        timings: &mut Timings,
+    ) -> Result<()> {
+        let mut pending = Vec::new();
+        let mut line = String::new();
+        loop {
+            let read = timings.time(Stage::Read, || -> io::Result<usize> {
+                let buffer = input.fill_buf()?;
+                pending.extend_from_slice(buffer);
+                let read = buffer.len();
+                input.consume(read);
+                Ok(read)
+            })?;
+            let at_end = read == 0;
+            let start = Instant::now();
+
+            let mut rest = &pending[..];
+            while !rest.is_empty() {
+                let (valid_len, invalid_len) = match std::str::from_utf8(rest) {
+                    Ok(_) => (rest.len(), None),
+                    Err(e) => (e.valid_up_to(), e.error_len()),
+                };
+                let text =
+                    std::str::from_utf8(&rest[..valid_len]).expect("only valid UTF-8 is decoded");
+                // The last grapheme cluster may go on in the next read, unless something which is not text ends it
+                let done = self.spongify_text(
+                    text,
+                    at_end || invalid_len.is_some(),
+                    output,
+                    &mut line,
+                    stats,
+                )?;
+                rest = &rest[done..];
+                if done < valid_len {
+                    break;
+                }
+
+                match invalid_len {
+                    Some(len) => {
+                        output.write_all(&rest[..len])?;
+                        rest = &rest[len..];
+                    }
+                    // A character cut off by the end of the input is not UTF-8 either
+                    None if at_end => {
+                        output.write_all(rest)?;
+                        rest = &[];
+                    }
+                    None => break,
+                }
+            }
+            let kept = rest.len();
+            pending.drain(..pending.len() - kept);
+
+            timings.add(Stage::Spongify, start.elapsed());
+            if at_end {
+                stats.record_line(&line);
+                return Ok(());
+            }
+        }
+    }
+
+    /// SpOnGiFy the grapheme clusters of `text` into `output` for `spongify_bytes`, collecting the current line in
+    /// `line` for `Stats::record_line`. Unless `complete` is set, the last grapheme cluster is left for the next call.
+    ///
+    /// # Return
+    /// The number of bytes of `text` which were written.
+    fn spongify_text(
+        &mut self,
+        text: &str,
+        complete: bool,
+        output: &mut dyn io::Write,
+        line: &mut String,
+        stats: &mut Stats,
+    ) -> io::Result<usize> {
+        let mut graphemes = text.grapheme_indices(true).peekable();
+        while let Some((offset, grapheme)) = graphemes.next() {
+            if !complete && graphemes.peek().is_none() {
+                return Ok(offset);
+            }
+
+            let verbatim = self.text.is_verbatim_line(self.line_index);
+            if grapheme == "\n" || grapheme == "\r\n" {
+                output.write_all(grapheme.as_bytes())?;
+                stats.unchanged += grapheme.chars().count();
+                stats.record_line(line);
+                line.clear();
+                if !verbatim {
+                    self.capitalizer.line_break();
+                    self.index += 1;
+                }
+                self.line_index += 1;
+                if self.text.reset_per_line {
+                    self.capitalizer.reset();
+                }
+                continue;
+            }
+
+            if verbatim || (self.text.skip_emoji_ticks && is_emoji(grapheme)) {
+                output.write_all(grapheme.as_bytes())?;
+                line.push_str(grapheme);
+                stats.unchanged += grapheme.chars().count();
+                self.index += usize::from(!verbatim);
+                continue;
+            }
+
+            let mut written = Ok(());
+            capitalize_grapheme(self.capitalizer, self.index, grapheme, |_, c, mapped| {
+                let recased = mapped
+                    .iter()
+                    .flat_map(|c| c.to_uppercase())
+                    .eq(c.to_uppercase())
+                    || mapped
+                        .iter()
+                        .flat_map(|c| c.to_lowercase())
+                        .eq(c.to_lowercase());
+                let line_start = line.len();
+                if recased {
+                    line.extend(mapped);
+                } else {
+                    line.push(c);
+                }
+                if written.is_ok() {
+                    written = output.write_all(&line.as_bytes()[line_start..]);
+                }
+                stats.record_char(c, &line[line_start..]);
+            });
+            written?;
+            self.index += 1;
+        }
+        Ok(text.len())
+    }
+}
+
+/// SpOnGiFies lines in parallel for `--parallel`.
+///
+/// Each line is SpOnGiFied by its own engine, so the capitalization pattern starts fresh on every line and grapheme
+/// indices count from the start of the line. Random choices use a generator seeded from the line number, so the output
+/// is the same no matter how many threads there are. Output order always matches input order.
+struct ParallelSpongifier<'a> {
+    text: &'a TextOpt,
+    strategy: &'a CapitalizationStrategy,
+    seed: u64,
+    random_bias: f64,
+    pool: rayon::ThreadPool,
+}
+
+impl<'a> ParallelSpongifier<'a> {
+    /// Create an instance running on `threads` threads, or one per CPU if `threads` is `None`.
+    pub fn new(
+        text: &'a TextOpt,
+        strategy: &'a CapitalizationStrategy,
+        seed: u64,
+        random_bias: f64,
+        threads: Option<NonZeroUsize>,
+    ) -> Result<Self> {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads.map_or(0, NonZeroUsize::get))
+            .build()?;
+        Ok(Self {
+            text,
+            strategy,
+            seed,
+            random_bias,
+            pool,
+        })
+    }
+
+    /// Get the seed for the random number generator of the line at `line_index`.
+    fn line_seed(&self, line_index: usize) -> u64 {
+        self.seed ^ (line_index as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+
+    /// SpOnGiFy each line of `input` into `output`, with the same `newline` handling as `Spongifier::spongify_lines`.
+    pub fn spongify_lines(
+        &self,
+        input: impl io::BufRead,
+        output: &mut dyn io::Write,
+        newline: bool,
+        stats: &mut Stats,
+        timings: &mut Timings,
+    ) -> Result<()> {
+        let lines = timings.time(Stage::Read, || {
+            read_terminated_lines(input, self.text.lossy_utf8).collect::<io::Result<Vec<_>>>()
+        })?;
+
+        let start = Instant::now();
+        let spongified = self.pool.install(|| {
+            lines
+                .par_iter()
+                .enumerate()
+                .map(|(line_index, (line, _))| {
+                    let mut line_stats = if stats.words.is_some() {
+                        Stats::with_words()
+                    } else {
+                        Stats::new()
+                    };
+                    if self.text.is_verbatim_line(line_index) {
+                        return (verbatim_line(line, &mut line_stats), line_stats);
+                    }
+
+                    let mut capitalizer = self.strategy.create_engine(EngineOptions {
+                        seed: Some(self.line_seed(line_index)),
+                        random_bias: self.random_bias,
+                    });
+                    let rng = StdRng::seed_from_u64(self.line_seed(line_index));
+                    let mut spongifier = Spongifier::new(self.text, capitalizer.as_mut(), rng);
+                    // The line's own index, so `--alternate-lines` agrees with the check above
+                    spongifier.line_index = line_index;
+                    let spongified = spongifier.spongify_line(line, &mut line_stats);
+                    (spongified, line_stats)
+                })
+                .collect::<Vec<_>>()
+        });
+
+        for (line_index, ((line, line_stats), (_, terminator))) in
+            spongified.into_iter().zip(&lines).enumerate()
+        {
+            stats.merge(line_stats);
+
+            if !newline && line_index > 0 {
+                write!(output, " ")?;
+            }
+            output.write_all(line.as_bytes())?;
+            if newline {
+                write!(output, "{}", self.text.line_ending.terminator(terminator))?;
+            }
+        }
+        timings.add(Stage::Spongify, start.elapsed());
+
+        Ok(())
+    }
+}
+
+/// Either a `Spongifier` or a `ParallelSpongifier`, depending on `--parallel`.
+enum LineEngine<'a> {
+    Serial(Box<Spongifier<'a>>),
+    Parallel(ParallelSpongifier<'a>),
+}
+
+impl LineEngine<'_> {
+    /// See `Spongifier::spongify_lines`.
+    pub fn spongify_lines(
+        &mut self,
+        input: impl io::BufRead,
+        output: &mut dyn io::Write,
+        newline: bool,
+        stats: &mut Stats,
+        timings: &mut Timings,
+    ) -> Result<()> {
+        match self {
+            Self::Serial(spongifier) => {
+                spongifier.spongify_lines(input, output, newline, stats, timings)
+            }
+            Self::Parallel(spongifier) => {
+                spongifier.spongify_lines(input, output, newline, stats, timings)
+            }
+        }
+    }
+}
+
+/// Read the caption for `--image` from `input`, cased according to `case`.
+fn read_caption(
+    input: impl io::BufRead,
+    case: CaptionCase,
+    engine: &mut LineEngine,
+    stats: &mut Stats,
+    timings: &mut Timings,
+) -> Result<String> {
+    let text = match case {
+        CaptionCase::Spongify => {
+            let mut text = Vec::new();
+            engine.spongify_lines(input, &mut text, true, stats, timings)?;
+            String::from_utf8(text)?
+        }
+        CaptionCase::AsIs => timings.time(Stage::Read, || io::read_to_string(input))?,
+        CaptionCase::Upper => {
+            CaseMapping::Upper.apply(&timings.time(Stage::Read, || io::read_to_string(input))?)
+        }
+        CaptionCase::Lower => {
+            CaseMapping::Lower.apply(&timings.time(Stage::Read, || io::read_to_string(input))?)
+        }
+    };
+    Ok(text)
+}
+
+/// Get the path of the image numbered `n` for `--caption-from-stdin-lines`, by replacing `{n}` in `template`.
+fn numbered_path(template: &str, n: usize) -> PathBuf {
+    PathBuf::from(template.replace("{n}", &n.to_string()))
+}
+
+/// Write an image macro for each of `captions` for `--caption-from-stdin-lines`, to `template` with `{n}` replaced by
+/// the one-based caption number. Returns the paths written to.
+fn write_numbered_macros(
+    captions: &[(Option<String>, Option<String>)],
+    template: &str,
+    format: MacroFormat,
+    image: &ImageOpt,
+    render_options: &RenderOptions,
+    timings: &mut Timings,
+) -> Result<Vec<PathBuf>> {
+    if !template.contains("{n}") {
+        return Err(format!(
+            "--caption-from-stdin-lines needs {{n}} in --output-file for the image number, like out-{{n}}.png (got {template})"
+        )
+        .into());
+    }
+
+    let mut paths = Vec::with_capacity(captions.len());
+    for (index, (top_text, bottom_text)) in captions.iter().enumerate() {
+        let encoded = match format {
+            MacroFormat::Svg => imagemacro::generate_svg(
+                top_text.as_deref(),
+                bottom_text.as_deref(),
+                render_options,
+                !image.svg_no_image,
+                timings,
+            )?
+            .into_bytes(),
+            MacroFormat::Raster(format) => {
+                let macro_image = imagemacro::generate_image(
+                    top_text.as_deref(),
+                    bottom_text.as_deref(),
+                    render_options,
+                    timings,
+                )?;
+                timings.time(Stage::Encode, || {
+                    imagemacro::encode_image(&macro_image, format, image.image_quality)
+                })?
+            }
+        };
+
+        let path = numbered_path(template, index + 1);
+        fs::write(&path, encoded)
+            .map_err(|e| format!("could not write image {}: {e}", path.display()))?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Print the fonts built into SpOnGiFy, for `spongify fonts`.
+fn list_fonts(output: &mut dyn io::Write) -> Result<()> {
+    for font in imagemacro::bundled_fonts() {
+        let scripts = font
+            .scripts
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        writeln!(output, "{}: {}", font.family, scripts.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Check the options for `--validate`, printing each problem with them to `output`. Only checks which need nothing
+/// but the options are made, so nothing is read or written.
+fn validate(opt: &Opt, output: &mut dyn io::Write) -> Result<()> {
+    let mut problems = opt.problems();
+    if opt.output.in_place && opt.input.file.len() != 1 {
+        problems.push("--in-place needs exactly one --file".to_owned());
+    }
+    if opt.output.image {
+        if let Err(e) = opt.output.image_format() {
+            problems.push(e.to_string());
+        }
+    }
+
+    for problem in &problems {
+        writeln!(output, "error: {problem}")?;
+    }
+    match problems.len() {
+        0 => Ok(()),
+        1 => Err("found a problem with the options".into()),
+        count => Err(format!("found {count} problems with the options").into()),
+    }
+}
+
+/// Print example runs of SpOnGiFy, for `spongify examples`. The outputs are SpOnGiFied as the examples are printed, so
+/// they always match what SpOnGiFy does.
+fn print_examples(output: &mut dyn io::Write) -> Result<()> {
+    let text = "your text here";
+    let styled = |style: &str| -> Result<String> { Ok(spongify::spongify(text, style.parse()?)) };
+    let lines = "hello\nis it me you're looking for?";
+    let caption = "why is it tuesday";
+    let clipped = "now you can paste sane-cased text anywhere";
+    let notes = "buy milk\ncall the plumber";
+
+    let examples = [
+        (
+            "SpOnGiFy text",
+            vec![(
+                format!("spongify \"{text}\""),
+                spongify::spongify(text, CapitalizationStrategy::default()),
+            )],
+        ),
+        (
+            "Pick a style",
+            vec![(
+                format!("spongify --style \"lIkE ThIs\" \"{text}\""),
+                styled("lIkE ThIs")?,
+            )],
+        ),
+        (
+            "Alternate whole words",
+            vec![(
+                format!("spongify --style \"like THIS\" \"{text}\""),
+                styled("like THIS")?,
+            )],
+        ),
+        (
+            "SpOnGiFy a file",
+            vec![
+                ("cat notes.txt".to_owned(), notes.to_owned()),
+                (
+                    "spongify --file notes.txt".to_owned(),
+                    spongify::spongify(notes, CapitalizationStrategy::default()),
+                ),
+            ],
+        ),
+        (
+            "Read from standard input",
+            vec![(
+                format!("printf \"{}\\n\" | spongify -", lines.replace('\n', "\\n")),
+                spongify::spongify(lines, CapitalizationStrategy::default()),
+            )],
+        ),
+        (
+            "Make an image macro",
+            vec![(
+                format!("spongify --image -o meme.png \"{caption}\""),
+                format!(
+                    "(writes meme.png, captioned \"{}\")",
+                    spongify::spongify(caption, CapitalizationStrategy::default())
+                ),
+            )],
+        ),
+        (
+            "Copy to the clipboard",
+            vec![(
+                format!("spongify -c \"{clipped}\""),
+                format!(
+                    "(copies \"{}\")",
+                    spongify::spongify(clipped, CapitalizationStrategy::default())
+                ),
+            )],
+        ),
+    ];
+
+    for (description, steps) in examples {
+        writeln!(output, "{description}:")?;
+        for (command, result) in steps {
+            writeln!(output, "  $ {command}")?;
+            for line in result.lines() {
+                writeln!(output, "  {line}")?;
+            }
+        }
+        writeln!(output)?;
+    }
+    Ok(())
+}
+
+/// Parse the options of `spongify diff` from `args`, the arguments after `diff`, which are those of a normal run.
+fn diff_options(args: &[OsString]) -> Result<Opt, clap::Error> {
+    let mut opt = Opt::try_parse_from(
+        std::iter::once(OsString::from("spongify diff")).chain(args.iter().cloned()),
+    )?;
+    let conflict = if opt.command.is_some() {
+        Some("a subcommand")
+    } else if opt.output.image {
+        Some("--image")
+    } else if opt.filter {
+        Some("--filter")
+    } else if opt.parallel {
+        Some("--parallel")
+    } else if opt.text.transform_order.is_some() {
+        Some("--transform-order")
+    } else {
+        None
+    };
+    if let Some(conflict) = conflict {
+        return Err(Opt::command().bin_name("spongify diff").error(
+            clap::error::ErrorKind::ArgumentConflict,
+            format!("spongify diff cannot be used with {conflict}"),
+        ));
+    }
+    opt.diff = true;
+    Ok(opt)
+}
+
+/// Run each job of the manifest at `path` for `spongify batch`, then report how each went to `report`. Every job is
+/// run, even after one fails, and it is an error if any of them did.
+fn run_batch(path: &Path, report: &mut dyn io::Write) -> Result<()> {
+    let jobs = batch::load_manifest(path)?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let results = jobs
+        .iter()
+        .map(|job| -> Result<()> {
+            let opt = Opt::try_parse_from(job.command_line(base)).map_err(|e| {
+                // Only the first line has the problem; the rest is the usage
+                let message = e.to_string();
+                let first = message.lines().next().unwrap_or_default();
+                first.trim_start_matches("error: ").to_owned()
+            })?;
+            if opt.command.is_some() {
+                return Err("a batch job cannot run a subcommand".into());
+            }
+            run(opt)
+        })
+        .collect::<Vec<_>>();
+
+    let mut failed = 0;
+    for (n, (job, result)) in jobs.iter().zip(results).enumerate() {
+        let name = format!(
+            "job {} ({} -> {})",
+            n + 1,
+            job.input.display(),
+            job.output.display()
+        );
+        match result {
+            Ok(()) => writeln!(report, "{name}: ok")?,
+            Err(e) => {
+                failed += 1;
+                writeln!(report, "{name}: failed: {e}")?;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{failed} of {} batch jobs failed", jobs.len()).into());
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::parse();
+    let opt = match opt.command {
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Opt::command(), "spongify", &mut io::stdout());
+            return Ok(());
+        }
+        Some(Command::Batch { ref manifest }) => return run_batch(manifest, &mut io::stderr()),
+        Some(Command::Diff { ref args }) => diff_options(args).unwrap_or_else(|e| e.exit()),
+        Some(Command::Examples) => return print_examples(&mut io::stdout()),
+        Some(Command::Fonts) => return list_fonts(&mut io::stdout()),
+        None => opt,
+    };
+    #[cfg(feature = "tracing")]
+    if let Some(level) = opt.log_level {
+        trace::init(level);
+    }
+    run(opt)
+}
+
+/// SpOnGiFy as `opt` says to.
+fn run(opt: Opt) -> Result<()> {
+    if opt.validate {
+        return validate(&opt, &mut io::stderr());
+    }
+    for problem in opt.problems() {
+        warn!("{problem}");
+    }
+    let _span = enter_span!(INFO, "run");
+
+    let mut timings = Timings::new();
+    let mut stats = if opt.stats_verbose {
+        Stats::with_words()
+    } else {
+        Stats::new()
+    };
+    let strategy = opt.strategy()?;
+    let seed = opt.seed()?;
+
+    let in_place_path = match opt.input.file[..] {
+        [ref path] if opt.output.in_place => Some(path.clone()),
+        _ if opt.output.in_place => return Err("--in-place needs exactly one --file".into()),
+        _ => None,
+    };
+    let force_binary = opt.input.force_binary;
+    let limits = opt.input.limits();
+    // The map reads the whole file itself, so it cannot stop at the input limits (`--count-bytes` conflicts with it)
+    let mmap_unlimited = limits.is_unlimited();
+    let input_spec = InputSpec::from(opt.input);
+    let mmap_input = match input_spec {
+        InputSpec::Files(ref paths) if opt.output.mmap_output && paths.len() == 1 => {
+            Some(paths[0].clone())
+        }
+        _ => None,
+    };
+    let byte_counts = ByteCounts::default();
+    let mut input = input_spec.into_reader()?;
+    if !limits.is_unlimited() {
+        input = Box::new(LimitedReader::new(input, limits));
+    }
+    if opt.count_bytes {
+        input = Box::new(CountingReader::new(input, byte_counts.read.clone()));
+    }
+    if !force_binary && !opt.filter && !opt.image.has_caption() {
+        timings.time(Stage::Read, || {
+            refuse_binary(&mut input, opt.text.lossy_utf8)
+        })?;
+    }
+
+    let mut clip = opt.output.clip.then(ClipWriter::new);
+    let mut in_place = in_place_path
+        .as_deref()
+        .map(InPlaceWriter::new)
+        .transpose()?;
+    let (mut output, newline): (Box<dyn io::Write + '_>, bool) = match (&mut clip, &mut in_place) {
+        (Some(clip), _) => (Box::new(clip), false),
+        (_, Some(in_place)) => (Box::new(in_place), true),
+        // Each image of --caption-from-stdin-lines is written to its own file
+        _ if opt.image.caption_from_stdin_lines.is_some() => (Box::new(io::sink()), true),
+        _ => opt.output.get_writer()?,
+    };
+    if opt.count_bytes {
+        output = Box::new(CountingWriter::new(output, byte_counts.written.clone()));
+    }
+    let random_bias = opt.random_bias.unwrap_or(DEFAULT_RANDOM_BIAS);
+    let mut capitalizer = strategy.create_engine(EngineOptions { seed, random_bias });
+    let mut rng = seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64);
+    let mut engine = if opt.parallel {
+        LineEngine::Parallel(ParallelSpongifier::new(
+            &opt.text,
+            &strategy,
+            rng.gen(),
+            random_bias,
+            opt.engine_threads,
+        )?)
+    } else {
+        LineEngine::Serial(Box::new(Spongifier::new(
+            &opt.text,
+            capitalizer.as_mut(),
+            rng,
+        )))
+    };
+
+    if let Some(count) = opt.image.caption_from_stdin_lines {
+        let lines = timings.time(Stage::Read, || {
+            read_lines(input, opt.text.lossy_utf8)
+                .take(count.get())
+                .collect::<io::Result<Vec<_>>>()
+        })?;
+        if lines.len() < count.get() {
+            warn!(
+                "--caption-from-stdin-lines {count} only got {} lines",
+                lines.len()
+            );
+        }
+        let captions = opt
+            .image
+            .line_captions(&lines, &mut engine, &mut stats, &mut timings)?;
+        if opt.image.warn_empty_caption {
+            for (n, (top_text, bottom_text)) in captions.iter().enumerate() {
+                if [top_text, bottom_text]
+                    .into_iter()
+                    .flatten()
+                    .all(|text| imagemacro::is_empty_caption(text))
+                {
+                    warn!(
+                        "the caption on line {} is empty, so it will not be drawn",
+                        n + 1
+                    );
+                }
+            }
+        }
+        let mut render_options = timings.time(Stage::FontLoad, || opt.image.render_options())?;
+        render_options.base_image =
+            timings.time(Stage::ImageLoad, || opt.image.load_base_image())?;
+
+        let format = opt.output.image_format()?;
+        let template = match opt.output.output_file {
+            Some(ref path) => path.to_string_lossy().into_owned(),
+            None => match format {
+                MacroFormat::Svg => "out-{n}.svg".to_owned(),
+                MacroFormat::Raster(format) => {
+                    format!("out-{{n}}.{}", format.extensions_str()[0])
+                }
+            },
+        };
+        write_numbered_macros(
+            &captions,
+            &template,
+            format,
+            &opt.image,
+            &render_options,
+            &mut timings,
+        )?;
+    } else if opt.output.image {
+        let (top_text, bottom_text) =
+            opt.image
+                .captions(input, &mut engine, &mut stats, &mut timings)?;
+        if opt.image.warn_empty_caption {
+            for (name, text) in [("top", &top_text), ("bottom", &bottom_text)] {
+                if text.as_deref().is_some_and(imagemacro::is_empty_caption) {
+                    warn!("the {name} caption is empty, so it will not be drawn");
+                }
+            }
+        }
+        let mut render_options = timings.time(Stage::FontLoad, || opt.image.render_options())?;
+        render_options.base_image =
+            timings.time(Stage::ImageLoad, || opt.image.load_base_image())?;
+
+        if let Some(ref path) = opt.image.export_layout {
+            let layout = imagemacro::export_layout(
+                top_text.as_deref(),
+                bottom_text.as_deref(),
+                &render_options,
+                &mut timings,
+            )?;
+            fs::write(path, layout)
+                .map_err(|e| format!("could not write layout {}: {e}", path.display()))?;
+        }
+
+        match opt.output.image_format()? {
+            MacroFormat::Svg => {
+                let svg = imagemacro::generate_svg(
+                    top_text.as_deref(),
+                    bottom_text.as_deref(),
+                    &render_options,
+                    !opt.image.svg_no_image,
+                    &mut timings,
+                )?;
+                ignore_broken_pipe(output.write_all(svg.as_bytes()).map_err(Into::into))?;
+            }
+            MacroFormat::Raster(format) => {
+                let image = imagemacro::generate_image(
+                    top_text.as_deref(),
+                    bottom_text.as_deref(),
+                    &render_options,
+                    &mut timings,
+                )?;
+
+                #[cfg_attr(not(feature = "preview"), allow(unused_mut))]
+                let mut write_image = true;
+                #[cfg(feature = "preview")]
+                if opt.image.preview_image {
+                    print!("{}", preview::render(&image, preview::terminal_columns()));
+                    write_image = opt.output.output_file.is_some();
+                }
+
+                if write_image {
+                    let encoded = timings.time(Stage::Encode, || {
+                        imagemacro::encode_image(&image, format, opt.image.image_quality)
+                    })?;
+                    ignore_broken_pipe(output.write_all(&encoded).map_err(Into::into))?;
+                }
+            }
+        }
+    } else if opt.diff {
+        let LineEngine::Serial(spongifier) = &mut engine else {
+            unreachable!("spongify diff cannot be used with --parallel");
+        };
+        ignore_broken_pipe(diff::write_diff(
+            spongifier,
+            input,
+            &mut output,
+            diff::color_enabled(),
+            &mut stats,
+            &mut timings,
+        ))?;
+    } else if opt.filter {
+        let LineEngine::Serial(spongifier) = &mut engine else {
+            unreachable!("--filter conflicts with --parallel");
+        };
+        ignore_broken_pipe(spongifier.spongify_filter(
+            input,
+            &mut output,
+            &mut stats,
+            &mut timings,
+        ))?;
+    } else {
+        let mut mapped = false;
+        if opt.output.mmap_output && opt.output.compression().is_some() {
+            warn!("--mmap-output cannot compress the output, falling back to normal writes");
+        } else if opt.output.mmap_output && !mmap_unlimited {
+            warn!(
+                "--mmap-output cannot apply --input-limit-lines or --input-limit-bytes, falling back to normal writes"
+            );
+        } else if opt.output.mmap_output {
+            if let (Some(input_path), Some(output_path), LineEngine::Serial(spongifier)) =
+                (&mmap_input, &opt.output.output_file, &mut engine)
+            {
+                mapped = mmap::spongify_mmap(
+                    spongifier,
+                    input_path,
+                    output_path,
+                    &mut stats,
+                    &mut timings,
+                )?;
+            }
+            if !mapped {
+                warn!("--mmap-output needs ASCII input from a file, falling back to normal writes");
+            }
+        }
+
+        if !mapped {
+            ignore_broken_pipe(engine.spongify_lines(
+                input,
+                &mut output,
+                newline,
+                &mut stats,
+                &mut timings,
+            ))?;
+        }
+    }
+
+    // The clipboard is only set, and the file is only replaced, once all the output has been written
+    drop(output);
+    if let Some(clip) = clip {
+        clip.finish()?;
+    }
+    if let Some(in_place) = in_place {
+        in_place.finish()?;
+    }
+
+    if opt.stats || opt.stats_verbose {
+        match opt.stats_format {
+            StatsFormat::Text => eprint!("{stats}"),
+            StatsFormat::Json => eprint!("{}", stats.to_json()),
+        }
+    }
+    if opt.timing {
+        eprint!("{timings}");
+    }
+    if opt.count_bytes {
+        eprint!("{byte_counts}");
+    }
+    if let Some(report) = capitalizer.entropy_report().filter(|_| opt.entropy_report) {
+        eprint!("{report}");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spongify::MappedChars;
+
+    #[test]
+    fn refuse_binary_nul() {
+        let mut input = io::Cursor::new(b"taco\0truck".to_vec());
+
+        let err = refuse_binary(&mut input, false).unwrap_err();
+        assert!(err.to_string().starts_with("input appears to be binary"));
+    }
+
+    #[test]
+    fn refuse_binary_invalid_utf8() {
+        let mut input = io::Cursor::new(b"taco \xff\xfe truck".to_vec());
+
+        assert!(refuse_binary(&mut input, false).is_err());
+        refuse_binary(&mut input, true).unwrap();
+    }
+
+    #[test]
+    fn refuse_binary_accepts_text() {
+        let mut input = io::Cursor::new("taco trück".as_bytes().to_vec());
+
+        refuse_binary(&mut input, false).unwrap();
+        assert_eq!(io::read_to_string(input).unwrap(), "taco trück");
+    }
+
+    #[test]
+    fn broken_pipe_is_not_an_error() {
+        let (mut reader, mut writer) = io::pipe().unwrap();
+        let head = std::thread::spawn(move || {
+            let mut buf = [0; 16];
+            io::Read::read_exact(&mut reader, &mut buf).unwrap();
+            buf
+        });
+
+        let input = "taco truck\n".repeat(100_000);
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        let result = Spongifier::new(
+            &TextOpt::default(),
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )
+        .spongify_lines(
+            io::Cursor::new(input),
+            &mut writer,
+            true,
+            &mut Stats::new(),
+            &mut Timings::new(),
+        );
+
+        assert_eq!(&head.join().unwrap(), b"TaCo tRuCk\ntAcO ");
+        assert!(is_broken_pipe(result.as_ref().unwrap_err().as_ref()));
+        ignore_broken_pipe(result).unwrap();
+        assert!(ignore_broken_pipe(Err("taco".into())).is_err());
+    }
+
+    #[test]
+    fn inline_input_file_or_text() {
+        let path = std::env::temp_dir().join(format!("spongify-inline-{}.txt", std::process::id()));
+        fs::write(&path, "taco truck\n").unwrap();
+        let spec = |inline: &std::ffi::OsStr| {
+            let opt = Opt::try_parse_from(["spongify".as_ref(), inline]).unwrap();
+            InputSpec::from(opt.input)
+        };
+
+        let existing = spec(path.as_os_str());
+        assert!(matches!(existing, InputSpec::Files(ref paths) if *paths == [path.clone()]));
+        let mut text = String::new();
+        existing
+            .into_reader()
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "taco truck\n");
+
+        fs::remove_file(&path).unwrap();
+        assert!(
+            matches!(spec(path.as_os_str()), InputSpec::Text(ref text) if Path::new(text) == path)
+        );
+        assert!(matches!(spec("-".as_ref()), InputSpec::Stdin));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn input_fd() {
+        use std::os::fd::IntoRawFd;
+
+        let (reader, mut writer) = io::pipe().unwrap();
+        io::Write::write_all(&mut writer, b"taco truck\n").unwrap();
+        drop(writer);
+
+        // The descriptor is handed over to the input, which closes it
+        let fd = reader.into_raw_fd().to_string();
+        let opt = Opt::try_parse_from(["spongify", "--fd", &fd]).unwrap();
+        let spec = InputSpec::from(opt.input);
+        assert!(matches!(spec, InputSpec::Fd(_)));
+        let input = io::read_to_string(spec.into_reader().unwrap()).unwrap();
+        assert_eq!(input, "taco truck\n");
+
+        let err = InputSpec::Fd(i32::MAX).into_reader().err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!("file descriptor {} is not open", i32::MAX)
+        );
+        assert!(Opt::try_parse_from(["spongify", "--fd", "3", "--stdin"]).is_err());
+        let err = InputSpec::Fd(-1).into_reader().err().unwrap();
+        assert_eq!(err.to_string(), "file descriptor -1 is not open");
+
+        // Standard input is read as it usually is, and standard output and error cannot be read at all
+        let opt = Opt::try_parse_from(["spongify", "--fd", "0"]).unwrap();
+        assert!(matches!(InputSpec::from(opt.input), InputSpec::Stdin));
+        for fd in [1, 2] {
+            let err = InputSpec::Fd(fd).into_reader().err().unwrap();
+            assert_eq!(
+                err.to_string(),
+                format!("file descriptor {fd} is an output and cannot be read")
+            );
+        }
+    }
+
+    #[test]
+    fn multiple_input_files() {
+        let dir = std::env::temp_dir().join(format!("spongify-inputs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+        fs::write(&a, "taco\ntruck").unwrap();
+        fs::write(&b, " time\nburrito\n").unwrap();
+
+        let spec = |args: &[&std::ffi::OsStr]| {
+            let opt = Opt::try_parse_from(["spongify".as_ref()].iter().chain(args)).unwrap();
+            InputSpec::from(opt.input)
+        };
+        let flags = spec(&[
+            "--file".as_ref(),
+            a.as_os_str(),
+            "-f".as_ref(),
+            b.as_os_str(),
+        ]);
+        assert!(matches!(flags, InputSpec::Files(ref paths) if *paths == [a.clone(), b.clone()]));
+        // Several inline words are always text, even if they all name files; only `--file` reads several files
+        let inline = spec(&[a.as_os_str(), b.as_os_str()]);
+        let joined = format!("{} {}", a.display(), b.display());
+        assert!(matches!(inline, InputSpec::Text(ref text) if *text == joined));
+        let missing = dir.join("missing.txt");
+
+        // The files are read as one, so the capitalization pattern continues from one to the next
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        let mut output = Vec::new();
+        Spongifier::new(
+            &TextOpt::default(),
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )
+        .spongify_lines(
+            flags.into_reader().unwrap(),
+            &mut output,
+            true,
+            &mut Stats::new(),
+            &mut Timings::new(),
+        )
+        .unwrap();
+        assert_eq!(output, b"TaCo\ntRuCk tImE\nBuRrItO\n");
+
+        let err = InputSpec::Files(vec![a, missing.clone()])
+            .into_reader()
+            .err()
+            .unwrap();
+        assert!(
+            err.to_string().contains(&missing.display().to_string()),
+            "{err}"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_file_fifo() {
+        let path = std::env::temp_dir().join(format!("spongify-fifo-{}", std::process::id()));
+        let status = std::process::Command::new("mkfifo")
+            .arg(&path)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let reader = std::thread::spawn({
+            let path = path.clone();
+            move || fs::read_to_string(path).unwrap()
+        });
+
+        let output = OutputOpt {
+            output_file: Some(path.clone()),
+            follow_symlinks: false,
+            no_follow_symlinks: false,
+            clip: false,
+            image: false,
+            image_format: None,
+            mmap_output: false,
+            compress: None,
+            in_place: false,
+        };
+        let (mut writer, newline) = output.get_writer().unwrap();
+        assert!(newline);
+        writer.write_all(b"TaCo TrUcK\n").unwrap();
+        drop(writer);
+
+        let contents = reader.join().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "TaCo TrUcK\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn output_file_symlink_refused() {
+        let dir = std::env::temp_dir().join(format!("spongify-symlink-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "burrito\n").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let output = |follow_symlinks| OutputOpt {
+            output_file: Some(link.clone()),
+            follow_symlinks,
+            no_follow_symlinks: false,
+            clip: false,
+            image: false,
+            image_format: None,
+            mmap_output: false,
+            compress: None,
+            in_place: false,
+        };
+        let err = output(false).get_writer().err().unwrap();
+        assert!(err.to_string().contains("symbolic link"), "{err}");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "burrito\n");
+
+        let (mut writer, _) = output(true).get_writer().unwrap();
+        writer.write_all(b"TaCo TrUcK\n").unwrap();
+        drop(writer);
+        assert_eq!(fs::read_to_string(&target).unwrap(), "TaCo TrUcK\n");
+
+        assert!(Opt::try_parse_from(["spongify", "-o", "out.txt"])
+            .is_ok_and(|opt| !opt.output.follow_symlinks));
+        let opt = Opt::try_parse_from([
+            "spongify",
+            "-o",
+            "out.txt",
+            "--follow-symlinks",
+            "--no-follow-symlinks",
+        ])
+        .unwrap();
+        assert!(!opt.output.follow_symlinks);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn in_place() {
+        let path =
+            std::env::temp_dir().join(format!("spongify-in-place-{}.txt", std::process::id()));
+        fs::write(&path, "taco truck\r\ntime\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        }
+
+        let opt = Opt::try_parse_from([
+            "spongify".as_ref(),
+            "--in-place".as_ref(),
+            "--file".as_ref(),
+            path.as_os_str(),
+        ])
+        .unwrap();
+        let input = InputSpec::from(opt.input).into_reader().unwrap();
+        let mut writer = InPlaceWriter::new(&path).unwrap();
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        Spongifier::new(&opt.text, capitalizer.as_mut(), StdRng::seed_from_u64(0))
+            .spongify_lines(
+                input,
+                &mut writer,
+                true,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+        writer.finish().unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let permissions = fs::metadata(&path).unwrap().permissions();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "TaCo tRuCk\r\ntImE\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(permissions.mode() & 0o777, 0o640);
+        }
+
+        for args in [
+            &["--in-place", "--stdin"][..],
+            &["--in-place", "-c", "-f", "x"],
+        ] {
+            assert!(Opt::try_parse_from(["spongify"].iter().chain(args)).is_err());
+        }
+    }
+
+    #[test]
+    fn in_place_unfinished_leaves_file() {
+        let path =
+            std::env::temp_dir().join(format!("spongify-unfinished-{}.txt", std::process::id()));
+        fs::write(&path, "taco truck\n").unwrap();
+
+        let mut writer = InPlaceWriter::new(&path).unwrap();
+        io::Write::write_all(&mut writer, b"TaCo").unwrap();
+        let temp_path = writer.temp_path.clone();
+        drop(writer);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(contents, "taco truck\n");
+        assert!(!temp_path.exists());
+    }
+
+    #[test]
+    fn image_format_from_flag_or_extension() {
+        let format = |output_file: Option<&str>, image_format: Option<ImageType>| {
+            OutputOpt {
+                output_file: output_file.map(PathBuf::from),
+                follow_symlinks: false,
+                no_follow_symlinks: false,
+                clip: false,
+                image: true,
+                image_format,
+                mmap_output: false,
+                compress: None,
+                in_place: false,
+            }
+            .image_format()
+            .unwrap()
+        };
+        assert_eq!(
+            format(None, None),
+            MacroFormat::Raster(image::ImageFormat::Png)
+        );
+        assert_eq!(
+            format(Some("meme.jpg"), None),
+            MacroFormat::Raster(image::ImageFormat::Jpeg)
+        );
+        assert_eq!(
+            format(Some("meme.webp"), None),
+            MacroFormat::Raster(image::ImageFormat::WebP)
+        );
+        assert_eq!(format(Some("meme.SVG"), None), MacroFormat::Svg);
+        assert_eq!(format(None, Some(ImageType::Svg)), MacroFormat::Svg);
+        assert_eq!(
+            format(Some("meme.png"), Some(ImageType::Gif)),
+            MacroFormat::Raster(image::ImageFormat::Gif)
+        );
+    }
+
+    #[test]
+    fn output_file_compressed() {
+        fn gunzip(data: &[u8]) -> io::Result<String> {
+            io::read_to_string(flate2::read::GzDecoder::new(data))
+        }
+        fn unzstd(data: &[u8]) -> io::Result<String> {
+            io::read_to_string(zstd::Decoder::new(data)?)
+        }
+
+        type Decompress = fn(&[u8]) -> io::Result<String>;
+        let cases: [(_, _, Decompress); 3] = [
+            ("gz", None, gunzip),
+            ("zst", None, unzstd),
+            ("txt", Some(Compression::Zstd), unzstd),
+        ];
+        for (extension, compress, decompress) in cases {
+            let path = std::env::temp_dir().join(format!(
+                "spongify-compressed-{}.{extension}",
+                std::process::id()
+            ));
+            let output = OutputOpt {
+                output_file: Some(path.clone()),
+                follow_symlinks: false,
+                no_follow_symlinks: false,
+                clip: false,
+                image: false,
+                image_format: None,
+                mmap_output: false,
+                compress,
+                in_place: false,
+            };
+            let (mut writer, newline) = output.get_writer().unwrap();
+            let mut capitalizer =
+                CapitalizationStrategy::default().create_engine(EngineOptions::default());
+            Spongifier::new(
+                &TextOpt::default(),
+                capitalizer.as_mut(),
+                StdRng::seed_from_u64(0),
+            )
+            .spongify_lines(
+                io::Cursor::new("taco truck\ntime"),
+                &mut writer,
+                newline,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+            drop(writer);
+
+            let compressed = fs::read(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+            assert_eq!(
+                decompress(&compressed).unwrap(),
+                "TaCo tRuCk\ntImE",
+                "for .{extension}"
+            );
+        }
+    }
+
+    #[test]
+    fn seed_file_reproduces_output() {
+        let path = std::env::temp_dir().join(format!("spongify-seed-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let text = TextOpt {
+            shuffle_words: true,
+            ..Default::default()
+        };
+        let run = || {
+            let seed = load_seed(&path).unwrap();
+            let mut capitalizer =
+                CapitalizationStrategy::default().create_engine(EngineOptions::default());
+            Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(seed)).spongify_line(
+                "the quick brown fox jumps over the lazy dog",
+                &mut Stats::new(),
+            )
+        };
+
+        let first = run();
+        let saved = fs::read_to_string(&path).unwrap();
+        let second = run();
+        fs::remove_file(&path).unwrap();
+
+        assert!(
+            saved.trim().parse::<u64>().is_ok(),
+            "{saved:?} is not a seed"
+        );
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn load_pattern_file() {
+        let path =
+            std::env::temp_dir().join(format!("spongify-pattern-{}.txt", std::process::id()));
+        fs::write(&path, "uu l\nl\n").unwrap();
+        let pattern = load_pattern(&path);
+        fs::remove_file(&path).unwrap();
+
+        let mut output = Vec::new();
+        let mut capitalizer = CapitalizationStrategy::FromPattern(pattern.unwrap())
+            .create_engine(EngineOptions::default());
+        Spongifier::new(
+            &TextOpt::default(),
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )
+        .spongify_lines(
+            io::Cursor::new("taco truck"),
+            &mut output,
+            true,
+            &mut Stats::new(),
+            &mut Timings::new(),
+        )
+        .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "TAco TruCK");
+    }
+
+    #[test]
+    fn load_pattern_file_invalid() {
+        let path =
+            std::env::temp_dir().join(format!("spongify-bad-pattern-{}.txt", std::process::id()));
+        fs::write(&path, "uulx").unwrap();
+        let pattern = load_pattern(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert!(pattern
+            .unwrap_err()
+            .to_string()
+            .contains("Invalid pattern symbol 'x'"));
+    }
+
+    /// A `CapitalizationEngine` which never capitalizes, but remembers every index it was given.
+    struct IndexRecorder(Vec<usize>);
+
+    impl CapitalizationEngine for IndexRecorder {
+        fn start_grapheme(&mut self, index: usize, _character: char) {
+            self.0.push(index);
+        }
+
+        fn map_char(&mut self, character: char) -> MappedChars {
+            character.to_lowercase().collect()
+        }
+    }
+
+    #[test]
+    fn spongify_lines_index_is_continuous() {
+        let mut recorder = IndexRecorder(Vec::new());
+        let mut output = Vec::new();
+
+        Spongifier::new(&TextOpt::default(), &mut recorder, StdRng::seed_from_u64(0))
+            .spongify_lines(
+                io::Cursor::new("ab\ncd\ne\u{301}f"),
+                &mut output,
+                true,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+
+        assert_eq!(recorder.0, vec![0, 1, 3, 4, 6, 7]);
+    }
+
+    #[test]
+    fn entropy_report_counts_characters() {
+        let input = "taco truck\nburrito";
+        let mut capitalizer =
+            CapitalizationStrategy::Randomly.create_engine(EngineOptions::default());
+
+        Spongifier::new(
+            &TextOpt::default(),
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )
+        .spongify_lines(
+            io::Cursor::new(input),
+            &mut io::sink(),
+            true,
+            &mut Stats::new(),
+            &mut Timings::new(),
+        )
+        .unwrap();
+
+        let report = capitalizer.entropy_report().unwrap();
+        assert_eq!(
+            report.calls,
+            input
+                .lines()
+                .map(|line| line.chars().count() as u64)
+                .sum::<u64>()
+        );
+        assert!(report.uppercased <= report.calls);
+    }
+
+    fn spongify_str(input: &str, text: &TextOpt) -> String {
+        let mut output = Vec::new();
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+
+        Spongifier::new(text, capitalizer.as_mut(), StdRng::seed_from_u64(0))
+            .spongify_lines(
+                io::Cursor::new(input),
+                &mut output,
+                true,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn is_emoji_graphemes() {
+        assert!(is_emoji("😀"));
+        assert!(is_emoji("👍🏽"));
+        assert!(is_emoji("❤️"));
+        assert!(is_emoji("1️⃣"));
+        assert!(is_emoji("™"));
+        assert!(!is_emoji("1"));
+        assert!(!is_emoji("#"));
+        assert!(!is_emoji("a"));
+        assert!(!is_emoji("é"));
+    }
+
+    #[test]
+    fn emoji_keep() {
+        assert_eq!(
+            spongify_str("taco😀truck", &TextOpt::default()),
+            "TaCo😀tRuCk"
+        );
+    }
+
+    #[test]
+    fn emoji_strip() {
+        let text = TextOpt {
+            strip_emoji: true,
+            ..Default::default()
+        };
+
+        assert_eq!(spongify_str("tacos 😀 rule", &text), "TaCoS  rUlE");
+        assert_eq!(spongify_str("taco😀truck", &text), "TaCoTrUcK");
+    }
+
+    #[test]
+    fn emoji_skip_ticks() {
+        let text = TextOpt {
+            skip_emoji_ticks: true,
+            ..Default::default()
+        };
+
+        assert_eq!(spongify_str("taco👍🏽truck", &text), "TaCo👍🏽TrUcK");
+    }
+
+    #[test]
+    fn shuffle_words_seeded() {
+        let shuffled = shuffle_words(
+            "  the quick,  brown fox",
+            Tokenizer::Whitespace,
+            &mut StdRng::seed_from_u64(1),
+        );
+        assert!(shuffled.starts_with("  "));
+
+        let mut words = shuffled.split_whitespace().collect::<Vec<_>>();
+        words.sort();
+        assert_eq!(words, vec!["brown", "fox", "quick,", "the"]);
+
+        assert_eq!(
+            shuffled,
+            shuffle_words(
+                "  the quick,  brown fox",
+                Tokenizer::Whitespace,
+                &mut StdRng::seed_from_u64(1)
+            )
+        );
+    }
+
+    #[test]
+    fn shuffle_words_unicode_words() {
+        let line = "  the quick,  brown fox!";
+        let shuffled = shuffle_words(line, Tokenizer::UnicodeWords, &mut StdRng::seed_from_u64(1));
+
+        let between = |text: &str| {
+            text.chars()
+                .filter(|c| !c.is_alphanumeric())
+                .collect::<String>()
+        };
+        assert_eq!(between(&shuffled), between(line));
+
+        let mut words = spongify::tokenize::words(&shuffled)
+            .map(|(_, word)| word)
+            .collect::<Vec<_>>();
+        words.sort();
+        assert_eq!(words, vec!["brown", "fox", "quick", "the"]);
+    }
+
+    fn spongify_filter_bytes(input: &[u8]) -> Vec<u8> {
+        let text = TextOpt::default();
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        let mut output = Vec::new();
+        Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0))
+            .spongify_filter(
+                io::Cursor::new(input),
+                &mut output,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn spongify_filter_is_faithful() {
+        let input = b"taco truck\r\n\n  caf\xc3\xa9\tx\r\nno newline";
+        let output = spongify_filter_bytes(input);
+
+        assert_eq!(output, "TaCo tRuCk\r\n\n  CaFé\tx\r\nnO NeWlInE".as_bytes());
+        assert_eq!(output.to_ascii_lowercase(), input.to_ascii_lowercase());
+    }
+
+    #[test]
+    fn spongify_filter_passes_binary() {
+        let input = b"taco\0truck\n\xff";
+        assert_eq!(spongify_filter_bytes(input), input);
+    }
+
+    fn spongify_bytes(
+        strategy: CapitalizationStrategy,
+        input: &[u8],
+        buffer_capacity: usize,
+    ) -> Vec<u8> {
+        let text = TextOpt::default();
+        let mut capitalizer = strategy.create_engine(EngineOptions::default());
+        let mut output = Vec::new();
+        Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0))
+            .spongify_bytes(
+                io::BufReader::with_capacity(buffer_capacity, input),
+                &mut output,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn spongify_bytes_changes_only_case() {
+        let input = b"taco\ttruck  \r\n\tcaf\xc3\xa9 \xff time\r\n  e\xcc\x81e \x01stra\xc3\x9fe\t\n\xe2\x82";
+        // Small buffers split characters, grapheme clusters, and CRLF across reads
+        for capacity in [1, 2, 3, 8192] {
+            let output = spongify_bytes(CapitalizationStrategy::default(), input, capacity);
+            assert_eq!(
+                output,
+                b"TaCo\ttRuCk  \r\n\tCaF\xc3\xa9 \xff TiMe\r\n  e\xcc\x81E \x01sTrA\xc3\x9fE\t\n\xe2\x82",
+                "with a {capacity} byte buffer"
+            );
+        }
+
+        // Leet digits are not a case of the letter they replace, so those letters are left as they are
+        let input = b"leet speak\r\n\ttime  ";
+        let output = spongify_bytes(CapitalizationStrategy::Leet, input, 8192);
+        assert_ne!(&output[..], input);
+        assert!(output.eq_ignore_ascii_case(input));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_spans() {
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+        /// Records the name of every span created.
+        struct SpanNames(Arc<Mutex<Vec<&'static str>>>);
+
+        impl<S: tracing::Subscriber> Layer<S> for SpanNames {
+            fn on_new_span(
+                &self,
+                attrs: &tracing::span::Attributes,
+                _: &tracing::span::Id,
+                _: Context<S>,
+            ) {
+                self.0.lock().unwrap().push(attrs.metadata().name());
+            }
+        }
+
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(SpanNames(names.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            spongify_str("taco\ntruck", &TextOpt::default());
+        });
+
+        let names = names.lock().unwrap();
+        assert_eq!(
+            names
+                .iter()
+                .filter(|name| **name == "spongify_line")
+                .count(),
+            2
+        );
+        assert!(names.contains(&"stage"));
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn warnings_are_tracing_events() {
+        use std::sync::{Arc, Mutex};
+        use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+        /// Records the level of every event.
+        struct EventLevels(Arc<Mutex<Vec<tracing::Level>>>);
+
+        impl<S: tracing::Subscriber> Layer<S> for EventLevels {
+            fn on_event(&self, event: &tracing::Event, _: Context<S>) {
+                self.0.lock().unwrap().push(*event.metadata().level());
+            }
+        }
+
+        let levels = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry().with(EventLevels(levels.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            warn!("the {} caption is empty, so it will not be drawn", "top");
+        });
+
+        assert_eq!(*levels.lock().unwrap(), [tracing::Level::WARN]);
+    }
+
+    #[test]
+    fn list_fonts_includes_anton() {
+        let opt = Opt::try_parse_from(["spongify", "fonts"]).unwrap();
+        assert!(matches!(opt.command, Some(Command::Fonts)));
+
+        let mut output = Vec::new();
+        list_fonts(&mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(
+            output.lines().any(|line| line == "Anton: Latin"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn validate_reports_every_problem() {
+        let check = |args: &[&str]| {
+            let opt = Opt::try_parse_from(["spongify"].iter().chain(args)).unwrap();
+            let mut output = Vec::new();
+            let result = validate(&opt, &mut output);
+            (result.is_ok(), String::from_utf8(output).unwrap())
+        };
+
+        assert_eq!(
+            check(&["--seed", "7", "--style", "LiKe tHiS", "taco"]),
+            (
+                false,
+                "error: --seed only applies to --style RaNDOmlY and --shuffle-words\n".to_owned()
+            )
+        );
+        assert_eq!(
+            check(&["--seed", "7", "--style", "RaNDOmlY", "taco"]),
+            (true, String::new())
+        );
+        assert_eq!(
+            check(&["--seed", "7", "--shuffle-words", "taco"]),
+            (true, String::new())
+        );
+
+        let (ok, output) = check(&[
+            "--seed",
+            "7",
+            "--random-bias",
+            "0.7",
+            "--image",
+            "--image-quality",
+            "90",
+            "--output-file",
+            "meme.png",
+            "taco",
+        ]);
+        assert!(!ok);
+        assert_eq!(output.lines().count(), 3, "{output}");
+    }
+
+    #[test]
+    fn batch_runs_every_job() {
+        let dir = std::env::temp_dir().join(format!("spongify-batch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("taco.txt"), "taco truck\n").unwrap();
+        let manifest = dir.join("manifest.json");
+        fs::write(
+            &manifest,
+            r#"[
+                { "input": "taco.txt", "output": "spongified.txt" },
+                { "input": "taco.txt", "output": "words.txt", "style": "like THIS" }
+            ]"#,
+        )
+        .unwrap();
+
+        let mut report = Vec::new();
+        run_batch(&manifest, &mut report).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("spongified.txt")).unwrap(),
+            "TaCo tRuCk\n"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("words.txt")).unwrap(),
+            "taco TRUCK\n"
+        );
+        assert_eq!(
+            String::from_utf8(report).unwrap(),
+            "job 1 (taco.txt -> spongified.txt): ok\njob 2 (taco.txt -> words.txt): ok\n"
+        );
+
+        // A job which fails does not stop the ones after it
+        fs::write(
+            &manifest,
+            r#"[
+                { "input": "burrito.txt", "output": "burrito-out.txt" },
+                { "input": "taco.txt", "output": "bad-style.txt", "style": "taco" },
+                { "input": "taco.txt", "output": "again.txt" }
+            ]"#,
+        )
+        .unwrap();
+        let mut report = Vec::new();
+        let err = run_batch(&manifest, &mut report).unwrap_err();
+        assert_eq!(err.to_string(), "2 of 3 batch jobs failed");
+        let report = String::from_utf8(report).unwrap();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert!(
+            lines[0].starts_with("job 1 (burrito.txt -> burrito-out.txt): failed: "),
+            "{report}"
+        );
+        assert!(
+            lines[1].starts_with("job 2 (taco.txt -> bad-style.txt): failed: "),
+            "{report}"
+        );
+        assert_eq!(lines[2], "job 3 (taco.txt -> again.txt): ok");
+        assert_eq!(
+            fs::read_to_string(dir.join("again.txt")).unwrap(),
+            "TaCo tRuCk\n"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn diff_subcommand() {
+        let opt =
+            Opt::try_parse_from(["spongify", "diff", "--style", "like THIS", "taco"]).unwrap();
+        let Some(Command::Diff { args }) = opt.command else {
+            panic!("not the diff subcommand: {:?}", opt.command);
+        };
+        let opt = diff_options(&args).unwrap();
+        assert!(opt.diff);
+        assert_eq!(opt.style, CapitalizationStrategy::AlternatingWords);
+        assert_eq!(opt.input.inline, ["taco"]);
+
+        for conflict in ["--image", "--filter", "--parallel"] {
+            let err = diff_options(&[conflict.into()]).unwrap_err();
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+            assert!(
+                err.to_string()
+                    .contains(&format!("spongify diff cannot be used with {conflict}")),
+                "{err}"
+            );
+        }
+
+        // After other arguments, the word is text to SpOnGiFy
+        let opt = Opt::try_parse_from(["spongify", "--stats", "diff"]).unwrap();
+        assert!(opt.command.is_none() && !opt.diff);
+        assert_eq!(opt.input.inline, ["diff"]);
+    }
+
+    #[test]
+    fn completions() {
+        // The subcommand does not need the arguments a normal run does
+        let opt = Opt::try_parse_from(["spongify", "completions", "zsh"]).unwrap();
+        assert!(matches!(
+            opt.command,
+            Some(Command::Completions {
+                shell: clap_complete::Shell::Zsh
+            })
+        ));
+        // The documentation of the subcommands is not the description of SpOnGiFy itself
+        assert!(Opt::command().get_about().is_none());
+
+        // After other arguments, the words are text to SpOnGiFy
+        let opt = Opt::try_parse_from(["spongify", "--stats", "completions", "zsh"]).unwrap();
+        assert!(opt.command.is_none());
+        assert_eq!(opt.input.inline, ["completions", "zsh"]);
+
+        for shell in [
+            clap_complete::Shell::Bash,
+            clap_complete::Shell::Zsh,
+            clap_complete::Shell::Fish,
+            clap_complete::Shell::PowerShell,
+        ] {
+            let mut output = Vec::new();
+            clap_complete::generate(shell, &mut Opt::command(), "spongify", &mut output);
+            let output = String::from_utf8(output).unwrap();
+            assert!(
+                output.contains("style"),
+                "{shell} completions are missing --style"
+            );
+        }
+    }
+
+    #[test]
+    fn examples_cover_each_mode() {
+        let opt = Opt::try_parse_from(["spongify", "examples"]).unwrap();
+        assert!(matches!(opt.command, Some(Command::Examples)));
+
+        let mut output = Vec::new();
+        print_examples(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        let commands = output
+            .lines()
+            .filter_map(|line| line.strip_prefix("  $ "))
+            .collect::<Vec<_>>();
+        for mode in [
+            "spongify \"",
+            "--style",
+            "--file",
+            "| spongify -",
+            "--image",
+            "-c",
+        ] {
+            assert!(
+                commands.iter().any(|command| command.contains(mode)),
+                "no example for {mode}: {output}"
+            );
+        }
+        assert!(output.contains("  YoUr tExT HeRe\n"), "{output}");
+        assert!(
+            output.contains("  $ cat notes.txt\n  buy milk\n  call the plumber\n"),
+            "{output}"
+        );
+        assert!(!output.contains("(the SpOnGiFied"), "{output}");
+        assert!(
+            output.contains("  HeLlO\n  Is iT Me yOu'rE LoOkInG FoR?\n"),
+            "{output}"
+        );
+    }
+
+    #[test]
+    fn diacritics_are_preserved() {
+        use unicode_normalization::UnicodeNormalization;
+
+        let text = TextOpt::default();
+        for input in [
+            // Precomposed (NFC) Latin
+            "éèêëñçåø ÉÈÊËÑÇÅØ",
+            // Decomposed (NFD) Latin
+            "e\u{301}e\u{300}n\u{303}a\u{30a} E\u{301}N\u{303}",
+            // Greek with tonos, dialytika, and iota subscript
+            "άέήίόύώ ϊϋΐΰ ᾳῃῳᾀᾷ",
+        ] {
+            let output = spongify_str(input, &text);
+            let output = output.trim_end();
+            assert_eq!(
+                output.graphemes(true).count(),
+                input.graphemes(true).count(),
+                "{input} -> {output}"
+            );
+            assert_eq!(
+                output.to_lowercase().nfd().collect::<String>(),
+                input.to_lowercase().nfd().collect::<String>(),
+                "{input} -> {output}"
+            );
+        }
+    }
+
+    #[test]
+    fn grapheme_clusters_are_cased_together() {
+        let text = TextOpt::default();
+        assert_eq!(
+            spongify_str("e\u{301}e\u{301}e", &text),
+            "E\u{301}e\u{301}E"
+        );
+        assert_eq!(spongify_str("ᾳᾳ", &text), "ᾼᾳ");
+        assert_eq!(spongify_str("ßß", &text), "SSß");
+    }
+
+    #[test]
+    fn case_mapping_expansions_keep_alternation() {
+        let text = TextOpt::default();
+        let mut capitalizer = CapitalizationStrategy::AlternatingInitialUppercase
+            .create_engine(EngineOptions::default());
+        let mut spongifier = Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0));
+        let mut stats = Stats::new();
+
+        // "ß" uppercases to "SS" and "İ" lowercases to "i\u{307}", but each is still one step of the pattern and
+        // counts as one changed character
+        assert_eq!(spongifier.spongify_line("straße", &mut stats), "StRaSSe");
+        assert_eq!(
+            spongifier.spongify_line("İİİ", &mut stats),
+            "i\u{307}İi\u{307}"
+        );
+        assert_eq!(
+            (stats.uppercased, stats.lowercased, stats.unchanged),
+            (3, 2, 4)
+        );
+    }
+
+    #[test]
+    fn alternate_lines() {
+        let text = TextOpt {
+            alternate_lines: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            spongify_str("taco truck\ntaco truck\ntaco truck\ntaco truck", &text),
+            "TaCo tRuCk\ntaco truck\ntAcO TrUcK\ntaco truck"
+        );
+
+        let text = TextOpt {
+            alternate_lines: true,
+            alternate_even: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            spongify_str("taco truck\ntaco truck\ntaco truck\ntaco truck", &text),
+            "taco truck\nTaCo tRuCk\ntaco truck\ntAcO TrUcK"
+        );
+    }
+
+    #[test]
+    fn transform_order_changes_output() {
+        let spongify_in = |order: &str| {
+            let text = TextOpt {
+                transform_order: Some(order.parse().unwrap()),
+                ..Default::default()
+            };
+            spongify_str("the quick brown fox jumps over the lazy dog", &text)
+        };
+        let shuffled_first = spongify_in("shuffle-words,spongify");
+        let shuffled_last = spongify_in("spongify,shuffle-words");
+        assert_ne!(shuffled_first, shuffled_last);
+
+        // Shuffling first SpOnGiFies the words in their new places, so the pattern still alternates along the line
+        let alternates = |line: &str| {
+            line.chars()
+                .enumerate()
+                .filter(|(_, c)| c.is_alphabetic())
+                .all(|(index, c)| c.is_uppercase() == index.is_multiple_of(2))
+        };
+        assert!(alternates(&shuffled_first), "{shuffled_first}");
+        assert!(!alternates(&shuffled_last), "{shuffled_last}");
+
+        let sorted = |line: &str| {
+            let mut words = line
+                .split_whitespace()
+                .map(str::to_lowercase)
+                .collect::<Vec<_>>();
+            words.sort();
+            words
+        };
+        assert_eq!(sorted(&shuffled_first), sorted(&shuffled_last));
+    }
+
+    #[test]
+    fn spongify_transform_composes() {
+        let text = TextOpt::default();
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        let spongifier = Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0));
+
+        let mut pipeline = Pipeline::new()
+            .then(|line: &str| line.replace("taco", "burrito"))
+            .then(spongifier)
+            .then(|line: &str| format!("{line}!"));
+        assert_eq!(pipeline.apply("taco truck"), "BuRrItO TrUcK!");
+        // The pattern carries on from the line before, counting its line break, like it does for input
+        assert_eq!(pipeline.apply("taco truck"), "BuRrItO TrUcK!");
+    }
+
+    #[test]
+    fn mmap_output_keeps_input_limits() {
+        let dir = std::env::temp_dir().join(format!("spongify-mmap-limits-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.txt");
+        let output = dir.join("out.txt");
+        fs::write(&input, "taco\ntruck\ntime\n").unwrap();
+        let spongify = |args: &[&str]| {
+            let opt = Opt::try_parse_from(
+                [
+                    "spongify",
+                    "--file",
+                    input.to_str().unwrap(),
+                    "--output-file",
+                    output.to_str().unwrap(),
+                ]
+                .iter()
+                .chain(args),
+            )
+            .unwrap();
+            run(opt)
+        };
+
+        assert!(spongify(&["--mmap-output", "--input-limit-lines", "1"]).is_err());
+        spongify(&[
+            "--mmap-output",
+            "--input-limit-lines",
+            "1",
+            "--input-limit-action",
+            "warn",
+        ])
+        .unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "TaCo\n");
+        spongify(&[
+            "--mmap-output",
+            "--input-limit-bytes",
+            "8",
+            "--input-limit-action",
+            "warn",
+        ])
+        .unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "TaCo\ntRu");
+
+        // Without limits, the map is used and gives the same output as normal writes
+        spongify(&["--mmap-output"]).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), "TaCo\ntRuCk\ntImE\n");
+        let count_bytes = [
+            "spongify",
+            "--file",
+            "in.txt",
+            "-o",
+            "out.txt",
+            "--mmap-output",
+            "--count-bytes",
+        ];
+        assert!(Opt::try_parse_from(count_bytes).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn count_bytes_changes_with_case() {
+        let counts = ByteCounts::default();
+        let input = CountingReader::new(io::Cursor::new("taſtı\n"), counts.read.clone());
+        let mut output = CountingWriter::new(Vec::new(), counts.written.clone());
+
+        let text = TextOpt::default();
+        let mut capitalizer = CapitalizationStrategy::FromPattern("u".parse().unwrap())
+            .create_engine(EngineOptions::default());
+        Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0))
+            .spongify_lines(
+                input,
+                &mut output,
+                true,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+
+        // "ſ" and "ı" are two bytes each, but their uppercase forms "S" and "I" are one
+        assert_ne!(counts.read.get(), counts.written.get());
+        assert_eq!(counts.read.get(), 8);
+        assert_eq!(counts.written.get(), 6);
+        assert_eq!(counts.to_string(), " bytes in: 8\nbytes out: 6\n");
+    }
+
+    #[test]
+    fn lossy_utf8() {
+        let spongify = |lossy_utf8| {
+            let text = TextOpt {
+                lossy_utf8,
+                ..Default::default()
+            };
+            let mut capitalizer =
+                CapitalizationStrategy::default().create_engine(EngineOptions::default());
+            let mut output = Vec::new();
+            Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0))
+                .spongify_lines(
+                    io::Cursor::new(b"taco \xff truck\r\nsalsa".to_vec()),
+                    &mut output,
+                    true,
+                    &mut Stats::new(),
+                    &mut Timings::new(),
+                )
+                .map(|()| String::from_utf8(output).unwrap())
+        };
+
+        assert_eq!(spongify(true).unwrap(), "TaCo \u{fffd} tRuCk\r\nsAlSa");
+
+        let err = spongify(false).unwrap_err();
+        assert!(err
+            .downcast_ref::<io::Error>()
+            .is_some_and(|err| err.kind() == io::ErrorKind::InvalidData));
+    }
+
+    #[test]
+    fn line_endings() {
+        let input = "taco\r\ntruck\ntime";
+        assert_eq!(
+            spongify_str(input, &TextOpt::default()),
+            "TaCo\r\ntRuCk\ntImE"
+        );
+        assert_eq!(spongify_str("taco\r\n", &TextOpt::default()), "TaCo\r\n");
+
+        for (line_ending, expected) in [
+            (LineEnding::Lf, "TaCo\ntRuCk\ntImE\n"),
+            (LineEnding::Crlf, "TaCo\r\ntRuCk\r\ntImE\r\n"),
+        ] {
+            let text = TextOpt {
+                line_ending,
+                ..Default::default()
+            };
+            assert_eq!(spongify_str(input, &text), expected, "for {line_ending:?}");
+        }
+    }
+
+    #[test]
+    fn alternation_continues_across_lines() {
+        // The line break counts as a character, so the pattern continues as if the input were one stream
+        assert_eq!(spongify_str("ab\ncd", &TextOpt::default()), "Ab\ncD");
+
+        let mut output = Vec::new();
+        let mut capitalizer = CapitalizationStrategy::AlternatingInitialUppercaseSkipWhitespace
+            .create_engine(EngineOptions::default());
+        Spongifier::new(
+            &TextOpt::default(),
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )
+        .spongify_lines(
+            io::Cursor::new("ab\ncd"),
+            &mut output,
+            true,
+            &mut Stats::new(),
+            &mut Timings::new(),
+        )
+        .unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "Ab\nCd");
+    }
+
+    #[test]
+    fn reset_per_line() {
+        let input = "taco\ntruck\nburrito";
+        assert_eq!(
+            spongify_str(input, &TextOpt::default()),
+            "TaCo\ntRuCk\nbUrRiTo"
+        );
+
+        let text = TextOpt {
+            reset_per_line: true,
+            ..Default::default()
+        };
+        assert_eq!(spongify_str(input, &text), "TaCo\nTrUcK\nBuRrItO");
+    }
+
+    #[test]
+    fn alternate_lines_parallel() {
+        let spongify = |alternate_even| {
+            let text = TextOpt {
+                alternate_lines: true,
+                alternate_even,
+                ..Default::default()
+            };
+            let strategy = CapitalizationStrategy::default();
+            let mut output = Vec::new();
+            ParallelSpongifier::new(
+                &text,
+                &strategy,
+                0,
+                DEFAULT_RANDOM_BIAS,
+                NonZeroUsize::new(2),
+            )
+            .unwrap()
+            .spongify_lines(
+                io::Cursor::new("taco\ntaco\ntaco\ntaco"),
+                &mut output,
+                true,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(spongify(false), "TaCo\ntaco\nTaCo\ntaco");
+        assert_eq!(spongify(true), "taco\nTaCo\ntaco\nTaCo");
+    }
+
+    #[test]
+    fn shuffle_words_spongified() {
+        let text = TextOpt {
+            shuffle_words: true,
+            ..Default::default()
+        };
+
+        let spongify = |seed| {
+            let mut output = Vec::new();
+            let mut capitalizer =
+                CapitalizationStrategy::default().create_engine(EngineOptions::default());
+            Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(seed))
+                .spongify_lines(
+                    io::Cursor::new("one two three four\nfive six"),
+                    &mut output,
+                    true,
+                    &mut Stats::new(),
+                    &mut Timings::new(),
+                )
+                .unwrap();
+            String::from_utf8(output).unwrap()
+        };
+
+        assert_eq!(spongify(7), "TwO ThReE FoUr oNe\nsIx fIvE");
+        assert_eq!(spongify(7), spongify(7));
+    }
+
+    #[test]
+    fn parallel_thread_count_is_deterministic() {
+        let text = TextOpt {
+            shuffle_words: true,
+            ..Default::default()
+        };
+        let strategy = CapitalizationStrategy::default();
+        let input = (0..200)
+            .map(|x| format!("line {x} has some words in it\n"))
+            .collect::<String>();
+
+        let spongify = |threads| {
+            let mut output = Vec::new();
+            let mut stats = Stats::new();
+            ParallelSpongifier::new(
+                &text,
+                &strategy,
+                42,
+                DEFAULT_RANDOM_BIAS,
+                NonZeroUsize::new(threads),
+            )
+            .unwrap()
+            .spongify_lines(
+                io::Cursor::new(&input),
+                &mut output,
+                true,
+                &mut stats,
+                &mut Timings::new(),
+            )
+            .unwrap();
+            (String::from_utf8(output).unwrap(), stats)
+        };
+
+        let (single, single_stats) = spongify(1);
+        let (multi, multi_stats) = spongify(4);
+        assert_eq!(single, multi);
+        assert_eq!(single_stats, multi_stats);
+        assert_eq!(single.lines().count(), 200);
+        assert!(!single
+            .lines()
+            .any(|line| line.starts_with(char::is_lowercase)));
+    }
+
+    #[test]
+    fn top_and_bottom_captions() {
+        let captions = |args: &[&str]| {
+            let opt = Opt::try_parse_from(["spongify", "--image"].iter().chain(args))?;
+            let mut capitalizer =
+                CapitalizationStrategy::default().create_engine(EngineOptions::default());
+            let mut engine = LineEngine::Serial(Box::new(Spongifier::new(
+                &opt.text,
+                capitalizer.as_mut(),
+                StdRng::seed_from_u64(0),
+            )));
+            opt.image.captions(
+                Box::new(io::Cursor::new("taco truck\nsalsa")),
+                &mut engine,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+        };
+        let some = |text: &str| Some(text.to_owned());
+
+        assert_eq!(
+            captions(&["--top", "taco", "--bottom", "truck"]).unwrap(),
+            (some("TaCo"), some("tRuCk"))
+        );
+        assert_eq!(
+            captions(&["--bottom", "truck"]).unwrap(),
+            (None, some("TrUcK"))
+        );
+        assert_eq!(
+            captions(&["--top", "taco", "--caption-case", "upper"]).unwrap(),
+            (some("TACO"), None)
+        );
+        // Without them, the caption from the input is split
+        assert_eq!(captions(&[]).unwrap(), (some("TaCo tRuCk"), some("sAlSa")));
+        assert_eq!(
+            captions(&["--no-spongify-caption"]).unwrap(),
+            (some("taco truck"), some("salsa"))
+        );
+
+        assert!(captions(&["--top", "taco", "--caption", "truck"]).is_err());
+        assert!(captions(&["--top", "taco", "--top-only"]).is_err());
+        assert!(captions(&["--no-spongify-caption", "--caption-case", "upper"]).is_err());
+    }
+
+    #[test]
+    fn hyphenate_lang() {
+        let hyphenate = |args: &[&str]| {
+            Opt::try_parse_from(["spongify", "--image"].iter().chain(args))
+                .map(|opt| opt.image.render_options().unwrap().hyphenate)
+        };
+
+        assert_eq!(hyphenate(&[]).unwrap(), None);
+        assert_eq!(
+            hyphenate(&["--hyphenate"]).unwrap(),
+            Some(hypher::Lang::English)
+        );
+        for (tag, lang) in [
+            ("de", hypher::Lang::German),
+            ("en-GB", hypher::Lang::English),
+            ("FR_ca", hypher::Lang::French),
+        ] {
+            assert_eq!(
+                hyphenate(&["--hyphenate", "--hyphenate-lang", tag]).unwrap(),
+                Some(lang)
+            );
+        }
+
+        for tag in ["english", "xx-XX"] {
+            assert!(hyphenate(&["--hyphenate", "--hyphenate-lang", tag]).is_err());
+        }
+        // The language means nothing without --hyphenate
+        assert!(hyphenate(&["--hyphenate-lang", "de"]).is_err());
+    }
+
+    #[test]
+    fn profile_resolves_render_options() {
+        let render_options = |args: &[&str]| {
+            Opt::try_parse_from(["spongify", "--image"].iter().chain(args))
+                .unwrap()
+                .image
+                .render_options()
+                .unwrap()
+        };
+
+        let pretty = render_options(&["--profile", "pretty"]);
+        assert_eq!(pretty.supersample, Some(4));
+        assert!(!pretty.no_kerning);
+        assert_eq!(pretty.outline.map(|outline| outline.width), Some(2));
+
+        let balanced = render_options(&["--profile", "balanced"]);
+        assert_eq!(balanced.supersample, Some(2));
+        assert!(!balanced.no_kerning);
+        assert_eq!(
+            balanced.outline.map(|outline| outline.width),
+            Some(DEFAULT_OUTLINE_WIDTH)
+        );
+
+        let fast = render_options(&["--profile", "fast"]);
+        assert_eq!(fast.supersample, Some(1));
+        assert!(fast.no_kerning);
+        assert!(fast.outline.is_none());
+
+        // Flags override the profile
+        let overridden = render_options(&["--profile", "fast", "--supersample", "2", "--kerning"]);
+        assert_eq!(overridden.supersample, Some(2));
+        assert!(!overridden.no_kerning);
+        let overridden = render_options(&["--profile", "pretty", "--outline", "5", "--no-kerning"]);
+        assert_eq!(overridden.outline.map(|outline| outline.width), Some(5));
+
+        // Without a profile, captions have a black outline unless it is turned off
+        let default = render_options(&[]);
+        assert_eq!(
+            default.outline,
+            Some(Outline {
+                width: 2,
+                style: OutlineStyle::Round,
+                color: imagemacro::OUTLINE_COLOR
+            })
+        );
+        assert!(render_options(&["--outline", "0"]).outline.is_none());
+        let colored = render_options(&["--profile", "fast", "--outline-color", "red"]);
+        assert_eq!(colored.outline.map(|outline| outline.width), Some(2));
+        assert!(Opt::try_parse_from(["spongify", "--image", "--outline", "65"]).is_err());
+        assert!(overridden.no_kerning);
+
+        let default = render_options(&[]);
+        assert_eq!(default.supersample, None);
+        assert!(!default.no_kerning);
+    }
+
+    #[test]
+    fn caption_with_base_image() {
+        let path = std::env::temp_dir().join(format!("spongify-base-{}.png", std::process::id()));
+        image::RgbaImage::from_pixel(400, 200, image::Rgba([0, 0, 255, 255]))
+            .save(&path)
+            .unwrap();
+
+        let opt = Opt::try_parse_from([
+            "spongify".as_ref(),
+            "--image".as_ref(),
+            "--caption".as_ref(),
+            "taco truck".as_ref(),
+            "--base-image".as_ref(),
+            path.as_os_str(),
+        ])
+        .unwrap();
+        let base_image = opt.image.load_base_image();
+        fs::remove_file(&path).unwrap();
+
+        let mut caption = String::new();
+        opt.image
+            .caption_reader()
+            .unwrap()
+            .expect("--caption was given")
+            .read_to_string(&mut caption)
+            .unwrap();
+        assert_eq!(caption, "taco truck");
+
+        let options = RenderOptions {
+            base_image: base_image.unwrap(),
+            ..Default::default()
+        };
+        let image = imagemacro::generate_image(Some(&caption), None, &options, &mut Timings::new())
+            .unwrap();
+        assert_eq!(image.dimensions(), (400, 200));
+        assert!(image.pixels().any(|pixel| pixel.0 == [255, 255, 255, 255]));
+        assert_eq!(*image.get_pixel(0, 199), image::Rgba([0, 0, 255, 255]));
+
+        assert!(
+            Opt::try_parse_from(["spongify", "--image", "--caption", "taco", "truck"]).is_err()
+        );
+
+        // Files which are not images in a format we can read are an error
+        fs::write(&path, "taco truck").unwrap();
+        let opt = Opt::try_parse_from([
+            "spongify".as_ref(),
+            "--image".as_ref(),
+            "--caption".as_ref(),
+            "taco truck".as_ref(),
+            "--background".as_ref(),
+            path.as_os_str(),
+        ])
+        .unwrap();
+        let base_image = opt.image.load_base_image();
+        fs::remove_file(&path).unwrap();
+        assert!(base_image.is_err());
+    }
+
+    #[test]
+    fn caption_from_clip() {
+        let opt = Opt::try_parse_from([
+            "spongify",
+            "--image",
+            "--caption-from-clip",
+            "--base-image",
+            "-",
+        ])
+        .unwrap();
+        assert!(opt.image.has_caption());
+
+        let mut caption = String::new();
+        opt.image
+            .caption_reader_with(|| Ok("taco truck\n".to_owned()))
+            .unwrap()
+            .expect("--caption-from-clip was given")
+            .read_to_string(&mut caption)
+            .unwrap();
+        assert_eq!(caption, "taco truck\n");
+
+        // An empty clipboard is an error rather than an image without a caption
+        let error = opt
+            .image
+            .caption_reader_with(|| Ok(" \n".to_owned()))
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("empty"), "{error}");
+
+        assert!(Opt::try_parse_from([
+            "spongify",
+            "--image",
+            "--caption-from-clip",
+            "--caption",
+            "taco"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn caption_from_stdin_lines() {
+        let dir = std::env::temp_dir().join(format!("spongify-lines-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("meme-{n}.png");
+        let opt = Opt::try_parse_from([
+            "spongify".as_ref(),
+            "--image".as_ref(),
+            "--caption-from-stdin-lines".as_ref(),
+            "3".as_ref(),
+            "--bottom-only".as_ref(),
+            "-o".as_ref(),
+            template.as_os_str(),
+        ])
+        .unwrap();
+
+        let text = TextOpt::default();
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+        let mut engine = LineEngine::Serial(Box::new(Spongifier::new(
+            &text,
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )));
+        let lines = ["taco", "truck", "time"].map(str::to_owned);
+        let captions = opt
+            .image
+            .line_captions(&lines, &mut engine, &mut Stats::new(), &mut Timings::new())
+            .unwrap();
+        assert_eq!(
+            captions,
+            [
+                (None, Some("TaCo".to_owned())),
+                (None, Some("tRuCk".to_owned())),
+                (None, Some("tImE".to_owned())),
+            ]
+        );
+
+        let options = RenderOptions {
+            base_image: Some(image::RgbaImage::from_pixel(
+                80,
+                80,
+                image::Rgba([0, 0, 255, 255]),
+            )),
+            ..Default::default()
+        };
+        let paths = write_numbered_macros(
+            &captions,
+            &template.to_string_lossy(),
+            opt.output.image_format().unwrap(),
+            &opt.image,
+            &options,
+            &mut Timings::new(),
+        )
+        .unwrap();
+        assert_eq!(
+            paths,
+            (1..=3)
+                .map(|n| dir.join(format!("meme-{n}.png")))
+                .collect::<Vec<_>>()
+        );
+        for path in &paths {
+            let image = image::open(path).unwrap();
+            assert_eq!(image.width(), 80);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Without {n}, every image would overwrite the last
+        assert!(write_numbered_macros(
+            &captions,
+            "meme.png",
+            MacroFormat::Raster(image::ImageFormat::Png),
+            &opt.image,
+            &options,
+            &mut Timings::new(),
+        )
+        .is_err());
+    }
+
+    fn read_caption_with(case: CaptionCase, strategy: CapitalizationStrategy) -> String {
+        let text = TextOpt::default();
+        let mut capitalizer = strategy.create_engine(EngineOptions::default());
+        let mut engine = LineEngine::Serial(Box::new(Spongifier::new(
+            &text,
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )));
+
+        read_caption(
+            io::Cursor::new("Taco truck\ntime"),
+            case,
+            &mut engine,
+            &mut Stats::new(),
+            &mut Timings::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn caption_case() {
+        use CapitalizationStrategy::*;
+
+        assert_eq!(
+            read_caption_with(CaptionCase::Upper, Randomly),
+            "TACO TRUCK\nTIME"
+        );
+        assert_eq!(
+            read_caption_with(CaptionCase::Lower, Randomly),
+            "taco truck\ntime"
+        );
+        assert_eq!(
+            read_caption_with(CaptionCase::AsIs, Randomly),
+            "Taco truck\ntime"
+        );
+        assert_eq!(
+            read_caption_with(CaptionCase::Spongify, AlternatingInitialUppercase),
+            "TaCo tRuCk\ntImE"
+        );
+    }
+
+    #[test]
+    fn spongify_lines_records_stats() {
+        let mut stats = Stats::with_words();
+        let mut output = Vec::new();
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+
+        Spongifier::new(
+            &TextOpt::default(),
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )
+        .spongify_lines(
+            io::Cursor::new("a bb ccc\nDDD!"),
+            &mut output,
+            true,
+            &mut stats,
+            &mut Timings::new(),
+        )
+        .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "A Bb cCc\ndDd!");
+        assert_eq!(
+            (stats.uppercased, stats.lowercased, stats.unchanged),
+            (3, 2, 7)
+        );
+
+        let words = stats.words.unwrap();
+        assert_eq!(words.count, 4);
+        assert_eq!(words.longest, "cCc");
+        assert_eq!((words.uppercase, words.lowercase, words.mixed), (1, 0, 3));
+    }
+
+    #[test]
+    fn spongify_lines_records_timings() {
+        let mut timings = Timings::new();
+        let mut output = Vec::new();
+        let mut capitalizer =
+            CapitalizationStrategy::default().create_engine(EngineOptions::default());
+
+        Spongifier::new(
+            &TextOpt::default(),
+            capitalizer.as_mut(),
+            StdRng::seed_from_u64(0),
+        )
+        .spongify_lines(
+            io::Cursor::new("taco\ntruck"),
+            &mut output,
+            true,
+            &mut Stats::new(),
+            &mut timings,
+        )
+        .unwrap();
+
+        assert_eq!(String::from_utf8(output).unwrap(), "TaCo\ntRuCk");
+        assert!(timings.get(Stage::Read).is_some());
+        assert!(timings.get(Stage::Spongify).is_some());
+    }
 }
@@ -0,0 +1,166 @@
+//! Memory-Mapped Output
+//! ====================
+//!
+//! Writes SpOnGiFied output through a memory map for `--mmap-output`, which avoids a copy through a write buffer for
+//! very large files. A map needs its size up front, which is only known when the input is a file and SpOnGiFying does
+//! not change its length. So this only works when:
+//!
+//! * the input is entirely ASCII, since Unicode case mappings can change the length of text (like `ß` to `SS`),
+//! * `--shuffle-words` is not used, since it collapses whitespace, and
+//! * the output is a regular file (not a named pipe).
+//!
+//! Otherwise, the caller is expected to fall back to buffered writes.
+
+use crate::{timing::Stage, Result, Spongifier, Stats, Timings};
+use memmap2::{Mmap, MmapMut};
+use std::{fs, path::Path, time::Instant};
+
+/// SpOnGiFy the file at `input_path` into the file at `output_path` through a memory map, with each line terminated as
+/// `--line-ending` says like `Spongifier::spongify_lines`.
+///
+/// # Return
+/// `false` if the input or output does not meet the constraints of the module, in which case nothing was written.
+pub fn spongify_mmap(
+    spongifier: &mut Spongifier,
+    input_path: &Path,
+    output_path: &Path,
+    stats: &mut Stats,
+    timings: &mut Timings,
+) -> Result<bool> {
+    if spongifier.text.shuffle_words {
+        return Ok(false);
+    }
+    if fs::metadata(output_path).is_ok_and(|meta| !meta.is_file()) {
+        return Ok(false);
+    }
+
+    let input_file = fs::File::open(input_path)?;
+    // SAFETY: The map is only read. Like any map, it is undefined behavior if another process changes the file while
+    // it is mapped; that is the documented risk of `--mmap-output`.
+    let source = timings.time(Stage::Read, || unsafe { Mmap::map(&input_file) })?;
+    if !source.is_ascii() {
+        return Ok(false);
+    }
+    let source = std::str::from_utf8(&source)?;
+
+    let start = Instant::now();
+    let line_ending = spongifier.text.line_ending;
+    let lines = || {
+        source
+            .split_inclusive('\n')
+            .map(|line| match line.strip_suffix('\n') {
+                Some(line) => match line.strip_suffix('\r') {
+                    Some(line) => (line, line_ending.terminator("\r\n")),
+                    None => (line, line_ending.terminator("\n")),
+                },
+                None => (line, line_ending.terminator("")),
+            })
+    };
+    let len = lines()
+        .map(|(line, terminator)| line.len() + terminator.len())
+        .sum::<usize>();
+    let output_file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output_path)?;
+    output_file.set_len(len as u64)?;
+    if len == 0 {
+        return Ok(true);
+    }
+
+    // SAFETY: The file was just created by this process and is the right length.
+    let mut output = unsafe { MmapMut::map_mut(&output_file) }?;
+    let mut position = 0;
+    for (line, terminator) in lines() {
+        let spongified = spongifier.spongify_line(line, stats);
+        if spongified.len() != line.len() {
+            return Err(
+                "SpOnGiFying changed the length of ASCII input, which should not be possible"
+                    .into(),
+            );
+        }
+
+        output[position..position + line.len()].copy_from_slice(spongified.as_bytes());
+        position += line.len();
+        output[position..position + terminator.len()].copy_from_slice(terminator.as_bytes());
+        position += terminator.len();
+    }
+    output.flush()?;
+    timings.add(Stage::Spongify, start.elapsed());
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LineEnding, TextOpt};
+    use rand::{rngs::StdRng, SeedableRng};
+    use spongify::{CapitalizationStrategy, EngineOptions};
+    use std::io;
+
+    /// SpOnGiFy `input` with both `spongify_mmap` and `Spongifier::spongify_lines`.
+    fn mmap_and_buffered(
+        name: &str,
+        input: &str,
+        line_ending: LineEnding,
+    ) -> (Option<String>, String) {
+        let dir = std::env::temp_dir();
+        let input_path = dir.join(format!("spongify-mmap-{name}-{}.in", std::process::id()));
+        let output_path = dir.join(format!("spongify-mmap-{name}-{}.out", std::process::id()));
+        fs::write(&input_path, input).unwrap();
+
+        let text = TextOpt {
+            line_ending,
+            ..Default::default()
+        };
+        let strategy = CapitalizationStrategy::default();
+        let mut capitalizer = strategy.create_engine(EngineOptions::default());
+        let mut spongifier = Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0));
+        let mapped = spongify_mmap(
+            &mut spongifier,
+            &input_path,
+            &output_path,
+            &mut Stats::new(),
+            &mut Timings::new(),
+        )
+        .unwrap()
+        .then(|| fs::read_to_string(&output_path).unwrap());
+        fs::remove_file(&input_path).unwrap();
+        let _ = fs::remove_file(&output_path);
+
+        let mut capitalizer = strategy.create_engine(EngineOptions::default());
+        let mut buffered = Vec::new();
+        Spongifier::new(&text, capitalizer.as_mut(), StdRng::seed_from_u64(0))
+            .spongify_lines(
+                io::Cursor::new(input),
+                &mut buffered,
+                true,
+                &mut Stats::new(),
+                &mut Timings::new(),
+            )
+            .unwrap();
+
+        (mapped, String::from_utf8(buffered).unwrap())
+    }
+
+    #[test]
+    fn mmap_matches_buffered() {
+        for line_ending in [LineEnding::Lf, LineEnding::Crlf, LineEnding::Keep] {
+            let input = "taco truck\r\nis here\n\nno newline";
+            let (mapped, buffered) = mmap_and_buffered("ascii", input, line_ending);
+            assert_eq!(mapped.unwrap(), buffered);
+
+            let (mapped, buffered) = mmap_and_buffered("empty", "", line_ending);
+            assert_eq!(mapped.unwrap(), buffered);
+        }
+    }
+
+    #[test]
+    fn mmap_refuses_unicode() {
+        let (mapped, _) = mmap_and_buffered("unicode", "straße", LineEnding::Keep);
+        assert_eq!(mapped, None);
+    }
+}
@@ -0,0 +1,77 @@
+//! Terminal Image Preview
+//! ======================
+//!
+//! Draws an image in the terminal for `--preview-image`, so a caption can be checked without opening the file. Each
+//! character cell shows two pixels stacked on top of each other: the upper half block (`▀`) in the foreground color
+//! and the lower one in the background color, using 24-bit ANSI colors.
+
+use std::fmt::Write;
+
+/// The number of columns to use if the terminal width is not known.
+const DEFAULT_COLUMNS: u32 = 80;
+
+/// Get the width of the terminal in columns from `COLUMNS`, or a reasonable default.
+pub fn terminal_columns() -> u32 {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+        .filter(|columns| *columns > 0)
+        .unwrap_or(DEFAULT_COLUMNS)
+}
+
+/// Get the `(columns, rows)` of characters needed to draw an image of `width` by `height` pixels at most `max_columns`
+/// wide, keeping its aspect ratio. Images are never scaled up.
+pub fn grid_size(width: u32, height: u32, max_columns: u32) -> (u32, u32) {
+    let columns = width.min(max_columns).max(1);
+    let pixel_rows = (height as u64 * columns as u64 / width.max(1) as u64).max(1) as u32;
+    (columns, pixel_rows.div_ceil(2))
+}
+
+/// Draw `image` as lines of ANSI-colored half blocks, at most `max_columns` wide.
+pub fn render(image: &image::RgbaImage, max_columns: u32) -> String {
+    let (columns, rows) = grid_size(image.width(), image.height(), max_columns);
+    let small = image::imageops::resize(
+        image,
+        columns,
+        rows * 2,
+        image::imageops::FilterType::Triangle,
+    );
+
+    let mut out = String::new();
+    for row in 0..rows {
+        for column in 0..columns {
+            let [tr, tg, tb, _] = small.get_pixel(column, row * 2).0;
+            let [br, bg, bb, _] = small.get_pixel(column, row * 2 + 1).0;
+            write!(out, "\x1b[38;2;{tr};{tg};{tb}m\x1b[48;2;{br};{bg};{bb}m▀").unwrap();
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_size_keeps_aspect() {
+        assert_eq!(grid_size(100, 50, 40), (40, 10));
+        assert_eq!(grid_size(100, 55, 40), (40, 11));
+        assert_eq!(grid_size(10, 10, 40), (10, 5));
+        assert_eq!(grid_size(1000, 1, 40), (40, 1));
+    }
+
+    #[test]
+    fn render_grid_dimensions() {
+        let image = image::RgbaImage::from_pixel(100, 50, image::Rgba([255, 0, 0, 255]));
+        let rendered = render(&image, 40);
+
+        let lines = rendered.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 10);
+        for line in lines {
+            assert_eq!(line.matches('▀').count(), 40);
+            assert!(line.starts_with("\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m"));
+            assert!(line.ends_with("\x1b[0m"));
+        }
+    }
+}
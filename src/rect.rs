@@ -0,0 +1,20 @@
+//! Rectangles
+//! ==========
+//!
+//! Areas of an image, shared by face detection and caption templates.
+
+/// A rectangle of pixels in an image.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    /// The row just past the bottom of the rectangle.
+    pub fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+}
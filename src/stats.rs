@@ -0,0 +1,238 @@
+//! Transformation Statistics
+//! =========================
+//!
+//! Counts of what SpOnGiFy did to the input, reported by `--stats`.
+
+use crate::diff::Change;
+use serde::Serialize;
+use spongify::tokenize;
+use std::fmt;
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+    /// Characters which were changed to uppercase.
+    pub uppercased: usize,
+    /// Characters which were changed to lowercase.
+    pub lowercased: usize,
+    /// Characters which came out the same as they went in.
+    pub unchanged: usize,
+    /// Word-level counts, only collected when requested with `with_words`.
+    pub words: Option<WordStats>,
+}
+
+/// Counts of the words in the output, for `--stats-verbose`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize)]
+pub struct WordStats {
+    pub count: usize,
+    /// The first of the longest words, counted in characters.
+    pub longest: String,
+    /// Words where every cased letter is uppercase.
+    pub uppercase: usize,
+    /// Words where every cased letter is lowercase.
+    pub lowercase: usize,
+    /// Words with both uppercase and lowercase letters.
+    pub mixed: usize,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an instance which also collects `WordStats`.
+    pub fn with_words() -> Self {
+        Self {
+            words: Some(WordStats::default()),
+            ..Self::default()
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.uppercased + self.lowercased + self.unchanged
+    }
+
+    /// Record that `original` was written as `output`.
+    pub fn record_char(&mut self, original: char, output: &str) {
+        let mut original_buf = [0; 4];
+        match Change::of(original.encode_utf8(&mut original_buf), output) {
+            Change::Unchanged => self.unchanged += 1,
+            Change::Uppercased => self.uppercased += 1,
+            Change::Lowercased => self.lowercased += 1,
+        }
+    }
+
+    /// Add the counts from `other` into this instance.
+    pub fn merge(&mut self, other: Stats) {
+        self.uppercased += other.uppercased;
+        self.lowercased += other.lowercased;
+        self.unchanged += other.unchanged;
+
+        if let (Some(words), Some(other)) = (&mut self.words, other.words) {
+            words.count += other.count;
+            if other.longest.chars().count() > words.longest.chars().count() {
+                words.longest = other.longest;
+            }
+            words.uppercase += other.uppercase;
+            words.lowercase += other.lowercase;
+            words.mixed += other.mixed;
+        }
+    }
+
+    /// Record the words of an `output` line, if word-level counts are being collected.
+    pub fn record_line(&mut self, output: &str) {
+        let Some(ref mut words) = self.words else {
+            return;
+        };
+
+        for (_, word) in tokenize::words(output) {
+            words.count += 1;
+            if word.chars().count() > words.longest.chars().count() {
+                words.longest = word.to_owned();
+            }
+
+            let has_upper = word.chars().any(char::is_uppercase);
+            let has_lower = word.chars().any(char::is_lowercase);
+            match (has_upper, has_lower) {
+                (true, true) => words.mixed += 1,
+                (true, false) => words.uppercase += 1,
+                (false, true) => words.lowercase += 1,
+                (false, false) => {}
+            }
+        }
+    }
+}
+
+impl Stats {
+    /// Format the counts as a line of JSON, for `--stats-format json`. The `words` object is only there with
+    /// word-level counts.
+    pub fn to_json(&self) -> String {
+        let json = JsonStats {
+            uppercased: self.uppercased,
+            lowercased: self.lowercased,
+            unchanged: self.unchanged,
+            total: self.total(),
+            words: self.words.as_ref(),
+        };
+        serde_json::to_string(&json).expect("stats are always valid JSON") + "\n"
+    }
+}
+
+/// The counts written by `Stats::to_json`, which also include the `total`.
+#[derive(Serialize)]
+struct JsonStats<'a> {
+    uppercased: usize,
+    lowercased: usize,
+    unchanged: usize,
+    total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    words: Option<&'a WordStats>,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>15}: {}", "uppercased", self.uppercased)?;
+        writeln!(f, "{:>15}: {}", "lowercased", self.lowercased)?;
+        writeln!(f, "{:>15}: {}", "unchanged", self.unchanged)?;
+        writeln!(f, "{:>15}: {}", "total", self.total())?;
+
+        if let Some(ref words) = self.words {
+            writeln!(f, "{:>15}: {}", "words", words.count)?;
+            writeln!(f, "{:>15}: {}", "longest word", words.longest)?;
+            writeln!(f, "{:>15}: {}", "uppercase words", words.uppercase)?;
+            writeln!(f, "{:>15}: {}", "lowercase words", words.lowercase)?;
+            writeln!(f, "{:>15}: {}", "mixed words", words.mixed)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_char() {
+        let mut stats = Stats::new();
+        stats.record_char('a', "A");
+        stats.record_char('ß', "SS");
+        stats.record_char('B', "b");
+        stats.record_char('c', "c");
+        stats.record_char(' ', " ");
+
+        assert_eq!(stats.uppercased, 2);
+        assert_eq!(stats.lowercased, 1);
+        assert_eq!(stats.unchanged, 2);
+        assert_eq!(stats.total(), 5);
+        assert_eq!(stats.words, None);
+    }
+
+    #[test]
+    fn record_line_words() {
+        let mut stats = Stats::with_words();
+        stats.record_line("A Bb cCc, DDD!");
+        stats.record_line("eee 42");
+
+        assert_eq!(
+            stats.words,
+            Some(WordStats {
+                count: 6,
+                longest: "cCc".to_owned(),
+                uppercase: 2,
+                lowercase: 1,
+                mixed: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn merge() {
+        let mut lhs = Stats::with_words();
+        lhs.record_char('a', "A");
+        lhs.record_line("A bb");
+
+        let mut rhs = Stats::with_words();
+        rhs.record_char('b', "b");
+        rhs.record_line("ccc DD");
+        lhs.merge(rhs);
+
+        assert_eq!((lhs.uppercased, lhs.unchanged), (1, 1));
+        assert_eq!(
+            lhs.words,
+            Some(WordStats {
+                count: 4,
+                longest: "ccc".to_owned(),
+                uppercase: 2,
+                lowercase: 2,
+                mixed: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn to_json() {
+        let mut stats = Stats::new();
+        stats.record_char('a', "A");
+        stats.record_char(' ', " ");
+        assert_eq!(
+            stats.to_json(),
+            "{\"uppercased\":1,\"lowercased\":0,\"unchanged\":1,\"total\":2}\n"
+        );
+
+        let mut stats = Stats::with_words();
+        stats.record_line("\"TaCo\" tRuCk");
+        assert_eq!(
+            stats.to_json(),
+            "{\"uppercased\":0,\"lowercased\":0,\"unchanged\":0,\"total\":0,\"words\":{\"count\":2,\
+             \"longest\":\"tRuCk\",\"uppercase\":0,\"lowercase\":0,\"mixed\":2}}\n"
+        );
+    }
+
+    #[test]
+    fn record_line_without_words() {
+        let mut stats = Stats::new();
+        stats.record_line("A Bb cCc");
+
+        assert_eq!(stats.words, None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Meme Templates
+//! ==============
+//!
+//! Loads the caption regions of a template for `--template-json`. A template is a JSON list of regions, each with the
+//! rectangle of the image the caption goes in as `[x, y, width, height]` and, optionally, how its lines are aligned
+//! (`"left"`, `"center"`, or `"right"`) and the color of its text (`"#rrggbb"`, `"#rrggbbaa"`, or a name like
+//! `"yellow"`):
+//!
+//! ```json
+//! [
+//!   { "rect": [0, 0, 400, 100], "align": "left", "color": "#ffff00" },
+//!   { "rect": [0, 300, 400, 100] }
+//! ]
+//! ```
+//!
+//! Regions are cut off at the edges of the image they are drawn on.
+
+use crate::{
+    imagemacro::{self, TemplateRegion, TextAlign},
+    rect::Rect,
+    Result,
+};
+use serde::Deserialize;
+use std::{fs, path::Path};
+
+/// A region as it is written in a template file.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RegionSpec {
+    rect: [u32; 4],
+    #[serde(default)]
+    align: AlignSpec,
+    color: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum AlignSpec {
+    Left,
+    #[default]
+    Center,
+    Right,
+}
+
+impl From<AlignSpec> for TextAlign {
+    fn from(value: AlignSpec) -> Self {
+        match value {
+            AlignSpec::Left => Self::Left,
+            AlignSpec::Center => Self::Center,
+            AlignSpec::Right => Self::Right,
+        }
+    }
+}
+
+/// Parse the regions of the template `source`.
+fn parse_template(source: &str) -> Result<Vec<TemplateRegion>> {
+    let specs: Vec<RegionSpec> = serde_json::from_str(source)?;
+    if specs.is_empty() {
+        return Err("the template has no regions".into());
+    }
+
+    specs
+        .into_iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            let [x, y, width, height] = spec.rect;
+            if width == 0 || height == 0 {
+                return Err(format!("region {} of the template is empty", index + 1).into());
+            }
+            if x.checked_add(width).is_none() || y.checked_add(height).is_none() {
+                return Err(format!("region {} of the template is too large", index + 1).into());
+            }
+            let color = spec
+                .color
+                .as_deref()
+                .map(imagemacro::parse_color)
+                .transpose()?;
+            Ok(TemplateRegion {
+                rect: Rect {
+                    x,
+                    y,
+                    width,
+                    height,
+                },
+                align: spec.align.into(),
+                color,
+            })
+        })
+        .collect()
+}
+
+/// Load the template at `path`.
+pub fn load_template(path: &Path) -> Result<Vec<TemplateRegion>> {
+    let source = fs::read_to_string(path)
+        .map_err(|e| format!("could not read template {}: {e}", path.display()))?;
+    parse_template(&source).map_err(|e| format!("invalid template {}: {e}", path.display()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_two_regions() {
+        let regions = parse_template(
+            r##"[
+                { "rect": [0, 0, 400, 100], "align": "left", "color": "#ffff0080" },
+                { "rect": [10, 300, 380, 90] }
+            ]"##,
+        )
+        .unwrap();
+
+        assert_eq!(
+            regions,
+            [
+                TemplateRegion {
+                    rect: Rect {
+                        x: 0,
+                        y: 0,
+                        width: 400,
+                        height: 100
+                    },
+                    align: TextAlign::Left,
+                    color: Some(image::Rgba([255, 255, 0, 128])),
+                },
+                TemplateRegion {
+                    rect: Rect {
+                        x: 10,
+                        y: 300,
+                        width: 380,
+                        height: 90
+                    },
+                    align: TextAlign::Center,
+                    color: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for source in [
+            "[]",
+            r##"[{ "rect": [0, 0, 0, 100] }]"##,
+            r##"[{ "rect": [0, 0, 100] }]"##,
+            r##"[{ "rect": [1, 0, 4294967295, 100] }]"##,
+            r##"[{ "rect": [0, 4294967295, 100, 100] }]"##,
+            r##"[{ "rect": [0, 0, 100, 100], "align": "justify" }]"##,
+            r##"[{ "rect": [0, 0, 100, 100], "colour": "#ffffff" }]"##,
+            r##"[{ "rect": [0, 0, 100, 100], "color": "taco" }]"##,
+            r##"[{ "rect": [0, 0, 100, 100], "color": "#fff" }]"##,
+            r##"[{ "rect": [0, 0, 100, 100], "color": "#ff€fff" }]"##,
+        ] {
+            assert!(parse_template(source).is_err(), "{source} parsed");
+        }
+    }
+}
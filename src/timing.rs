@@ -0,0 +1,106 @@
+//! Stage Timing
+//! ============
+//!
+//! Wall-clock durations of the stages of a run, reported by `--timing`. Collecting them is a handful of `Instant::now`
+//! calls, so they are always collected and only printed on request.
+
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage {
+    Read,
+    Spongify,
+    ImageLoad,
+    FontLoad,
+    Rasterize,
+    Composite,
+    Encode,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Self::Read => "read",
+            Self::Spongify => "spongify",
+            Self::ImageLoad => "image load",
+            Self::FontLoad => "font load",
+            Self::Rasterize => "rasterize",
+            Self::Composite => "composite",
+            Self::Encode => "encode",
+        };
+
+        write!(f, "{}", s)
+    }
+}
+
+/// The accumulated duration of each `Stage`, in the order the stages were first seen.
+#[derive(Debug, Default)]
+pub struct Timings {
+    stages: Vec<(Stage, Duration)>,
+}
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f`, adding the time it took to `stage`.
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let _span = enter_span!(DEBUG, "stage", %stage);
+        let start = Instant::now();
+        let ret = f();
+        self.add(stage, start.elapsed());
+        ret
+    }
+
+    /// Add `duration` to the running total for `stage`.
+    pub fn add(&mut self, stage: Stage, duration: Duration) {
+        match self.stages.iter_mut().find(|(x, _)| *x == stage) {
+            Some((_, total)) => *total += duration,
+            None => self.stages.push((stage, duration)),
+        }
+    }
+
+    /// Get the total time spent in `stage`, if it ran at all.
+    #[cfg(test)]
+    pub fn get(&self, stage: Stage) -> Option<Duration> {
+        self.stages
+            .iter()
+            .find(|(x, _)| *x == stage)
+            .map(|(_, total)| *total)
+    }
+}
+
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (stage, total) in self.stages.iter() {
+            writeln!(f, "{:>10}: {:.3?}", stage.to_string(), total)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timings_accumulate_and_report() {
+        let mut timings = Timings::new();
+        timings.add(Stage::Read, Duration::from_millis(2));
+        timings.add(Stage::Spongify, Duration::from_millis(5));
+        timings.add(Stage::Read, Duration::from_millis(3));
+
+        assert_eq!(timings.get(Stage::Read), Some(Duration::from_millis(5)));
+        assert_eq!(timings.get(Stage::Encode), None);
+
+        let report = timings.to_string();
+        let lines = report.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].trim_start().starts_with("read:"));
+        assert!(lines[1].trim_start().starts_with("spongify:"));
+    }
+}
@@ -0,0 +1,94 @@
+//! Word Tokenization
+//! =================
+//!
+//! Finding the words in a line, for the features which work on words. `words` follows the Unicode word boundary rules
+//! (UAX #29): contractions like "don't" are a single word, hyphenated words are split at the hyphen, and text written
+//! without spaces, like Chinese, is split into its ideographs. Each word comes with its byte range, so the text between
+//! words can be kept as it is.
+
+use clap::ValueEnum;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How words are found, for `--tokenize`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum Tokenizer {
+    /// Words are separated by whitespace, so punctuation is part of the word it is next to.
+    #[default]
+    Whitespace,
+    /// Words are found with the Unicode word boundary rules, leaving out punctuation and whitespace.
+    UnicodeWords,
+}
+
+impl Tokenizer {
+    /// Get the words of `text` and their byte ranges, in order.
+    pub fn words<'t>(
+        self,
+        text: &'t str,
+    ) -> Box<dyn Iterator<Item = (Range<usize>, &'t str)> + 't> {
+        match self {
+            Self::Whitespace => Box::new(text.split_whitespace().map(move |word| {
+                // `split_whitespace` gives subslices of `text`, so their offset can be found from their address
+                let start = word.as_ptr() as usize - text.as_ptr() as usize;
+                (start..start + word.len(), word)
+            })),
+            Self::UnicodeWords => Box::new(words(text)),
+        }
+    }
+}
+
+/// Get the words of `text` and their byte ranges, in order, using the Unicode word boundary rules. Only segments with
+/// a letter or digit count as words, so whitespace and punctuation are left out.
+pub fn words(text: &str) -> impl Iterator<Item = (Range<usize>, &str)> {
+    text.split_word_bound_indices()
+        .filter(|(_, word)| word.chars().any(char::is_alphanumeric))
+        .map(|(start, word)| (start..start + word.len(), word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word_list(text: &str) -> Vec<&str> {
+        words(text).map(|(_, word)| word).collect()
+    }
+
+    #[test]
+    fn words_ranges() {
+        let text = "  taco, truck!";
+        let words = words(text).collect::<Vec<_>>();
+        assert_eq!(words, [(2..6, "taco"), (8..13, "truck")]);
+        for (range, word) in words {
+            assert_eq!(&text[range], word);
+        }
+    }
+
+    #[test]
+    fn words_contractions() {
+        assert_eq!(word_list("don't you're it's"), ["don't", "you're", "it's"]);
+    }
+
+    #[test]
+    fn words_hyphenated() {
+        assert_eq!(
+            word_list("well-known taco-truck"),
+            ["well", "known", "taco", "truck"]
+        );
+    }
+
+    #[test]
+    fn words_cjk() {
+        assert_eq!(
+            word_list("我喜欢吃玉米饼"),
+            ["我", "喜", "欢", "吃", "玉", "米", "饼"]
+        );
+        assert_eq!(word_list("taco 卡车!"), ["taco", "卡", "车"]);
+    }
+
+    #[test]
+    fn tokenizer_whitespace() {
+        let text = " taco,  truck ";
+        let words = Tokenizer::Whitespace.words(text).collect::<Vec<_>>();
+        assert_eq!(words, [(1..6, "taco,"), (8..13, "truck")]);
+    }
+}
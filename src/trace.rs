@@ -0,0 +1,49 @@
+//! Tracing Spans
+//! =============
+//!
+//! With the `tracing` feature, the stages of a run are wrapped in `tracing` spans which `--log-level` prints to
+//! standard error. Without it, `enter_span!` expands to nothing, so its arguments are not even evaluated.
+//!
+//! Warnings go through `warn!`, which makes them `tracing` events when something is listening for them and prints them
+//! to standard error otherwise, so they are never lost.
+
+/// Enter a span at `$level` (a `tracing::Level` constant like `DEBUG`) with the rest of the arguments given to
+/// `tracing::span!`. The span is exited when the returned guard is dropped.
+macro_rules! enter_span {
+    ($level:ident, $($args:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        let guard = tracing::span!(tracing::Level::$level, $($args)+).entered();
+        #[cfg(not(feature = "tracing"))]
+        let guard = $crate::trace::NoSpan;
+        guard
+    }};
+}
+
+/// Warn about something with the arguments of `format!`, as a `tracing` event at the `WARN` level if a subscriber is
+/// listening for it, or as a `warning:` line on standard error if not.
+macro_rules! warn {
+    ($($args:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        if tracing::enabled!(tracing::Level::WARN) {
+            tracing::warn!($($args)+);
+        } else {
+            eprintln!("warning: {}", format_args!($($args)+));
+        }
+        #[cfg(not(feature = "tracing"))]
+        eprintln!("warning: {}", format_args!($($args)+));
+    }};
+}
+
+/// The guard `enter_span!` returns without the `tracing` feature.
+#[cfg(not(feature = "tracing"))]
+pub struct NoSpan;
+
+/// Print spans and events at `level` and above to standard error, for `--log-level`.
+#[cfg(feature = "tracing")]
+pub fn init(level: tracing::Level) {
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_writer(std::io::stderr)
+        .init();
+}
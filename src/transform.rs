@@ -0,0 +1,216 @@
+//! Text Transforms
+//! ===============
+//!
+//! A `Transform` turns a piece of text into another, like SpOnGiFying it or shuffling its words. Transforms compose
+//! with a `Pipeline`, which applies each of its transforms in order to the output of the one before, so features which
+//! change the text can be put together without knowing about each other.
+
+use crate::shuffle_words;
+use rand::rngs::StdRng;
+use spongify::tokenize::Tokenizer;
+use std::{fmt, str};
+
+/// Something which changes text. Transforms may keep state from one call to the next, like how far into a
+/// capitalization pattern they are.
+pub trait Transform {
+    fn apply(&mut self, input: &str) -> String;
+}
+
+/// Any function from text to text is a transform.
+impl<F: FnMut(&str) -> String> Transform for F {
+    fn apply(&mut self, input: &str) -> String {
+        self(input)
+    }
+}
+
+/// Transforms applied one after the other, in the order they were added.
+#[derive(Default)]
+pub struct Pipeline<'a> {
+    transforms: Vec<Box<dyn Transform + 'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `transform` to the end of the pipeline.
+    pub fn then(mut self, transform: impl Transform + 'a) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+
+    /// Check if the pipeline has no transforms, so it would leave text as it is.
+    pub fn is_empty(&self) -> bool {
+        self.transforms.is_empty()
+    }
+}
+
+impl Transform for Pipeline<'_> {
+    fn apply(&mut self, input: &str) -> String {
+        self.transforms
+            .iter_mut()
+            .fold(input.to_owned(), |text, transform| transform.apply(&text))
+    }
+}
+
+/// Change every letter to one case, with the full Unicode case mappings.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CaseMapping {
+    Upper,
+    Lower,
+}
+
+impl Transform for CaseMapping {
+    fn apply(&mut self, input: &str) -> String {
+        match self {
+            Self::Upper => input.to_uppercase(),
+            Self::Lower => input.to_lowercase(),
+        }
+    }
+}
+
+/// Shuffle the words of each line for `--shuffle-words`.
+pub struct ShuffleWords {
+    pub tokenizer: Tokenizer,
+    pub rng: StdRng,
+}
+
+impl Transform for ShuffleWords {
+    fn apply(&mut self, input: &str) -> String {
+        shuffle_words(input, self.tokenizer, &mut self.rng)
+    }
+}
+
+/// A transform which can be named in `--transform-order`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TransformStep {
+    ShuffleWords,
+    Spongify,
+}
+
+impl fmt::Display for TransformStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::ShuffleWords => write!(f, "shuffle-words"),
+            Self::Spongify => write!(f, "spongify"),
+        }
+    }
+}
+
+impl str::FromStr for TransformStep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "shuffle-words" => Ok(Self::ShuffleWords),
+            "spongify" => Ok(Self::Spongify),
+            _ => Err(format!(
+                "unknown transform \"{s}\" (expected shuffle-words or spongify)"
+            )),
+        }
+    }
+}
+
+/// The order the transforms are applied to each line in, for `--transform-order`. SpOnGiFying is in the order exactly
+/// once: the transforms before it change the line it is given, and the ones after it change what it gives back.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TransformOrder(Vec<TransformStep>);
+
+impl TransformOrder {
+    /// Create an order of `steps`, which must name `TransformStep::Spongify` once and every other step at most once.
+    pub fn new(steps: Vec<TransformStep>) -> Result<Self, String> {
+        for (index, step) in steps.iter().enumerate() {
+            if steps[..index].contains(step) {
+                return Err(format!("the transform order names {step} more than once"));
+            }
+        }
+        if !steps.contains(&TransformStep::Spongify) {
+            return Err("the transform order must name spongify".to_owned());
+        }
+        Ok(Self(steps))
+    }
+
+    /// Check if the order names `step`.
+    pub fn contains(&self, step: TransformStep) -> bool {
+        self.0.contains(&step)
+    }
+
+    /// Get the steps which come before SpOnGiFying and the ones which come after it.
+    pub fn split(&self) -> (&[TransformStep], &[TransformStep]) {
+        let at = self
+            .0
+            .iter()
+            .position(|step| *step == TransformStep::Spongify)
+            .expect("the order names spongify");
+        (&self.0[..at], &self.0[at + 1..])
+    }
+}
+
+impl str::FromStr for TransformOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let steps = s
+            .split(',')
+            .map(|step| step.trim().parse())
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(steps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn pipeline_applies_in_order() {
+        let mut pipeline = Pipeline::new()
+            .then(|text: &str| text.replace("taco", "burrito"))
+            .then(CaseMapping::Upper)
+            .then(|text: &str| format!("{text}!"));
+        assert_eq!(pipeline.apply("taco truck"), "BURRITO TRUCK!");
+
+        let mut pipeline = Pipeline::new()
+            .then(CaseMapping::Upper)
+            .then(|text: &str| text.replace("taco", "burrito"));
+        assert_eq!(pipeline.apply("taco truck"), "TACO TRUCK");
+    }
+
+    #[test]
+    fn empty_pipeline() {
+        let mut pipeline = Pipeline::new();
+        assert!(pipeline.is_empty());
+        assert_eq!(pipeline.apply("taco truck"), "taco truck");
+    }
+
+    #[test]
+    fn transform_order_from_str() {
+        use TransformStep::*;
+
+        let order: TransformOrder = "shuffle-words, spongify".parse().unwrap();
+        assert_eq!(order.split(), (&[ShuffleWords][..], &[][..]));
+        let order: TransformOrder = "spongify,shuffle-words".parse().unwrap();
+        assert_eq!(order.split(), (&[][..], &[ShuffleWords][..]));
+
+        assert!("shuffle-words".parse::<TransformOrder>().is_err());
+        assert!("spongify,spongify".parse::<TransformOrder>().is_err());
+        assert!("normalize,spongify".parse::<TransformOrder>().is_err());
+    }
+
+    #[test]
+    fn shuffle_words_keeps_words() {
+        let mut shuffle = ShuffleWords {
+            tokenizer: Tokenizer::Whitespace,
+            rng: StdRng::seed_from_u64(0),
+        };
+        let mut words = shuffle
+            .apply("the quick brown fox")
+            .split(' ')
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        words.sort();
+        assert_eq!(words, ["brown", "fox", "quick", "the"]);
+    }
+}
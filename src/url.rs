@@ -0,0 +1,278 @@
+//! URL Input
+//! =========
+//!
+//! Fetches input from a URL for `--url`. Servers may compress the response body, so the body is decompressed according
+//! to its `Content-Encoding` (gzip, deflate, and brotli are supported). Some servers send gzip data without saying so,
+//! so a body which starts with the gzip magic bytes is decompressed even without the header.
+//!
+//! Redirects are followed (up to ureq's limit of 5). Anything but a 200 OK response at the end of them is an error,
+//! even another success status like 204 No Content, since there is no text to SpOnGiFy in it.
+//!
+//! Fetches which fail in a way that might not happen again (a connection error or a 5xx status) can be retried with
+//! `--retry`. Other statuses, like 404, are not retried, since asking again will not help.
+
+use crate::Result;
+use std::{
+    io::{self, BufRead},
+    time::Duration,
+};
+
+/// The magic bytes every gzip stream starts with.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+type BoxedRead = Box<dyn io::Read + Send + Sync>;
+
+/// How to retry fetches which fail in a way that might not happen again.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Retry {
+    /// How many times to try again after the first attempt fails.
+    pub retries: u32,
+    /// How long to wait before the first retry. The wait doubles after each retry.
+    pub delay: Duration,
+}
+
+/// Check if a fetch which failed with `error` could succeed if it were tried again.
+fn is_transient(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Status(status, _) => *status >= 500,
+        ureq::Error::Transport(_) => true,
+    }
+}
+
+/// Fetch `url` and get a reader for its decompressed body, retrying transient failures according to `retry`.
+pub fn open_url(url: &str, retry: Retry) -> Result<Box<dyn BufRead + Send + Sync>> {
+    let mut delay = retry.delay;
+    let mut attempt = 0;
+    let response = loop {
+        match ureq::get(url)
+            .set("Accept-Encoding", "gzip, deflate, br")
+            .call()
+        {
+            Ok(response) if response.status() == 200 => break response,
+            Ok(response) => {
+                return Err(format!(
+                    "could not fetch {url}: the server responded with status {} {}",
+                    response.status(),
+                    response.status_text()
+                )
+                .into())
+            }
+            Err(e) if attempt < retry.retries && is_transient(&e) => {
+                attempt += 1;
+                warn!(
+                    "could not fetch {url}: {e}, retrying in {}ms ({attempt} of {})",
+                    delay.as_millis(),
+                    retry.retries
+                );
+                std::thread::sleep(delay);
+                delay = delay.saturating_mul(2);
+            }
+            Err(e) => return Err(format!("could not fetch {url}: {e}").into()),
+        }
+    };
+
+    let encoding = response.header("Content-Encoding").map(str::to_owned);
+    decode_body(encoding.as_deref(), response.into_reader())
+        .map_err(|e| format!("could not decode the response from {url}: {e}").into())
+}
+
+/// Decompress `body` according to its `Content-Encoding` header, `encoding`.
+fn decode_body(encoding: Option<&str>, body: BoxedRead) -> Result<Box<dyn BufRead + Send + Sync>> {
+    let mut body: BoxedRead = body;
+
+    // Encodings are listed in the order they were applied, so they are undone in reverse
+    for coding in encoding.unwrap_or_default().rsplit(',').map(str::trim) {
+        body = match coding.to_ascii_lowercase().as_str() {
+            "" | "identity" => body,
+            "gzip" | "x-gzip" => Box::new(flate2::read::MultiGzDecoder::new(body)),
+            "deflate" => decode_deflate(body)?,
+            "br" => Box::new(brotli_decompressor::Decompressor::new(body, 4096)),
+            _ => return Err(format!("unsupported Content-Encoding \"{coding}\"").into()),
+        };
+    }
+
+    let mut body = io::BufReader::new(body);
+    if body.fill_buf()?.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(io::BufReader::new(
+            flate2::read::MultiGzDecoder::new(body),
+        )))
+    } else {
+        Ok(Box::new(body))
+    }
+}
+
+/// Decompress a `deflate` body. This is supposed to be zlib-wrapped, but some servers send raw deflate data, so the
+/// zlib header is checked for.
+fn decode_deflate(body: BoxedRead) -> Result<BoxedRead> {
+    let mut body = io::BufReader::new(body);
+    let is_zlib = match body.fill_buf()? {
+        [cmf, flg, ..] => cmf & 0x0f == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    };
+
+    if is_zlib {
+        Ok(Box::new(flate2::bufread::ZlibDecoder::new(body)))
+    } else {
+        Ok(Box::new(flate2::bufread::DeflateDecoder::new(body)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decode(encoding: Option<&str>, body: Vec<u8>) -> Result<String> {
+        let mut decoded = String::new();
+        decode_body(encoding, Box::new(io::Cursor::new(body)))?.read_to_string(&mut decoded)?;
+        Ok(decoded)
+    }
+
+    /// Serve a response with each of the `statuses` in turn, one per connection, all with the given `Content-Encoding`
+    /// and `body`. Redirects point back to the same URL, which this returns.
+    fn serve(statuses: &'static [u16], encoding: &'static str, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+
+        std::thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = io::BufReader::new(stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                    line.clear();
+                }
+
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {status} Taco\r\nContent-Encoding: {encoding}\r\nContent-Length: {}\r\nLocation: /\r\nConnection: close\r\n\r\n",
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+        });
+
+        url
+    }
+
+    /// Serve a single successful response with the given `Content-Encoding` and `body`, returning the URL to fetch it
+    /// from.
+    fn serve_once(encoding: &'static str, body: Vec<u8>) -> String {
+        serve(&[200], encoding, body)
+    }
+
+    #[test]
+    fn open_url_gzip() {
+        let url = serve_once("gzip", gzip(b"taco truck\n"));
+
+        let mut body = String::new();
+        open_url(&url, Retry::default())
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "taco truck\n");
+    }
+
+    #[test]
+    fn open_url_retries_server_errors() {
+        let retry = Retry {
+            retries: 2,
+            delay: Duration::from_millis(1),
+        };
+        let url = serve(&[503, 503, 200], "identity", b"taco truck\n".to_vec());
+
+        let mut body = String::new();
+        open_url(&url, retry)
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "taco truck\n");
+
+        // Running out of retries gives the last error
+        let url = serve(&[503, 503], "identity", Vec::new());
+        let retry = Retry {
+            retries: 1,
+            ..retry
+        };
+        let err = open_url(&url, retry).err().unwrap();
+        assert!(err.to_string().contains("503"), "{err}");
+    }
+
+    #[test]
+    fn open_url_does_not_retry_client_errors() {
+        let retry = Retry {
+            retries: 2,
+            delay: Duration::from_millis(1),
+        };
+        let url = serve(&[404, 200], "identity", b"taco truck\n".to_vec());
+
+        let err = open_url(&url, retry).err().unwrap();
+        assert!(err.to_string().contains("404"), "{err}");
+    }
+
+    #[test]
+    fn open_url_follows_redirects() {
+        let url = serve(&[302, 301, 200], "identity", b"taco truck\n".to_vec());
+
+        let mut body = String::new();
+        open_url(&url, Retry::default())
+            .unwrap()
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, "taco truck\n");
+    }
+
+    #[test]
+    fn open_url_rejects_other_success_statuses() {
+        let url = serve(&[204], "identity", Vec::new());
+
+        let err = open_url(&url, Retry::default()).err().unwrap();
+        assert!(err.to_string().contains("status 204"), "{err}");
+    }
+
+    #[test]
+    fn decode_body_encodings() {
+        assert_eq!(decode(None, b"taco".to_vec()).unwrap(), "taco");
+        assert_eq!(decode(Some("identity"), b"taco".to_vec()).unwrap(), "taco");
+        assert_eq!(decode(Some("GZIP"), gzip(b"taco")).unwrap(), "taco");
+
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        zlib.write_all(b"taco").unwrap();
+        assert_eq!(
+            decode(Some("deflate"), zlib.finish().unwrap()).unwrap(),
+            "taco"
+        );
+
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(b"taco").unwrap();
+        assert_eq!(
+            decode(Some("deflate"), raw.finish().unwrap()).unwrap(),
+            "taco"
+        );
+    }
+
+    #[test]
+    fn decode_body_sniffs_gzip() {
+        assert_eq!(decode(None, gzip(b"taco")).unwrap(), "taco");
+    }
+
+    #[test]
+    fn decode_body_unknown_encoding() {
+        let err = decode(Some("compress"), b"taco".to_vec()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("unsupported Content-Encoding \"compress\""));
+    }
+}